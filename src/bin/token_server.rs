@@ -0,0 +1,281 @@
+// Stand-in for the production token service, so the client can run without LIVEKIT_API_SECRET.
+//
+// LIVEKIT_URL=wss://... LIVEKIT_API_KEY=... LIVEKIT_API_SECRET=... cargo run --bin token_server
+// LIVEKIT_TOKEN_ENDPOINT=http://127.0.0.1:7881 cargo run
+//
+// GET  /rooms                           [{"room_id", "participants", "metadata"}] for every active room
+// GET  /rooms?room_id=<id>               {"room_id", "participants", "metadata"}, 404 when the room does not exist
+// POST /rooms  {"room_id", "metadata"}   creates the room, 409 when it already exists so its metadata can't be replaced
+// POST /token  {"room_id", "identity", "password", "invite_code", "session_key"}
+//                                        {"url", "token"} valid for ROOM_TOKEN_TTL_SECONDS, 403 with the reason when a private room turns it down
+//                                        or someone else is already in the room under that identity
+//
+// listed metadata never carries the password hash, salt or invite key of a private room
+
+use std::{env, io::Read, time::Duration};
+
+use livekit_api::{access_token, services::room::{CreateRoomOptions, RoomClient}};
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+// the client's own metadata and access checks, so both agree on who gets into a private room
+#[path = "../networking/rtc/metadata.rs"]
+#[allow(dead_code)]
+mod metadata;
+#[path = "../networking/rtc/access.rs"]
+#[allow(dead_code)]
+mod access;
+
+use access::{check_room_access, hash_password, RoomAccess};
+use metadata::RoomMetadata;
+
+const ROOM_TOKEN_TTL_SECONDS: u64 = 10 * 60;
+const MAX_BODY_BYTES: u64 = 64 * 1024;
+// same cap as MAX_ROOM_PARTICIPANTS in the client
+const MAX_ROOM_PARTICIPANTS: u32 = 50;
+
+#[derive(Deserialize)]
+struct CreateRoomRequest {
+    room_id: String,
+    metadata: String
+}
+
+#[derive(Deserialize)]
+struct RoomTokenRequest {
+    room_id: String,
+    identity: String,
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    invite_code: String,
+    #[serde(default)]
+    session_key: String
+}
+
+#[derive(Serialize)]
+struct RoomListing {
+    room_id: String,
+    participants: u32,
+    metadata: String
+}
+
+#[derive(Serialize)]
+struct RoomToken {
+    url: String,
+    token: String
+}
+
+struct TokenServer {
+    url: String,
+    api_key: String,
+    api_secret: String,
+    room_service: RoomClient,
+    runtime: tokio::runtime::Runtime
+}
+
+impl TokenServer {
+    fn handle(&self, request: &mut Request) -> Response<std::io::Cursor<Vec<u8>>> {
+        let url = request.url().to_string();
+        let (path, query) = match url.split_once('?') {
+            Some((path, query)) => (path.to_string(), query.to_string()),
+            None => (url.to_string(), String::new())
+        };
+
+        match (request.method(), path.as_str()) {
+            (Method::Get, "/rooms") => {
+                let room_id = query.split('&').find_map(|pair| pair.strip_prefix("room_id=")).map(|value| decode_query_value(value)).unwrap_or_default();
+                if room_id.is_empty() {
+                    match self.runtime.block_on(self.room_service.list_rooms(vec![])) {
+                        Ok(rooms) => {
+                            let room_listings: Vec<RoomListing> = rooms.into_iter().map(|room| RoomListing {
+                                room_id: room.name,
+                                participants: room.num_participants,
+                                metadata: get_listed_metadata(&room.metadata)
+                            }).collect();
+                            let json = serde_json::to_string(&room_listings).unwrap_or_default();
+                            let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+                            return Response::from_string(json).with_header(header);
+                        },
+                        Err(e) => return Response::from_string(format!("{:?}", e)).with_status_code(502)
+                    }
+                }
+                match self.runtime.block_on(self.room_service.list_rooms(vec![room_id])) {
+                    Ok(rooms) => {
+                        match rooms.into_iter().next() {
+                            Some(room) => {
+                                let room_listing = RoomListing {
+                                    room_id: room.name,
+                                    participants: room.num_participants,
+                                    metadata: get_listed_metadata(&room.metadata)
+                                };
+                                let json = serde_json::to_string(&room_listing).unwrap_or_default();
+                                let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+                                return Response::from_string(json).with_header(header);
+                            },
+                            None => return Response::from_string("room not found").with_status_code(404)
+                        }
+                    },
+                    Err(e) => return Response::from_string(format!("{:?}", e)).with_status_code(502)
+                }
+            },
+            (Method::Post, "/rooms") => {
+                let body = match read_json::<CreateRoomRequest>(request) {
+                    Ok(body) => body,
+                    Err(e) => return Response::from_string(e).with_status_code(400)
+                };
+                // metadata the access checks can't read would leave the room open to anyone
+                if body.room_id.is_empty() || serde_json::from_str::<RoomMetadata>(&body.metadata).is_err() {
+                    return Response::from_string("room_id and metadata are required").with_status_code(400);
+                }
+                // LiveKit hands back an existing room as if it was created, so whoever asks second gets nothing
+                match self.runtime.block_on(self.room_service.list_rooms(vec![body.room_id.to_string()])) {
+                    Ok(rooms) => {
+                        if !rooms.is_empty() {
+                            return Response::from_string("room already exists").with_status_code(409);
+                        }
+                    },
+                    Err(e) => return Response::from_string(format!("{:?}", e)).with_status_code(502)
+                }
+                match self.runtime.block_on(self.room_service.create_room(&body.room_id, CreateRoomOptions {
+                    empty_timeout: 30,
+                    max_participants: MAX_ROOM_PARTICIPANTS,
+                    metadata: body.metadata,
+                    ..Default::default()
+                })) {
+                    Ok(_) => return Response::from_string("{}").with_status_code(200),
+                    Err(e) => return Response::from_string(format!("{:?}", e)).with_status_code(502)
+                }
+            },
+            (Method::Post, "/token") => {
+                let body = match read_json::<RoomTokenRequest>(request) {
+                    Ok(body) => body,
+                    Err(e) => return Response::from_string(e).with_status_code(400)
+                };
+                if body.identity.is_empty() || body.session_key.is_empty() {
+                    return Response::from_string("identity and session_key are required").with_status_code(400);
+                }
+                // kept in the participant's metadata, so only the client that joined first can take the identity back
+                let session_key_hash = hash_password(&body.identity, &body.session_key);
+                // the listings no longer carry what a client would need to check a private room, so it is checked here
+                match self.runtime.block_on(self.room_service.list_rooms(vec![body.room_id.to_string()])) {
+                    Ok(rooms) => {
+                        if let Some(room) = rooms.into_iter().next() {
+                            if let Ok(room_metadata) = serde_json::from_str::<RoomMetadata>(&room.metadata) {
+                                let access = check_room_access(&room_metadata, &body.room_id, &body.password, &body.invite_code);
+                                if access.ne(&RoomAccess::Granted) {
+                                    return Response::from_string(access.get_code()).with_status_code(403);
+                                }
+                            }
+                            // a dropped connection stays in the room for a while, its own client may replace it
+                            match self.runtime.block_on(self.room_service.list_participants(&room.name)) {
+                                Ok(participants) => {
+                                    if participants.iter().any(|participant| participant.identity.eq(&body.identity) && participant.metadata.ne(&session_key_hash)) {
+                                        return Response::from_string(RoomAccess::IdentityInUse.get_code()).with_status_code(403);
+                                    }
+                                },
+                                Err(e) => return Response::from_string(format!("{:?}", e)).with_status_code(502)
+                            }
+                        }
+                    },
+                    Err(e) => return Response::from_string(format!("{:?}", e)).with_status_code(502)
+                }
+                let token = access_token::AccessToken::with_api_key(&self.api_key, &self.api_secret)
+                .with_identity(&body.identity)
+                .with_name(&body.identity)
+                .with_metadata(&session_key_hash)
+                .with_ttl(Duration::from_secs(ROOM_TOKEN_TTL_SECONDS))
+                .with_grants(access_token::VideoGrants {
+                    room_join: true,
+                    room: body.room_id,
+                    can_update_own_metadata: true,
+                    ..Default::default()
+                })
+                .to_jwt();
+                match token {
+                    Ok(token) => {
+                        let room_token = RoomToken {
+                            url: self.url.to_string(),
+                            token
+                        };
+                        let json = serde_json::to_string(&room_token).unwrap_or_default();
+                        let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+                        return Response::from_string(json).with_header(header);
+                    },
+                    Err(e) => return Response::from_string(format!("{:?}", e)).with_status_code(500)
+                }
+            },
+            _ => return Response::from_string("not found").with_status_code(404)
+        }
+    }
+}
+
+// rooms whose metadata is not ours are listed as they are
+fn get_listed_metadata(metadata: &str) -> String {
+    match serde_json::from_str::<RoomMetadata>(metadata) {
+        Ok(mut room_metadata) => {
+            room_metadata.strip_secrets();
+            return serde_json::to_string(&room_metadata).unwrap_or_default();
+        },
+        Err(_) => return metadata.to_string()
+    }
+}
+
+fn decode_query_value(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded: Vec<u8> = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match std::str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    },
+                    None => decoded.push(b'%')
+                }
+            },
+            byte => decoded.push(byte)
+        }
+        i += 1;
+    }
+    return String::from_utf8_lossy(&decoded).to_string();
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(request: &mut Request) -> Result<T, String> {
+    let mut body = String::new();
+    if let Err(e) = request.as_reader().take(MAX_BODY_BYTES).read_to_string(&mut body) {
+        return Err(e.to_string());
+    }
+    return serde_json::from_str::<T>(&body).map_err(|e| e.to_string());
+}
+
+fn main() {
+    let url = env::var("LIVEKIT_URL").expect("LIVEKIT_URL is not set");
+    let api_key = env::var("LIVEKIT_API_KEY").expect("LIVEKIT_API_KEY is not set");
+    let api_secret = env::var("LIVEKIT_API_SECRET").expect("LIVEKIT_API_SECRET is not set");
+    let address = env::var("SHALLOVILLE_TOKEN_SERVER_ADDR").unwrap_or("127.0.0.1:7881".to_string());
+
+    let mut https_url = url.to_string();
+    if https_url.starts_with("wss") {
+        https_url = https_url.replace("wss", "https");
+    }
+
+    let token_server = TokenServer {
+        room_service: RoomClient::with_api_key(&https_url, &api_key, &api_secret),
+        url,
+        api_key,
+        api_secret,
+        runtime: tokio::runtime::Runtime::new().unwrap()
+    };
+
+    let server = Server::http(&address).expect("failed to bind the token server");
+    println!("token server listening on http://{}", address);
+
+    for mut request in server.incoming_requests() {
+        let response = token_server.handle(&mut request);
+        println!("{} {} -> {}", request.method(), request.url(), response.status_code().0);
+        let _ = request.respond(response);
+    }
+}
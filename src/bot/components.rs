@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::networking::rtc::resources::RTCResource;
+
+use super::BotScript;
+
+#[derive(Component)]
+pub struct Bot {
+    pub name: String,
+    pub uuid: String,
+    // every bot has its own connection, so a dozen of them can share one process
+    pub rtc: RTCResource,
+    pub script: BotScript,
+
+    pub pos: Vec2,
+    pub scale_x: f32,
+    pub animation: String,
+    pub target: Option<Vec2>,
+    pub waypoint_index: usize,
+    pub rest_timer: f32,
+    pub chat_timer: f32,
+
+    // identity -> display name
+    pub participants: HashMap<String, String>,
+    pub received_updates: u32,
+    pub seed: u64
+}
+
+impl Bot {
+    // xorshift, good enough to scatter bots without another dependency
+    pub fn next_random(&mut self) -> u64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        return self.seed;
+    }
+
+    pub fn random_range(&mut self, max: i32) -> i32 {
+        if max <= 0 {
+            return 0;
+        }
+        return (self.next_random() % max as u64) as i32;
+    }
+
+    pub fn get_participant_name(&self, identity: &str) -> String {
+        match self.participants.get(identity) {
+            Some(name) if !name.is_empty() => return name.to_string(),
+            _ => return identity.to_string()
+        }
+    }
+}
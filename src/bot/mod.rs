@@ -0,0 +1,21 @@
+use bevy::prelude::*;
+
+mod components;
+
+mod systems;
+use systems::*;
+
+pub mod resources;
+use resources::*;
+
+// headless participants for load and sync testing, run with --bots <count> --room <id>
+pub struct BotPlugin;
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<BotSettings>()
+        .add_systems(Startup, spawn_bots)
+        .add_systems(Update, (receive_bot_events, reconnect_bots, move_bots, send_bot_chat, log_bot_stats).chain());
+    }
+}
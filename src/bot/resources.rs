@@ -0,0 +1,124 @@
+use bevy::prelude::*;
+
+use crate::{config::resources::LaunchOptions, wardrobe::resources::BodyParts};
+
+#[derive(Clone, Debug)]
+pub enum BotScript {
+    Idle,
+    // wanders to random tiles inside a cols x rows area, resting in between
+    RandomWalk { cols: i32, rows: i32 },
+    // walks the tiles in order and starts over
+    Waypoints(Vec<(i32, i32)>)
+}
+
+impl BotScript {
+    // "idle", "random", "random:12,8" or "waypoints:1,1;6,1;6,4"
+    pub fn parse(script: &str) -> Result<BotScript, String> {
+        let (name, args) = match script.split_once(':') {
+            Some((name, args)) => (name, args),
+            None => (script, "")
+        };
+
+        match name {
+            "idle" => return Ok(BotScript::Idle),
+            "random" => {
+                if args.is_empty() {
+                    return Ok(BotScript::RandomWalk { cols: 10, rows: 8 });
+                }
+                let (cols, rows) = parse_tile(args)?;
+                return Ok(BotScript::RandomWalk { cols: cols.max(1), rows: rows.max(1) });
+            },
+            "waypoints" => {
+                let mut waypoints: Vec<(i32, i32)> = Vec::new();
+                for waypoint in args.split(';').filter(|waypoint| !waypoint.is_empty()) {
+                    waypoints.push(parse_tile(waypoint)?);
+                }
+                if waypoints.is_empty() {
+                    return Err("waypoints needs at least one x,y".to_string());
+                }
+                return Ok(BotScript::Waypoints(waypoints));
+            },
+            _ => return Err(format!("unknown script {}", name))
+        }
+    }
+}
+
+fn parse_tile(tile: &str) -> Result<(i32, i32), String> {
+    if let Some((x, y)) = tile.split_once(',') {
+        if let (Ok(x), Ok(y)) = (x.trim().parse::<i32>(), y.trim().parse::<i32>()) {
+            return Ok((x, y));
+        }
+    }
+    return Err(format!("{} is not x,y", tile));
+}
+
+#[derive(Resource)]
+pub struct BotSettings {
+    pub count: usize,
+    pub room_id: String,
+    pub name_prefix: String,
+    // used when the first bot has to create the room
+    pub map: String,
+    pub script: BotScript,
+    // None gives every bot a random avatar
+    pub avatar: Option<BodyParts>,
+    // 0 keeps the bots quiet
+    pub chat_interval_seconds: f32
+}
+
+impl Default for BotSettings {
+    fn default() -> BotSettings {
+        BotSettings {
+            count: 0,
+            room_id: String::new(),
+            name_prefix: "bot".to_string(),
+            map: "0".to_string(),
+            script: BotScript::RandomWalk { cols: 10, rows: 8 },
+            avatar: None,
+            chat_interval_seconds: 0.0
+        }
+    }
+}
+
+impl BotSettings {
+    pub fn from_launch_options(launch_options: &LaunchOptions) -> BotSettings {
+        let mut bot_settings = BotSettings::default();
+        bot_settings.count = launch_options.bots;
+        if let Some(room) = &launch_options.room {
+            bot_settings.room_id = room.to_string();
+        }
+        if let Some(name) = &launch_options.name {
+            bot_settings.name_prefix = name.to_string();
+        }
+        if let Some(map) = &launch_options.map {
+            bot_settings.map = map.to_string();
+        }
+        if let Some(script) = &launch_options.bot_script {
+            match BotScript::parse(script) {
+                Ok(script) => bot_settings.script = script,
+                Err(e) => println!("bad --script, using random walk: {}", e)
+            }
+        }
+        // head,hair,eyes,upper,hip,legs
+        if let Some(avatar) = &launch_options.bot_avatar {
+            let parts: Vec<&str> = avatar.split(',').map(|part| part.trim()).collect();
+            if parts.len() == 6 {
+                bot_settings.avatar = Some(BodyParts {
+                    head: parts[0].to_string(),
+                    hair: parts[1].to_string(),
+                    eyes: parts[2].to_string(),
+                    upper_dress: parts[3].to_string(),
+                    hip: parts[4].to_string(),
+                    legs: parts[5].to_string(),
+                    ..default()
+                });
+            } else {
+                println!("bad --avatar, expected head,hair,eyes,upper,hip,legs");
+            }
+        }
+        if let Some(chat_interval) = launch_options.bot_chat_interval {
+            bot_settings.chat_interval_seconds = chat_interval.max(0.0);
+        }
+        return bot_settings;
+    }
+}
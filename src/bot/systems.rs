@@ -0,0 +1,322 @@
+use std::{collections::HashMap, sync::Arc};
+use uuid::Uuid;
+
+use bevy::prelude::*;
+
+use crate::{config::resources::AppConfig, networking::rtc::{components::MultiplayerUserAttribute, protocol::{ChatKind, RoomMessage, RoomPacket}, resources::{RTCResource, ReconnectPoll, RoomMetadata}, transport::{get_room_transport, TransportEvent}}, wardrobe::resources::{BodyParts, WardrobeResources}};
+
+use super::{components::Bot, BotScript, BotSettings};
+
+const BOT_STATS_INTERVAL_SECONDS: f32 = 10.0;
+const BOT_CHAT_LINES: [&str; 5] = ["hello!", "anyone here?", "nice map", "brb", "testing 1 2 3"];
+
+pub fn spawn_bots(mut commands: Commands, bot_settings: Res<BotSettings>, config: Res<AppConfig>){
+    if bot_settings.room_id.is_empty() {
+        println!("bots need a room, pass --room <id>");
+        return;
+    }
+
+    let transport = get_room_transport(&config);
+    let wardrobe_parts = WardrobeResources::default().wardrobe_parts;
+    // the first bot to arrive creates the room when it does not exist yet
+    let metadata = serde_json::to_string(&RoomMetadata {
+        map: bot_settings.map.to_string(),
+        name: String::new(),
+        // nobody moderates a room the bots made
+        host_key: String::new(),
+        password_hash: String::new(),
+        password_salt: String::new(),
+        invite_key: String::new(),
+        has_password: false,
+        has_invite_key: false
+    }).unwrap_or_default();
+
+    for index in 0..bot_settings.count {
+        let uuid = Uuid::new_v4();
+        let mut bot = Bot {
+            name: format!("{}-{}", bot_settings.name_prefix, index + 1),
+            uuid: uuid.to_string(),
+            rtc: RTCResource::default(),
+            script: bot_settings.script.clone(),
+
+            pos: Vec2::ZERO,
+            scale_x: 1.0,
+            animation: "idle".to_string(),
+            target: None,
+            waypoint_index: 0,
+            rest_timer: 0.0,
+            chat_timer: 0.0,
+
+            participants: HashMap::new(),
+            received_updates: 0,
+            seed: uuid.as_u128() as u64 | 1
+        };
+
+        let body_parts = match &bot_settings.avatar {
+            Some(avatar) => avatar.clone(),
+            None => BodyParts {
+                head: bot.random_range(wardrobe_parts.head_total as i32).to_string(),
+                hair: bot.random_range(wardrobe_parts.hair_total as i32).to_string(),
+                eyes: bot.random_range(wardrobe_parts.eyes_total as i32).to_string(),
+                upper_dress: bot.random_range(wardrobe_parts.upper_dress_total as i32).to_string(),
+                hip: bot.random_range(wardrobe_parts.hip_total as i32).to_string(),
+                legs: bot.random_range(wardrobe_parts.legs_total as i32).to_string(),
+                ..default()
+            }
+        };
+
+        let start_tile = match bot.script.clone() {
+            BotScript::Waypoints(waypoints) => waypoints[0],
+            BotScript::RandomWalk { cols, rows } => (bot.random_range(cols), bot.random_range(rows)),
+            BotScript::Idle => (bot.random_range(10), bot.random_range(8))
+        };
+        bot.pos = get_tile_pos(&config, start_tile);
+        if bot_settings.chat_interval_seconds > 0.0 {
+            // spread the first messages out so the bots don't all talk at once
+            bot.chat_timer = bot_settings.chat_interval_seconds * bot.random_range(100) as f32 / 100.0;
+        }
+
+        bot.rtc.set_transport(Arc::clone(&transport));
+        bot.rtc.new_room_in_background(bot_settings.room_id.to_string(), bot.uuid.to_string(), Some(metadata.to_string()), "", "", MultiplayerUserAttribute {
+            username: bot.name.to_string(),
+            body_part: body_parts,
+            roles: HashMap::new()
+        });
+        println!("{} joining room {}", bot.name, bot_settings.room_id);
+        commands.spawn(bot);
+    }
+}
+
+fn get_tile_pos(config: &AppConfig, tile: (i32, i32)) -> Vec2 {
+    return Vec2::new(tile.0 as f32 * config.tile_width, tile.1 as f32 * config.tile_height);
+}
+
+pub fn receive_bot_events(mut bots_query: Query<&mut Bot>){
+    for mut bot in bots_query.iter_mut() {
+        let bot = bot.as_mut();
+
+        let mut events: Vec<TransportEvent> = Vec::new();
+        if let Some(room_event) = &bot.rtc.room_event {
+            events = room_event.try_iter().collect();
+            // the transport thread ended without reporting it
+            if events.is_empty() && room_event.is_disconnected() {
+                events.push(TransportEvent::Disconnected);
+            }
+        }
+
+        for event in events {
+            // same as on_room_event_received, whatever was queued after a disconnect is stale
+            let is_disconnected = matches!(event, TransportEvent::Disconnected);
+            on_bot_event(bot, event);
+            if is_disconnected {
+                break;
+            }
+        }
+    }
+}
+
+fn on_bot_event(bot: &mut Bot, event: TransportEvent){
+    match event {
+        TransportEvent::Connected { participants } => {
+            bot.participants.clear();
+            for participant in participants.iter() {
+                let name = participant.attributes.get("name").cloned().unwrap_or_default();
+                bot.participants.insert(participant.identity.to_string(), name);
+            }
+            println!("{}: connected, {} others in the room", bot.name, bot.participants.len());
+        },
+        TransportEvent::ParticipantConnected(participant) => {
+            let name = participant.attributes.get("name").cloned().unwrap_or_default();
+            bot.participants.insert(participant.identity.to_string(), name);
+            println!("{}: {} joined", bot.name, bot.get_participant_name(&participant.identity));
+
+            // same as a real client, the newcomer should not see this bot at the spawn point
+            let snapshot = RoomMessage::Snapshot {
+                x: bot.pos.x,
+                y: bot.pos.y,
+                scale_x: bot.scale_x,
+                animation: bot.animation.to_string()
+            };
+            bot.rtc.send_message_to(&bot.uuid, snapshot, Vec::from([participant.identity.to_string()]));
+        },
+        TransportEvent::ParticipantDisconnected(identity) => {
+            println!("{}: {} left", bot.name, bot.get_participant_name(&identity));
+            bot.participants.remove(&identity);
+        },
+        TransportEvent::AttributesChanged { identity, changed } => {
+            if let Some(name) = changed.get("name") {
+                bot.participants.insert(identity.to_string(), name.to_string());
+            }
+            println!("{}: {} changed {:?}", bot.name, bot.get_participant_name(&identity), changed.keys().collect::<Vec<&String>>());
+        },
+        TransportEvent::DataReceived { payload, .. } => {
+            match RoomPacket::decode(&payload) {
+                Ok(packet) => {
+                    match packet.message {
+                        RoomMessage::Move { .. } | RoomMessage::Animation { .. } => {
+                            bot.received_updates += 1;
+                        },
+                        RoomMessage::Chat { kind, text, .. } => {
+                            println!("{}: {:?} from {}: {}", bot.name, kind, bot.get_participant_name(&packet.sender), text);
+                        },
+                        RoomMessage::Snapshot { x, y, .. } => {
+                            println!("{}: snapshot from {} at ({}, {})", bot.name, bot.get_participant_name(&packet.sender), x, y);
+                        },
+                        // bots don't track roles, they only report what they were asked to do
+                        RoomMessage::Moderate { action, target } => {
+                            println!("{}: {} sent {:?} for {}", bot.name, bot.get_participant_name(&packet.sender), action, bot.get_participant_name(&target));
+                        }
+                    }
+                },
+                Err(e) => {
+                    println!("{}: dropped room message: {}", bot.name, e);
+                }
+            }
+        },
+        TransportEvent::VideoTrackPublished { identity, track_name } => {
+            println!("{}: {} published video {}", bot.name, bot.get_participant_name(&identity), track_name);
+        },
+        TransportEvent::VideoTrackUnpublished { identity, track_name } => {
+            println!("{}: {} unpublished video {}", bot.name, bot.get_participant_name(&identity), track_name);
+        },
+        TransportEvent::Reconnecting => {
+            println!("{}: connection unstable", bot.name);
+            bot.rtc.reconnect.is_resuming = true;
+        },
+        TransportEvent::Reconnected => {
+            println!("{}: connection resumed", bot.name);
+            bot.rtc.reconnect.is_resuming = false;
+        },
+        TransportEvent::Disconnected => {
+            println!("{}: disconnected", bot.name);
+            bot.rtc.begin_reconnect();
+        }
+    }
+}
+
+pub fn reconnect_bots(mut bots_query: Query<&mut Bot>){
+    for mut bot in bots_query.iter_mut() {
+        match bot.rtc.poll_reconnect() {
+            ReconnectPoll::Reconnected => println!("{}: reconnected", bot.name),
+            ReconnectPoll::GaveUp => println!("{}: gave up reconnecting", bot.name),
+            _ => {}
+        }
+    }
+}
+
+// walks at the same speed and sends the same move/anime traffic as move_character_on_input
+pub fn move_bots(mut bots_query: Query<&mut Bot>, config: Res<AppConfig>, time: Res<Time>){
+    let speed = config.tile_width * 2.0;
+    let delta = time.delta_seconds();
+    let time_ms = (time.elapsed_seconds_f64() * 1000.0) as u32;
+
+    for mut bot in bots_query.iter_mut() {
+        let bot = bot.as_mut();
+        if !bot.rtc.is_multiplayer() {
+            continue;
+        }
+        let room_len = bot.participants.len() + 1;
+
+        if bot.target.is_none() {
+            if bot.rest_timer > 0.0 {
+                bot.rest_timer -= delta;
+            } else {
+                bot.target = get_next_target(bot, &config);
+            }
+        }
+
+        if let Some(target) = bot.target {
+            let offset = target - bot.pos;
+            let step = speed * delta;
+            if offset.x != 0.0 {
+                bot.scale_x = offset.x.signum();
+            }
+            if offset.length() <= step {
+                bot.pos = target;
+                bot.target = None;
+                if let BotScript::RandomWalk { .. } = bot.script {
+                    bot.rest_timer = 1.0 + bot.random_range(20) as f32 / 10.0;
+                } else {
+                    bot.target = get_next_target(bot, &config);
+                }
+            } else {
+                bot.pos += offset.normalize() * step;
+            }
+
+            bot.rtc.send_message(room_len, &bot.uuid, RoomMessage::Move {
+                x: bot.pos.x,
+                y: bot.pos.y,
+                time_ms
+            });
+        }
+
+        let mut animation = "idle";
+        if bot.target.is_some() {
+            animation = "walk";
+        }
+        if bot.animation.ne(animation) {
+            bot.animation = animation.to_string();
+            bot.rtc.send_message(room_len, &bot.uuid, RoomMessage::Animation {
+                name: animation.to_string(),
+                x: bot.pos.x,
+                y: bot.pos.y,
+                time_ms
+            });
+        }
+    }
+}
+
+fn get_next_target(bot: &mut Bot, config: &AppConfig) -> Option<Vec2> {
+    match bot.script.clone() {
+        BotScript::Idle => return None,
+        BotScript::RandomWalk { cols, rows } => {
+            let tile = (bot.random_range(cols), bot.random_range(rows));
+            return Some(get_tile_pos(config, tile));
+        },
+        BotScript::Waypoints(waypoints) => {
+            if waypoints.len() < 2 {
+                return None;
+            }
+            bot.waypoint_index = (bot.waypoint_index + 1) % waypoints.len();
+            return Some(get_tile_pos(config, waypoints[bot.waypoint_index]));
+        }
+    }
+}
+
+pub fn send_bot_chat(mut bots_query: Query<&mut Bot>, bot_settings: Res<BotSettings>, time: Res<Time>){
+    if bot_settings.chat_interval_seconds <= 0.0 {
+        return;
+    }
+
+    for mut bot in bots_query.iter_mut() {
+        let bot = bot.as_mut();
+        if !bot.rtc.is_multiplayer() {
+            continue;
+        }
+        bot.chat_timer -= time.delta_seconds();
+        if bot.chat_timer > 0.0 {
+            continue;
+        }
+        bot.chat_timer = bot_settings.chat_interval_seconds;
+
+        let line = BOT_CHAT_LINES[bot.random_range(BOT_CHAT_LINES.len() as i32) as usize];
+        bot.rtc.send_message(bot.participants.len() + 1, &bot.uuid, RoomMessage::Chat {
+            kind: ChatKind::Message,
+            text: line.to_string(),
+            range_tiles: 0
+        });
+    }
+}
+
+pub fn log_bot_stats(mut bots_query: Query<&mut Bot>, time: Res<Time>, mut elapsed: Local<f32>){
+    *elapsed += time.delta_seconds();
+    if *elapsed < BOT_STATS_INTERVAL_SECONDS {
+        return;
+    }
+    *elapsed = 0.0;
+
+    for mut bot in bots_query.iter_mut() {
+        println!("{}: {} others in the room, {} position updates in the last {}s", bot.name, bot.participants.len(), bot.received_updates, BOT_STATS_INTERVAL_SECONDS);
+        bot.received_updates = 0;
+    }
+}
@@ -1,15 +1,21 @@
+#[cfg(feature = "self-signed-tokens")]
 use std::env::set_var;
+#[cfg(feature = "self-signed-tokens")]
 use dotenvy_macro::dotenv;
 
 pub fn init() {
     #[cfg(target_os = "macos")]
     embed_plist::embed_info_plist!("Info.plist");
 
-    set_var("AWS_ACCESS_KEY_ID", dotenv!("AWS_ACCESS_KEY_ID"));
-    set_var("AWS_SECRET_ACCESS_KEY", dotenv!("AWS_SECRET_ACCESS_KEY"));
-    set_var("AWS_REGION", dotenv!("AWS_REGION"));
-
-    set_var("LIVEKIT_URL", dotenv!("LIVEKIT_URL"));
-    set_var("LIVEKIT_API_KEY", dotenv!("LIVEKIT_API_KEY"));
-    set_var("LIVEKIT_API_SECRET", dotenv!("LIVEKIT_API_SECRET"));
+    // release builds get join tokens from LIVEKIT_TOKEN_ENDPOINT and never contain the API secret,
+    // bucket credentials come from the environment at runtime or storage = "local" is used instead
+    #[cfg(feature = "self-signed-tokens")]
+    {
+        set_var("AWS_ACCESS_KEY_ID", dotenv!("AWS_ACCESS_KEY_ID"));
+        set_var("AWS_SECRET_ACCESS_KEY", dotenv!("AWS_SECRET_ACCESS_KEY"));
+        set_var("AWS_REGION", dotenv!("AWS_REGION"));
+        set_var("LIVEKIT_URL", dotenv!("LIVEKIT_URL"));
+        set_var("LIVEKIT_API_KEY", dotenv!("LIVEKIT_API_KEY"));
+        set_var("LIVEKIT_API_SECRET", dotenv!("LIVEKIT_API_SECRET"));
+    }
 }
@@ -21,7 +21,7 @@ impl Plugin for CharacterPlugin {
         .init_state::<CharacterExistState>()
         .add_systems(Update, update_character_animation.run_if(in_state(CharacterExistState::Exist)))
         .add_systems(Update, move_character_on_input.run_if(in_state(MovementState::Movable).and_then(in_state(FocusState::Game))))
-        .add_systems(Update, update_character_controller.run_if(in_state(CharacterExistState::Exist)))
+        .add_systems(Update, (interpolate_remote_characters, update_character_controller).chain().run_if(in_state(CharacterExistState::Exist)))
         .add_systems(Update, on_tilemap_loaded.run_if(in_state(CharacterExistState::Exist)));
     }
 }
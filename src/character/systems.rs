@@ -1,9 +1,8 @@
 use std::collections::HashMap;
 
 use bevy::prelude::*;
-use livekit::DataPacketKind;
 
-use crate::{editor::{resources::Scene, TilemapLoadedEvent}, game::{components::{Depth, TriggerButton}, MultiplayerRoomState, TriggerButtonState}, main_menu::resources::{RoomData, UserData, UserStatus}, map_structures::{components::InteractiveTrigger, resources::{MapData, MapStructures}}, networking::{rtc::resources::RTCResource, s3::{resources::S3Data, systems::load_sprite_from_s3}}, utils::{from_grid_xy_to_index, from_index_to_xy, from_xy_to_grid, lerp}, wardrobe::resources::BodyParts, AnimationTimer};
+use crate::{editor::{resources::Scene, TilemapLoadedEvent}, game::{components::{Depth, TriggerButton}, MultiplayerRoomState, TriggerButtonState}, main_menu::resources::{RoomData, UserData, UserStatus}, map_structures::{components::InteractiveTrigger, resources::{MapData, MapStructures}}, networking::{rtc::{protocol::RoomMessage, resources::RTCResource}, s3::{resources::S3Data, systems::load_sprite_from_s3}}, utils::{from_grid_xy_to_index, from_index_to_xy, from_xy_to_grid, lerp}, wardrobe::resources::BodyParts, AnimationTimer};
 
 use super::{components::Character, CharacterAnimation, TrackLoop, MAX_FRAME};
 
@@ -36,6 +35,9 @@ pub fn on_tilemap_loaded(mut tilemap_loaded_event: EventReader<TilemapLoadedEven
 
         // set position of loaded characters to map spawn position
         for (_uuid, user_data) in room_data.room_users.iter_mut() {
+            if user_data.is_state_synced {
+                continue;
+            }
             user_data.init_pos = map_data.spawn_pos;
             let (x, y) = from_index_to_xy(user_data.init_pos, map_data.tile_width as i32, map_data.tile_height as i32, map_data.map_col);
             user_data.character_controller.set_pos_x(x as f32);
@@ -60,7 +62,7 @@ fn create_character_part(commands: &mut Commands, assets_server: &Res<AssetServe
         },
     ).id();
 
-    load_sprite_from_s3(commands, scene_uuid, s3_data, "shalloville".to_string(), format!("character/{}/{}.png", part_name, part_index), sprite);
+    load_sprite_from_s3(commands, scene_uuid, s3_data, format!("character/{}/{}.png", part_name, part_index), sprite);
     commands.entity(anchor).add_child(sprite);
 
     return (anchor, sprite, *offset)
@@ -240,7 +242,11 @@ pub fn move_character_on_input(keys: Res<ButtonInput<KeyCode>>, map_data: Res<Ma
 
 
             if rtc_resource.is_multiplayer() {
-                rtc_resource.send_message(room_data.room_users.len(), &room_data.room_id, &room_data.this_user_uuid, "move", &format!("{} {}", current_pos.0, current_pos.1), DataPacketKind::Lossy)
+                rtc_resource.send_message(room_data.room_users.len(), &room_data.this_user_uuid, RoomMessage::Move {
+                    x: current_pos.0,
+                    y: current_pos.1,
+                    time_ms: (time.elapsed_seconds_f64() * 1000.0) as u32
+                })
             }
         } else {
             user_data.character_controller.set_animation("idle");
@@ -248,9 +254,22 @@ pub fn move_character_on_input(keys: Res<ButtonInput<KeyCode>>, map_data: Res<Ma
     }
 }
 
-pub fn update_character_controller(mut transform_query: Query<&mut Transform>, mut characters_query: Query<(&mut Depth, &mut Character)>, mut room_data: ResMut<RoomData>, mut interactive_triggers_query: Query<(Entity, &InteractiveTrigger)>, map_data: Res<MapData>, mut z_button_query: Query<&mut TriggerButton>, mut trigger_button_state: ResMut<NextState<TriggerButtonState>>, mut rtc_resource: ResMut<RTCResource>){
+pub fn interpolate_remote_characters(time: Res<Time>, mut room_data: ResMut<RoomData>){
+    let this_user_uuid = room_data.this_user_uuid.to_string();
+    let local_time = time.elapsed_seconds_f64();
+
+    for (uuid, user_data) in room_data.room_users.iter_mut() {
+        if uuid.eq(&this_user_uuid) || user_data.position_buffer.is_empty() {
+            continue;
+        }
+        if let Some((x, y)) = user_data.position_buffer.sample(local_time) {
+            user_data.character_controller.set_interpolated_pos(x, y);
+        }
+    }
+}
+
+pub fn update_character_controller(mut transform_query: Query<&mut Transform>, mut characters_query: Query<(&mut Depth, &mut Character)>, mut room_data: ResMut<RoomData>, mut interactive_triggers_query: Query<(Entity, &InteractiveTrigger)>, map_data: Res<MapData>, mut z_button_query: Query<&mut TriggerButton>, mut trigger_button_state: ResMut<NextState<TriggerButtonState>>, mut rtc_resource: ResMut<RTCResource>, time: Res<Time>){
     let this_user_uuid = room_data.this_user_uuid.to_string();
-    let room_id = room_data.room_id.to_string();
     let room_len = room_data.room_users.len();
 
     for (uuid, user_data) in room_data.room_users.iter_mut() {
@@ -278,7 +297,12 @@ pub fn update_character_controller(mut transform_query: Query<&mut Transform>, m
                 let current_animation = user_data.character_controller.get_animation();
                 if current_animation.0.ne(&current_animation.1) {
                     if this_user_uuid.eq(uuid) && rtc_resource.is_multiplayer() {
-                        rtc_resource.send_message(room_len, &room_id, &this_user_uuid, "anime", &format!("{} {} {}", &current_animation.0, pos.0, pos.1), DataPacketKind::Reliable)
+                        rtc_resource.send_message(room_len, &this_user_uuid, RoomMessage::Animation {
+                            name: current_animation.0.to_string(),
+                            x: pos.0,
+                            y: pos.1,
+                            time_ms: (time.elapsed_seconds_f64() * 1000.0) as u32
+                        })
                     }
                     reset_all_animations(&mut character, &mut transform_query);
                 }
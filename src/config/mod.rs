@@ -0,0 +1,18 @@
+use bevy::prelude::*;
+
+mod systems;
+use systems::*;
+
+pub mod resources;
+use resources::*;
+
+pub struct ConfigPlugin;
+
+impl Plugin for ConfigPlugin {
+    fn build(&self, app: &mut App) {
+        app
+        .init_resource::<AppConfig>()
+        .init_resource::<LaunchOptions>()
+        .add_systems(Update, apply_launch_options.run_if(|launch_options: Res<LaunchOptions>| launch_options.is_pending));
+    }
+}
@@ -0,0 +1,201 @@
+use std::{env, fs, path::PathBuf, str::FromStr};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::networking::s3::resources::DEFAULT_PARALLEL_DOWNLOADS;
+
+// settings read from <config dir>/shalloville/config.json, any SHALLOVILLE_* variable wins over the file
+#[derive(Resource, Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AppConfig {
+    // no camera, LiveKit or S3: rooms stay in this process and maps come from the bundled assets
+    pub offline: bool,
+    pub bucket: String,
+
+    pub tile_width: f32,
+    pub tile_height: f32,
+    pub tile_atlas_width: i32,
+    pub tile_atlas_height: i32,
+
+    pub livekit_url: String,
+    pub token_endpoint: String,
+
+    pub storage: String,
+    pub storage_dir: String,
+    pub max_downloads: usize,
+
+    pub transport: String,
+    pub loopback_clients: usize
+}
+
+impl Default for AppConfig {
+    fn default() -> AppConfig {
+        AppConfig {
+            offline: false,
+            bucket: "shalloville".to_string(),
+
+            tile_width: 64.0,
+            tile_height: 64.0,
+            tile_atlas_width: 640,
+            tile_atlas_height: 128,
+
+            livekit_url: String::new(),
+            token_endpoint: String::new(),
+
+            storage: "s3".to_string(),
+            storage_dir: "storage".to_string(),
+            max_downloads: DEFAULT_PARALLEL_DOWNLOADS,
+
+            transport: "livekit".to_string(),
+            loopback_clients: 0
+        }
+    }
+}
+
+impl AppConfig {
+    pub fn get_default_path() -> Option<PathBuf> {
+        return dirs::config_dir().map(|config_dir| config_dir.join("shalloville").join("config.json"));
+    }
+
+    // a missing file is created with the defaults so there is something to edit
+    pub fn load(path: Option<PathBuf>) -> AppConfig {
+        let mut config = AppConfig::default();
+
+        if let Some(path) = path.or(AppConfig::get_default_path()) {
+            match fs::read_to_string(&path) {
+                Ok(config_str) => {
+                    match serde_json::from_str::<AppConfig>(&config_str) {
+                        Ok(file_config) => config = file_config,
+                        Err(e) => println!("config {} is invalid, using defaults: {}", path.display(), e)
+                    }
+                },
+                Err(_) => {
+                    if let Some(parent) = path.parent() {
+                        let _ = fs::create_dir_all(parent);
+                    }
+                    if let Ok(config_str) = serde_json::to_string_pretty(&config) {
+                        if fs::write(&path, config_str).is_ok() {
+                            println!("created config {}", path.display());
+                        }
+                    }
+                }
+            }
+        }
+
+        config.apply_env();
+        return config;
+    }
+
+    fn apply_env(&mut self) {
+        set_from_env(&mut self.offline, "SHALLOVILLE_OFFLINE");
+        set_from_env(&mut self.bucket, "SHALLOVILLE_BUCKET");
+        set_from_env(&mut self.tile_width, "SHALLOVILLE_TILE_WIDTH");
+        set_from_env(&mut self.tile_height, "SHALLOVILLE_TILE_HEIGHT");
+        set_from_env(&mut self.tile_atlas_width, "SHALLOVILLE_TILE_ATLAS_WIDTH");
+        set_from_env(&mut self.tile_atlas_height, "SHALLOVILLE_TILE_ATLAS_HEIGHT");
+        set_from_env(&mut self.livekit_url, "LIVEKIT_URL");
+        set_from_env(&mut self.token_endpoint, "LIVEKIT_TOKEN_ENDPOINT");
+        set_from_env(&mut self.storage, "SHALLOVILLE_STORAGE");
+        set_from_env(&mut self.storage_dir, "SHALLOVILLE_STORAGE_DIR");
+        set_from_env(&mut self.max_downloads, "SHALLOVILLE_MAX_DOWNLOADS");
+        set_from_env(&mut self.transport, "SHALLOVILLE_TRANSPORT");
+        set_from_env(&mut self.loopback_clients, "SHALLOVILLE_LOOPBACK_CLIENTS");
+    }
+}
+
+fn set_from_env<T: FromStr>(value: &mut T, key: &str) {
+    if let Ok(env_value) = env::var(key) {
+        match env_value.parse::<T>() {
+            Ok(parsed) => *value = parsed,
+            Err(_) => println!("ignoring {}={}, not a valid value", key, env_value)
+        }
+    }
+}
+
+// what to open right after startup, taken from the command line
+#[derive(Resource, Clone, Debug)]
+pub struct LaunchOptions {
+    pub config_path: Option<PathBuf>,
+    pub room: Option<String>,
+    pub name: Option<String>,
+    pub map: Option<String>,
+    pub editor: bool,
+    pub offline: bool,
+    pub is_pending: bool,
+
+    // headless mode: this many bots join the room instead of opening a window
+    pub bots: usize,
+    pub bot_script: Option<String>,
+    pub bot_avatar: Option<String>,
+    pub bot_chat_interval: Option<f32>
+}
+
+impl Default for LaunchOptions {
+    fn default() -> LaunchOptions {
+        LaunchOptions {
+            config_path: None,
+            room: None,
+            name: None,
+            map: None,
+            editor: false,
+            offline: false,
+            is_pending: false,
+
+            bots: 0,
+            bot_script: None,
+            bot_avatar: None,
+            bot_chat_interval: None
+        }
+    }
+}
+
+impl LaunchOptions {
+    // shalloville [--room <id>] [--name <name>] [--map <name>] [--editor] [--offline] [--config <path>]
+    //             [--bots <count> --script <script> --avatar <parts> --chat-interval <seconds>]
+    pub fn from_args(args: Vec<String>) -> LaunchOptions {
+        let mut launch_options = LaunchOptions::default();
+        if let Ok(config_path) = env::var("SHALLOVILLE_CONFIG") {
+            launch_options.config_path = Some(PathBuf::from(config_path));
+        }
+
+        let mut args = args.into_iter().skip(1);
+        while let Some(arg) = args.next() {
+            // both "--room abc" and "--room=abc" are accepted
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg.to_string(), None)
+            };
+
+            match flag.as_str() {
+                "--editor" => launch_options.editor = true,
+                "--offline" => launch_options.offline = true,
+                "--room" | "--name" | "--map" | "--config" | "--bots" | "--script" | "--avatar" | "--chat-interval" => {
+                    let value = match inline_value {
+                        Some(value) => Some(value),
+                        None => args.next()
+                    };
+                    match value {
+                        Some(value) if !value.is_empty() => {
+                            match flag.as_str() {
+                                "--room" => launch_options.room = Some(value),
+                                "--name" => launch_options.name = Some(value),
+                                "--map" => launch_options.map = Some(value),
+                                "--config" => launch_options.config_path = Some(PathBuf::from(value)),
+                                "--bots" => launch_options.bots = value.parse::<usize>().unwrap_or(0),
+                                "--script" => launch_options.bot_script = Some(value),
+                                "--avatar" => launch_options.bot_avatar = Some(value),
+                                _ => launch_options.bot_chat_interval = value.parse::<f32>().ok()
+                            }
+                        },
+                        _ => println!("{} needs a value", flag)
+                    }
+                },
+                _ => println!("unknown argument {}", arg)
+            }
+        }
+
+        launch_options.is_pending = launch_options.editor || launch_options.room.is_some() || launch_options.name.is_some() || launch_options.map.is_some();
+        return launch_options;
+    }
+}
@@ -0,0 +1,37 @@
+use bevy::prelude::*;
+
+use crate::{editor::resources::{EditorStorage, Scene}, main_menu::resources::RoomData, map_structures::resources::MapData, networking::{rtc::{resources::RTCResource, systems::check_room}, s3::{resources::S3Data, systems::load_tilemap_from_s3}}};
+
+use super::{AppConfig, LaunchOptions};
+
+// waits for the first scene, then does what --room, --name, --map and --editor asked for
+pub fn apply_launch_options(mut commands: Commands, scene: Res<Scene>, mut launch_options: ResMut<LaunchOptions>, mut room_data: ResMut<RoomData>, mut map_data: ResMut<MapData>, mut s3_data: ResMut<S3Data>, mut editor_storage: ResMut<EditorStorage>, mut rtc_resource: ResMut<RTCResource>, config: Res<AppConfig>){
+    match scene.scene_name.as_str() {
+        "tilemap editor" => {
+            launch_options.is_pending = false;
+            if let Some(map) = &launch_options.map {
+                load_tilemap_from_s3(&mut commands, &scene.scene_uuid, &mut s3_data, map.to_string());
+                editor_storage.publish_name = map.to_string();
+            }
+        },
+        "lobby" => {
+            launch_options.is_pending = false;
+            if let Some(name) = &launch_options.name {
+                room_data.this_user_name = name.to_string();
+            }
+            // picked by default when a new room is created
+            if let Some(map) = &launch_options.map {
+                map_data.map_name = map.to_string();
+            }
+            if let Some(room) = &launch_options.room {
+                if config.offline {
+                    println!("offline mode, not joining room {}", room);
+                    return;
+                }
+                room_data.room_id = room.to_string();
+                check_room(&mut commands, &scene.scene_uuid, &mut rtc_resource, &mut room_data);
+            }
+        },
+        _ => {}
+    }
+}
@@ -19,15 +19,23 @@ impl Plugin for EditorPlugin {
         app
         .init_resource::<DefaultStates>()
         .init_resource::<Scene>()
+        .init_resource::<EditorHistory>()
+        .init_resource::<EditorStorage>()
         .init_state::<EditorState>()
         .add_plugins(EntiTilesPlugin)
         .add_event::<TilemapLoadedEvent>()
         .add_event::<CreateSceneEvent>()
         .add_systems(Startup, setup_tilemap_editor.run_if(in_state(EditorState::Open)))
         .add_systems(Update, tilemap_editor.run_if(in_state(EditorState::Open)))
+        .add_systems(Update, tilemap_layer_editor.run_if(in_state(EditorState::Open)))
+        .add_systems(Update, tilemap_palette.run_if(in_state(EditorState::Open)))
+        .add_systems(Update, tilemap_storage_editor.run_if(in_state(EditorState::Open)))
+        .add_systems(Update, apply_editor_history.run_if(in_state(EditorState::Open)))
         .add_systems(Update, update_preview_image.run_if(in_state(EditorState::Open)))
         .add_systems(Update, load_tilemap_event_listener)
         .add_systems(Update, load_sprite_event_listener)
+        .add_systems(Update, upload_tilemap_event_listener)
+        .add_systems(Update, list_tilemap_event_listener)
         .add_systems(Update, on_create_scene);
     }
 }
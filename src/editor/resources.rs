@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use bevy::prelude::*;
 use num_enum::TryFromPrimitive;
 
-use crate::{camera::CameraState, character::{CharacterExistState, MovementState}, game::{MultiplayerRoomState, TriggerButtonState}, main_menu::MainMenuState, networking::rtc::RoomMetadataListener, nokhwa::StreamingState, wardrobe::WardrobeState};
+use crate::{camera::CameraState, character::{CharacterExistState, MovementState}, game::{MultiplayerRoomState, TriggerButtonState}, main_menu::MainMenuState, map_structures::resources::MapEntityKind, networking::rtc::RoomMetadataListener, nokhwa::StreamingState, wardrobe::WardrobeState};
 
 use super::EditorState;
 
@@ -29,6 +29,112 @@ impl Default for Scene {
     }
 }
 
+pub const MAX_EDITOR_HISTORY: usize = 100;
+
+#[derive(Clone, Debug)]
+pub enum EditorAction {
+    Tile { layer: usize, pos: i32, before: i32, after: i32 },
+    Hitbox { pos: i32, before: bool, after: bool },
+    Entity { kind: MapEntityKind, pos: i32, before: Option<i32>, after: Option<i32> },
+    Info { pos: String, before: Option<Vec<(String, String)>>, after: Option<Vec<(String, String)>> }
+}
+
+pub struct EditorHistoryEntry {
+    pub name: String,
+    pub actions: Vec<EditorAction>
+}
+
+pub enum EditorHistoryRequest {
+    Undo(usize),
+    Redo(usize)
+}
+
+#[derive(Resource)]
+pub struct EditorHistory {
+    pub undo_stack: Vec<EditorHistoryEntry>,
+    pub redo_stack: Vec<EditorHistoryEntry>,
+    pub request: Option<EditorHistoryRequest>,
+    is_entry_open: bool
+}
+
+impl Default for EditorHistory {
+    fn default() -> EditorHistory {
+        EditorHistory {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            request: None,
+            is_entry_open: false
+        }
+    }
+}
+
+impl EditorHistory {
+    // actions pushed while an entry is open are merged into it, so a whole brush stroke undoes at once
+    pub fn push(&mut self, name: &str, action: EditorAction) {
+        self.redo_stack.clear();
+        if self.is_entry_open {
+            if let Some(entry) = self.undo_stack.last_mut() {
+                if entry.name.eq(name) {
+                    entry.actions.push(action);
+                    return;
+                }
+            }
+        }
+        self.undo_stack.push(EditorHistoryEntry {
+            name: name.to_string(),
+            actions: Vec::from([action])
+        });
+        self.is_entry_open = true;
+        if self.undo_stack.len() > MAX_EDITOR_HISTORY {
+            self.undo_stack.remove(0);
+        }
+    }
+
+    pub fn close_entry(&mut self) {
+        self.is_entry_open = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.request = None;
+        self.is_entry_open = false;
+    }
+}
+
+#[derive(Resource)]
+pub struct EditorStorage {
+    pub is_publish_window_opened: bool,
+    pub is_open_window_opened: bool,
+    pub is_overwrite_confirming: bool,
+    pub is_list_requested: bool,
+    pub is_listing: bool,
+    // the scene the list task belongs to, the task is dropped when the scene changes
+    pub listing_scene_uuid: String,
+    // false until the latest list succeeded, publishing without it could overwrite a map unasked
+    pub is_list_loaded: bool,
+    pub publish_name: String,
+    pub map_names: Vec<String>,
+    pub status: String
+}
+
+impl Default for EditorStorage {
+    fn default() -> EditorStorage {
+        EditorStorage {
+            is_publish_window_opened: false,
+            is_open_window_opened: false,
+            is_overwrite_confirming: false,
+            is_list_requested: false,
+            is_listing: false,
+            listing_scene_uuid: String::new(),
+            is_list_loaded: false,
+            publish_name: String::new(),
+            map_names: Vec::new(),
+            status: String::new()
+        }
+    }
+}
+
 impl Default for DefaultStates {
     fn default() -> DefaultStates {
         DefaultStates {
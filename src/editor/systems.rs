@@ -1,6 +1,5 @@
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
-use bevy_entitiles::math::TileArea;
 use bevy_entitiles::prelude::StandardTilemapBundle;
 use bevy_entitiles::prelude::TileBuilder;
 use bevy_entitiles::prelude::TileLayer;
@@ -13,7 +12,7 @@ use bevy_entitiles::prelude::TilemapType;
 use uuid::Uuid;
 use std::collections::HashMap;
 use super::components::EditorPreviewImage;
-use super::resources::StateName;
+use super::resources::{EditorAction, EditorHistory, EditorHistoryRequest, EditorStorage, StateName};
 use super::Scene;
 
 use std::fs::File;
@@ -32,10 +31,19 @@ use crate::main_menu::resources::RoomData;
 use crate::networking::rtc::RoomMetadataListener;
 use crate::networking::s3::components::LoadSpriteTask;
 use crate::networking::s3::components::LoadTilemapTask;
+use crate::networking::s3::components::ListTilemapTask;
+use crate::networking::s3::components::UploadTilemapTask;
+use crate::networking::s3::resources::S3Data;
+use crate::networking::s3::systems::list_tilemaps_from_s3;
+use crate::networking::s3::systems::load_tilemap_from_s3;
+use crate::networking::s3::systems::upload_tilemap_to_s3;
 use crate::main_menu::MainMenuState;
 use crate::nokhwa::StreamingState;
 use crate::mouse::resources::MouseData;
 use crate::systems::get_interactive_trigger;
+use crate::systems::set_map_entity;
+use crate::systems::set_map_hitbox;
+use crate::systems::set_map_tile;
 use crate::systems::load_animated_sprite;
 use crate::systems::load_static_sprite;
 use crate::map_structures::components::AnimatedTag;
@@ -43,15 +51,16 @@ use crate::map_structures::components::InteractiveTrigger;
 use crate::map_structures::components::SpriteTag;
 use crate::map_structures::components::MapStructure;
 use crate::utils::from_index_to_grid_xy;
-use crate::utils::group_numbers;
 use crate::wardrobe::WardrobeState;
-use crate::{camera::CameraState, map_structures::resources::{MapData, MapSaver, MapStructures}, utils::from_index_to_xy, FocusState, TilemapEditorState};
+use crate::{camera::CameraState, map_structures::resources::{BrushTool, MapData, MapEntity, MapEntityKind, MapFile, MapFileLayer, MapLayer, MapStructures, EMPTY_TILE, MAX_LAYER_ORDER}, utils::from_index_to_xy, FocusState, TilemapEditorState};
 
 use super::CreateSceneEvent;
 use super::DefaultStates;
 use super::EditorState;
 use super::TilemapLoadedEvent;
 
+const PALETTE_THUMBNAIL_SIZE: f32 = 32.0;
+
 pub fn setup_tilemap_editor(mut commands: Commands, mut create_scene_event: EventWriter<CreateSceneEvent>, mut room_data: ResMut<RoomData>) {
     create_scene(&mut commands, &mut create_scene_event, &mut room_data, "tilemap editor", HashMap::from([
         (StateName::EditorState as u8, 1),
@@ -87,7 +96,7 @@ pub fn update_preview_image(mut commands: Commands, mut preview_image_query: Que
             match map_data.editor_mode_str.as_str() {
                 "Place Tile Mode" => {
                     if let Some((image_handle, atlas_layout, total)) = map_data.atlas_hashmap.get("tile") {
-                        let place_tile_index = match map_data.place_tile_index.parse::<i8>() {
+                        let place_tile_index = match map_data.place_tile_index.parse::<i32>() {
                             Ok(n) => n,
                             Err(_) => 0,
                         };
@@ -112,7 +121,7 @@ pub fn update_preview_image(mut commands: Commands, mut preview_image_query: Que
                 },
                 "Place Entity Mode" => {
                     *visibility = Visibility::Visible;
-                    let place_entity_index = match map_data.place_entity_index.parse::<i8>() {
+                    let place_entity_index = match map_data.place_entity_index.parse::<i32>() {
                         Ok(n) => n,
                         Err(_) => 0
                     };
@@ -134,11 +143,11 @@ pub fn update_preview_image(mut commands: Commands, mut preview_image_query: Que
                 },
                 "Place Animated Entity Mode" => {
                     *visibility = Visibility::Visible;
-                    let place_animated_entity_index = match map_data.place_animated_index.parse::<i8>() {
+                    let place_animated_entity_index = match map_data.place_animated_index.parse::<i32>() {
                         Ok(n) => n,
                         Err(_) => 0
                     };
-                    if let Some((image_handle, layout_handle, image_count)) = load_animated_sprite(&mut asset_server, &mut map_data, &mut texture_atlas_layouts) {
+                    if let Some((image_handle, layout_handle, image_count)) = load_animated_sprite(&mut asset_server, &mut map_data, &mut texture_atlas_layouts, &place_animated_entity_index.to_string()) {
                         let sprite =
                         commands.spawn((
                             SpriteBundle {
@@ -168,7 +177,7 @@ pub fn update_preview_image(mut commands: Commands, mut preview_image_query: Que
     }
 }
 
-pub fn tilemap_editor(mut commands: Commands, mut contexts: EguiContexts, mut asset_server: Res<AssetServer>, mut create_scene_event: EventWriter<CreateSceneEvent>, mut map_data: ResMut<MapData>, mut room_data: ResMut<RoomData>, mut editor_state: Res<State<EditorState>>, mut map_structures: ResMut<MapStructures>, mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>, mouse_data: ResMut<MouseData>, mut tilemap_editor_state: ResMut<NextState<TilemapEditorState>>, mut camera_state: ResMut<NextState<CameraState>>, mut focus_state: ResMut<NextState<FocusState>>, sprite_query: Query<&SpriteTag>, animated_sprite_query: Query<&AnimatedTag>, mut preview_image_query: Query<&mut EditorPreviewImage>){
+pub fn tilemap_editor(mut commands: Commands, mut contexts: EguiContexts, mut asset_server: Res<AssetServer>, mut create_scene_event: EventWriter<CreateSceneEvent>, mut map_data: ResMut<MapData>, mut room_data: ResMut<RoomData>, mut editor_state: Res<State<EditorState>>, mut map_structures: ResMut<MapStructures>, mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>, mouse_data: ResMut<MouseData>, mut tilemap_editor_state: ResMut<NextState<TilemapEditorState>>, mut camera_state: ResMut<NextState<CameraState>>, mut focus_state: ResMut<NextState<FocusState>>, (sprite_query, animated_sprite_query): (Query<&SpriteTag>, Query<&AnimatedTag>), mut preview_image_query: Query<&mut EditorPreviewImage>, (mut editor_history, mut editor_storage): (ResMut<EditorHistory>, ResMut<EditorStorage>)){
     let ctx = contexts.ctx_mut();
     if ctx.wants_pointer_input() {
         focus_state.set(FocusState::UI);
@@ -217,6 +226,7 @@ pub fn tilemap_editor(mut commands: Commands, mut contexts: EguiContexts, mut as
             map_data.map_row = map_data.map_row_str.parse::<i32>().unwrap();
             map_data.map_col = map_data.map_col_str.parse::<i32>().unwrap();
             set_clean_tilemap(&mut commands, &mut create_scene_event,&mut map_data, &mut map_structures, &mut room_data);
+            editor_history.clear();
         }
         ui.separator();
 
@@ -236,33 +246,7 @@ pub fn tilemap_editor(mut commands: Commands, mut contexts: EguiContexts, mut as
                 map_data.editor_mode_str = "Place Tile Mode".to_string();
                 preview_image.is_updated = true;
             }
-            if ui.button("-").clicked() {
-                let mut index = match map_data.place_tile_index.parse::<i8>() {
-                    Ok(n) => {n - 1},
-                    Err(_) => {0},
-                };
-                if let Some((_, _, total)) = map_data.atlas_hashmap.get("tile"){
-                    if index < 0 {
-                        index = *total;
-                    }
-                    map_data.place_tile_index = index.to_string();
-                    preview_image.is_updated = true;
-                }
-            }
-            ui.text_edit_singleline(&mut map_data.place_tile_index);
-            if ui.button("+").clicked() {
-                let mut index = match map_data.place_tile_index.parse::<i8>() {
-                    Ok(n) => {n + 1},
-                    Err(_) => {0},
-                };
-                if let Some((_, _, total)) = map_data.atlas_hashmap.get("tile"){
-                    if index > *total{
-                        index = 0;
-                    }
-                    map_data.place_tile_index = index.to_string();
-                    preview_image.is_updated = true;
-                }
-            }
+            ui.label(format!("Tile: {}", map_data.place_tile_index));
         });
 
         if ui.button("Place Hitbox Mode").clicked() {
@@ -272,6 +256,13 @@ pub fn tilemap_editor(mut commands: Commands, mut contexts: EguiContexts, mut as
             preview_image.is_updated = true;
         }
 
+        ui.horizontal(|ui| {
+            ui.label("Brush: ");
+            ui.selectable_value(&mut map_data.brush_tool, BrushTool::Drag, "Drag");
+            ui.selectable_value(&mut map_data.brush_tool, BrushTool::Rectangle, "Rectangle");
+            ui.selectable_value(&mut map_data.brush_tool, BrushTool::FloodFill, "Flood Fill");
+        });
+
         ui.horizontal(|ui| {
             if ui.button("Place Entity Mode").clicked() {
                 tilemap_editor_state.set(TilemapEditorState::PlaceEntity);
@@ -279,7 +270,7 @@ pub fn tilemap_editor(mut commands: Commands, mut contexts: EguiContexts, mut as
                 map_data.editor_mode_str = "Place Entity Mode".to_string();
                 preview_image.is_updated = true;
             }
-            ui.text_edit_singleline(&mut map_data.place_entity_index);
+            ui.label(format!("Sprite: {}", map_data.place_entity_index));
         });
 
         ui.horizontal(|ui| {
@@ -289,7 +280,7 @@ pub fn tilemap_editor(mut commands: Commands, mut contexts: EguiContexts, mut as
                 map_data.editor_mode_str = "Place Animated Entity Mode".to_string();
                 preview_image.is_updated = true;
             }
-            ui.text_edit_singleline(&mut map_data.place_animated_index);
+            ui.label(format!("Animated: {}", map_data.place_animated_index));
         });
 
         ui.horizontal(|ui| {
@@ -307,37 +298,13 @@ pub fn tilemap_editor(mut commands: Commands, mut contexts: EguiContexts, mut as
 
             #[cfg(not(target_arch = "wasm32"))] 
             {
-                let mut map_saver = MapSaver {..default()};
-                map_saver.col = map_data.map_col;
-                map_saver.row = map_data.map_row;
-
-                for counter in 0..map_structures.tiles.len() {
-                    let index = map_structures.tiles[counter];
-                    map_saver.tilemap.push_str(&format!("{} ", index));
-                }
-                for (pos, entity) in map_structures.sprite.iter() {
-                    let t = sprite_query.get(*entity).unwrap();
-                    map_saver.sprites.push_str(&format!("{} {} ", pos, t.index));
-                }
-                for (pos, entity) in map_structures.animated_sprite.iter() {
-                    let t = animated_sprite_query.get(*entity).unwrap();
-                    map_saver.animated_sprites.push_str(&format!("{} {} ", pos, t.index));
-                }
-                for (pos, (is_hitbox, _)) in map_structures.hitbox.iter() {
-                    if *is_hitbox {
-                        map_saver.hitbox.push_str(&format!("{} ", pos));
-                    }
-                }
-                map_saver.tilesheet = map_data.tile_atlas_name.to_string();
-                if let Ok(info) = serde_json::to_string(&map_structures.info) {
-                    map_saver.info = info;
-                }
+                let map_file = build_map_file(&map_data, &map_structures, &sprite_query, &animated_sprite_query);
 
                 if let Some(path) = dirs::download_dir() {
                     let current_date = chrono::Utc::now();
                     let filename = format!("{}/{}{}.txt", path.display(), current_date.date_naive(), current_date.timestamp());
                     let mut f = File::create(filename).expect("Unable to create file");
-                    let content = serde_json::to_string(&map_saver).unwrap();
+                    let content = serde_json::to_string(&map_file).unwrap();
                     f.write_all(content.as_bytes()).expect("Unable to write data");
                 }
             }
@@ -352,19 +319,74 @@ pub fn tilemap_editor(mut commands: Commands, mut contexts: EguiContexts, mut as
                             let mut f = File::open(file).expect("Unable to open file");
                             let mut data = String::new();
                             f.read_to_string(&mut data).expect("Unable to read string");
-                            let map_saver: MapSaver = serde_json::from_str(&data).unwrap();
-                            create_scene(&mut commands, &mut create_scene_event, &mut room_data, "tilemap editor", HashMap::from([
-                                (StateName::EditorState as u8, 1)
-                            ]), None);
-
-                            map_data.map_name = "editor".to_string();
-                            load_tilemap_from_saver(&mut commands, &mut asset_server, map_saver, &mut map_data, &mut room_data, &mut map_structures, &mut editor_state, &mut texture_atlas_layouts);
+                            match MapFile::parse(&data) {
+                                Ok(map_file) => {
+                                    create_scene(&mut commands, &mut create_scene_event, &mut room_data, "tilemap editor", HashMap::from([
+                                        (StateName::EditorState as u8, 1)
+                                    ]), None);
+
+                                    map_data.map_name = "editor".to_string();
+                                    load_tilemap_from_map_file(&mut commands, &mut asset_server, map_file, &mut map_data, &mut room_data, &mut map_structures, &mut editor_state, &mut texture_atlas_layouts);
+                                    editor_history.clear();
+                                },
+                                Err(e) => {println!("import map failed: {}", e);}
+                            }
                         }
                         None => {}
                     }
                 }
             }
         }
+
+        ui.horizontal(|ui| {
+            if ui.button("Publish").clicked() {
+                if editor_storage.publish_name.is_empty() && map_data.map_name.ne("editor") {
+                    editor_storage.publish_name = map_data.map_name.to_string();
+                }
+                editor_storage.is_publish_window_opened = true;
+                editor_storage.is_overwrite_confirming = false;
+                editor_storage.is_list_requested = true;
+                editor_storage.status.clear();
+            }
+            if ui.button("Open from storage").clicked() {
+                editor_storage.is_open_window_opened = true;
+                editor_storage.is_list_requested = true;
+                editor_storage.status.clear();
+            }
+        });
+
+        ui.separator();
+
+        egui::CollapsingHeader::new("History").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                if ui.add_enabled(!editor_history.undo_stack.is_empty(), egui::Button::new("Undo")).clicked() {
+                    editor_history.request = Some(EditorHistoryRequest::Undo(1));
+                }
+                if ui.add_enabled(!editor_history.redo_stack.is_empty(), egui::Button::new("Redo")).clicked() {
+                    editor_history.request = Some(EditorHistoryRequest::Redo(1));
+                }
+            });
+
+            let mut request: Option<EditorHistoryRequest> = None;
+            egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
+                let undo_len = editor_history.undo_stack.len();
+                for (counter, entry) in editor_history.undo_stack.iter().enumerate() {
+                    let label = format!("{} ({})", entry.name, entry.actions.len());
+                    if ui.selectable_label(counter + 1 == undo_len, label).clicked() {
+                        request = Some(EditorHistoryRequest::Undo(undo_len - counter - 1));
+                    }
+                }
+                for (counter, entry) in editor_history.redo_stack.iter().rev().enumerate() {
+                    let label = egui::RichText::new(format!("{} ({})", entry.name, entry.actions.len())).weak();
+                    if ui.selectable_label(false, label).clicked() {
+                        request = Some(EditorHistoryRequest::Redo(counter + 1));
+                    }
+                }
+            });
+            if request.is_some() {
+                editor_history.request = request;
+            }
+        });
     });
 
     egui::Window::new("Cursor")
@@ -394,6 +416,7 @@ pub fn tilemap_editor(mut commands: Commands, mut contexts: EguiContexts, mut as
             ui.text_edit_multiline(&mut map_data.info_window_content);
 
             if ui.button("Add").clicked() {
+                let before = map_structures.info.get(&map_data.place_info_index).cloned();
                 if let Some(info_vec) = map_structures.info.get_mut(&map_data.place_info_index) {
                     info_vec.push((map_data.info_window_title.to_string(), map_data.info_window_content.to_string()));
                 } else {
@@ -404,6 +427,8 @@ pub fn tilemap_editor(mut commands: Commands, mut contexts: EguiContexts, mut as
                         ])
                     );
                 }
+                editor_history.push("Add Info", EditorAction::Info { pos: map_data.place_info_index.to_string(), before, after: map_structures.info.get(&map_data.place_info_index).cloned() });
+                editor_history.close_entry();
                 map_data.info_window_title.clear();
                 map_data.info_window_content.clear();
             }
@@ -422,8 +447,9 @@ pub fn tilemap_editor(mut commands: Commands, mut contexts: EguiContexts, mut as
 
             ui.horizontal(|ui| {    
                 if ui.button("Clear All").clicked() {
-                    if map_structures.info.contains_key(&map_data.place_info_index) {
-                        map_structures.info.remove(&map_data.place_info_index);
+                    if let Some(before) = map_structures.info.remove(&map_data.place_info_index) {
+                        editor_history.push("Clear Info", EditorAction::Info { pos: map_data.place_info_index.to_string(), before: Some(before), after: None });
+                        editor_history.close_entry();
                     }
                     map_data.info_window_title.clear();
                     map_data.info_window_content.clear();
@@ -441,34 +467,423 @@ pub fn tilemap_editor(mut commands: Commands, mut contexts: EguiContexts, mut as
     }
 }
 
+pub fn tilemap_storage_editor(mut commands: Commands, mut contexts: EguiContexts, scene: Res<Scene>, mut s3_data: ResMut<S3Data>, mut editor_storage: ResMut<EditorStorage>, mut editor_history: ResMut<EditorHistory>, map_data: Res<MapData>, map_structures: Res<MapStructures>, sprite_query: Query<&SpriteTag>, animated_sprite_query: Query<&AnimatedTag>){
+    // execute_tasks drops tasks of an old scene, so a list started before Generate, Import or Open never comes back
+    if editor_storage.is_listing && editor_storage.listing_scene_uuid.ne(&scene.scene_uuid) {
+        editor_storage.is_listing = false;
+        editor_storage.is_overwrite_confirming = false;
+        editor_storage.is_list_requested = editor_storage.is_publish_window_opened || editor_storage.is_open_window_opened;
+    }
+
+    if editor_storage.is_list_requested {
+        editor_storage.is_list_requested = false;
+        if s3_data.storage.is_none() {
+            editor_storage.status = "Storage is not available".to_string();
+        } else if !editor_storage.is_listing {
+            editor_storage.is_listing = true;
+            editor_storage.is_list_loaded = false;
+            editor_storage.listing_scene_uuid = scene.scene_uuid.to_string();
+            list_tilemaps_from_s3(&mut commands, &scene.scene_uuid, &mut s3_data);
+        }
+    }
+
+    let ctx = contexts.ctx_mut();
+
+    if editor_storage.is_publish_window_opened {
+        let mut is_publishing = false;
+
+        egui::Window::new("Publish Map")
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0 ,0.0))
+        .resizable(false)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Map Name: ");
+                if ui.text_edit_singleline(&mut editor_storage.publish_name).changed() {
+                    editor_storage.is_overwrite_confirming = false;
+                }
+            });
+            let is_valid_name = !editor_storage.publish_name.is_empty() && editor_storage.publish_name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            let is_existing = editor_storage.map_names.contains(&editor_storage.publish_name);
+
+            if !is_valid_name {
+                ui.label("Use letters, numbers, - or _");
+            } else if is_existing {
+                ui.label(format!("map/{}.txt already exists", editor_storage.publish_name));
+            }
+            if !editor_storage.status.is_empty() {
+                ui.label(editor_storage.status.to_string());
+            }
+
+            ui.horizontal(|ui| {
+                if editor_storage.is_overwrite_confirming {
+                    if ui.button("Overwrite").clicked() {
+                        is_publishing = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        editor_storage.is_overwrite_confirming = false;
+                    }
+                } else {
+                    if ui.add_enabled(is_valid_name && editor_storage.is_list_loaded, egui::Button::new("Publish")).clicked() {
+                        if is_existing {
+                            editor_storage.is_overwrite_confirming = true;
+                        } else {
+                            is_publishing = true;
+                        }
+                    }
+                    if ui.button("Close").clicked() {
+                        editor_storage.is_publish_window_opened = false;
+                    }
+                }
+            });
+        });
+
+        if is_publishing {
+            editor_storage.is_overwrite_confirming = false;
+            if s3_data.storage.is_none() {
+                editor_storage.status = "Storage is not available".to_string();
+            } else {
+                let map_file = build_map_file(&map_data, &map_structures, &sprite_query, &animated_sprite_query);
+                let map_str = serde_json::to_string(&map_file).unwrap();
+                editor_storage.status = format!("Publishing {}...", editor_storage.publish_name);
+                upload_tilemap_to_s3(&mut commands, &scene.scene_uuid, &mut s3_data, editor_storage.publish_name.to_string(), map_str);
+            }
+        }
+    }
+
+    if editor_storage.is_open_window_opened {
+        let mut selected: Option<String> = None;
+
+        egui::Window::new("Open from Storage")
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0 ,0.0))
+        .resizable(false)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            if editor_storage.is_listing {
+                ui.label("Loading...");
+            } else if editor_storage.is_list_loaded && editor_storage.map_names.is_empty() {
+                ui.label("No maps found");
+            }
+            if !editor_storage.status.is_empty() {
+                ui.label(editor_storage.status.to_string());
+            }
+
+            egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                for map_name in editor_storage.map_names.iter() {
+                    if ui.button(format!("map/{}.txt", map_name)).clicked() {
+                        selected = Some(map_name.to_string());
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                if ui.add_enabled(!editor_storage.is_listing, egui::Button::new("Refresh")).clicked() {
+                    editor_storage.is_list_requested = true;
+                }
+                if ui.button("Close").clicked() {
+                    editor_storage.is_open_window_opened = false;
+                }
+            });
+        });
+
+        if let Some(map_name) = selected {
+            load_tilemap_from_s3(&mut commands, &scene.scene_uuid, &mut s3_data, map_name.to_string());
+            editor_history.clear();
+            editor_storage.publish_name = map_name;
+            editor_storage.is_open_window_opened = false;
+        }
+    }
+}
+
+pub fn tilemap_palette(mut contexts: EguiContexts, mut asset_server: Res<AssetServer>, mut map_data: ResMut<MapData>, mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>, mut tilemap_editor_state: ResMut<NextState<TilemapEditorState>>, mut camera_state: ResMut<NextState<CameraState>>, mut preview_image_query: Query<&mut EditorPreviewImage>){
+    // thumbnails have to be registered with egui before the context is borrowed
+    let mut tile_thumbnails: Vec<(i32, egui::TextureId, egui::Rect)> = Vec::new();
+    if let Some((image_handle, layout_handle, _)) = map_data.atlas_hashmap.get("tile") {
+        let texture_id = contexts.add_image(image_handle.clone_weak());
+        if let Some(layout) = texture_atlas_layouts.get(layout_handle) {
+            for (index, rect) in layout.textures.iter().enumerate() {
+                tile_thumbnails.push((index as i32, texture_id, get_palette_uv(layout, rect)));
+            }
+        }
+    }
+
+    let mut sprite_thumbnails: Vec<(i32, egui::TextureId)> = Vec::new();
+    for index in 0..map_data.static_sprite_count {
+        let image_handle = load_static_sprite(&mut asset_server, index.to_string());
+        sprite_thumbnails.push((index, contexts.add_image(image_handle)));
+    }
+
+    let mut animated_thumbnails: Vec<(i32, egui::TextureId, egui::Rect)> = Vec::new();
+    for index in 0..map_data.animated_sprite_count {
+        if let Some((image_handle, layout_handle, _)) = load_animated_sprite(&mut asset_server, &mut map_data, &mut texture_atlas_layouts, &index.to_string()) {
+            if let Some(layout) = texture_atlas_layouts.get(&layout_handle) {
+                if let Some(rect) = layout.textures.first() {
+                    animated_thumbnails.push((index, contexts.add_image(image_handle), get_palette_uv(layout, rect)));
+                }
+            }
+        }
+    }
+
+    let mut preview_image = preview_image_query.get_single_mut().unwrap();
+    let mut selected: Option<(&str, TilemapEditorState, i32)> = None;
+    let thumbnail_size = egui::vec2(PALETTE_THUMBNAIL_SIZE, PALETTE_THUMBNAIL_SIZE);
+
+    egui::Window::new("Palette")
+    .anchor(egui::Align2::RIGHT_CENTER, egui::vec2(-5.0 ,0.0))
+    .resizable(false)
+    .title_bar(false)
+    .show(contexts.ctx_mut(), |ui| {
+        egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+            ui.set_max_width(PALETTE_THUMBNAIL_SIZE * 6.0);
+
+            ui.label("Tiles");
+            ui.horizontal_wrapped(|ui| {
+                for (index, texture_id, uv) in tile_thumbnails.iter() {
+                    let is_selected = map_data.editor_mode_str.eq("Place Tile Mode") && map_data.place_tile_index.eq(&index.to_string());
+                    let image = egui::Image::new(egui::load::SizedTexture::new(*texture_id, thumbnail_size)).uv(*uv);
+                    if ui.add(egui::ImageButton::new(image).selected(is_selected)).on_hover_text(format!("Tile {}", index)).clicked() {
+                        selected = Some(("Place Tile Mode", TilemapEditorState::PlaceTile, *index));
+                    }
+                }
+            });
+            ui.separator();
+
+            ui.label("Sprites");
+            ui.horizontal_wrapped(|ui| {
+                for (index, texture_id) in sprite_thumbnails.iter() {
+                    let is_selected = map_data.editor_mode_str.eq("Place Entity Mode") && map_data.place_entity_index.eq(&index.to_string());
+                    let image = egui::Image::new(egui::load::SizedTexture::new(*texture_id, thumbnail_size));
+                    if ui.add(egui::ImageButton::new(image).selected(is_selected)).on_hover_text(get_palette_tooltip("Sprite", "static", *index)).clicked() {
+                        selected = Some(("Place Entity Mode", TilemapEditorState::PlaceEntity, *index));
+                    }
+                }
+            });
+            ui.separator();
+
+            ui.label("Animated Sprites");
+            ui.horizontal_wrapped(|ui| {
+                for (index, texture_id, uv) in animated_thumbnails.iter() {
+                    let is_selected = map_data.editor_mode_str.eq("Place Animated Entity Mode") && map_data.place_animated_index.eq(&index.to_string());
+                    let image = egui::Image::new(egui::load::SizedTexture::new(*texture_id, thumbnail_size)).uv(*uv);
+                    if ui.add(egui::ImageButton::new(image).selected(is_selected)).on_hover_text(get_palette_tooltip("Animated Sprite", "animated", *index)).clicked() {
+                        selected = Some(("Place Animated Entity Mode", TilemapEditorState::PlaceAnimatedEntity, *index));
+                    }
+                }
+            });
+        });
+    });
+
+    if let Some((mode_str, state, index)) = selected {
+        match state {
+            TilemapEditorState::PlaceTile => map_data.place_tile_index = index.to_string(),
+            TilemapEditorState::PlaceEntity => map_data.place_entity_index = index.to_string(),
+            TilemapEditorState::PlaceAnimatedEntity => map_data.place_animated_index = index.to_string(),
+            _ => {}
+        }
+        tilemap_editor_state.set(state);
+        camera_state.set(CameraState::Free);
+        map_data.editor_mode_str = mode_str.to_string();
+        preview_image.is_updated = true;
+    }
+}
+
+fn get_palette_uv(layout: &TextureAtlasLayout, rect: &URect) -> egui::Rect {
+    let size = layout.size.as_vec2();
+    return egui::Rect::from_min_max(
+        egui::pos2(rect.min.x as f32 / size.x, rect.min.y as f32 / size.y),
+        egui::pos2(rect.max.x as f32 / size.x, rect.max.y as f32 / size.y)
+    );
+}
+
+fn get_palette_tooltip(label: &str, sprite_type: &str, index: i32) -> String {
+    let mut tooltip = format!("{} {}", label, index);
+    for (key, interactive_type) in get_interactive_trigger(sprite_type, index).iter() {
+        tooltip.push_str(&format!("\n{:?}: {:?}", key, interactive_type));
+    }
+    return tooltip;
+}
+
+pub fn apply_editor_history(mut commands: Commands, mut contexts: EguiContexts, keys: Res<ButtonInput<KeyCode>>, buttons: Res<ButtonInput<MouseButton>>, mut editor_history: ResMut<EditorHistory>, mut asset_server: Res<AssetServer>, mut map_data: ResMut<MapData>, mut map_structures: ResMut<MapStructures>, mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>, mut tilemap_query: Query<&mut TilemapStorage>){
+    // a brush stroke is one history entry, from press to release
+    if buttons.just_released(MouseButton::Left) || buttons.just_released(MouseButton::Right) {
+        editor_history.close_entry();
+    }
+
+    if !contexts.ctx_mut().wants_keyboard_input() && keys.just_pressed(KeyCode::KeyZ) {
+        let is_ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight, KeyCode::SuperLeft, KeyCode::SuperRight]);
+        let is_shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        if is_ctrl && is_shift {
+            editor_history.request = Some(EditorHistoryRequest::Redo(1));
+        } else if is_ctrl {
+            editor_history.request = Some(EditorHistoryRequest::Undo(1));
+        }
+    }
+
+    if let Some(request) = editor_history.request.take() {
+        editor_history.close_entry();
+        let (steps, is_undo) = match request {
+            EditorHistoryRequest::Undo(steps) => (steps, true),
+            EditorHistoryRequest::Redo(steps) => (steps, false)
+        };
+
+        for _ in 0..steps {
+            let entry_opt = match is_undo {
+                true => editor_history.undo_stack.pop(),
+                false => editor_history.redo_stack.pop()
+            };
+            let entry = match entry_opt {
+                Some(entry) => entry,
+                None => break
+            };
+
+            let actions: Vec<&EditorAction> = match is_undo {
+                true => entry.actions.iter().rev().collect(),
+                false => entry.actions.iter().collect()
+            };
+            for action in actions {
+                match action.clone() {
+                    EditorAction::Tile { layer, pos, before, after } => {
+                        set_map_tile(&mut commands, &map_data, &mut map_structures, &mut tilemap_query, layer, pos, if is_undo { before } else { after });
+                    },
+                    EditorAction::Hitbox { pos, before, after } => {
+                        set_map_hitbox(&mut commands, &map_data, &mut map_structures, pos, if is_undo { before } else { after });
+                    },
+                    EditorAction::Entity { kind, pos, before, after } => {
+                        set_map_entity(&mut commands, &mut asset_server, &mut map_data, &mut texture_atlas_layouts, &mut map_structures, kind, pos, if is_undo { before } else { after });
+                    },
+                    EditorAction::Info { pos, before, after } => {
+                        match if is_undo { before } else { after } {
+                            Some(info) => {map_structures.info.insert(pos, info);},
+                            None => {map_structures.info.remove(&pos);}
+                        }
+                    }
+                }
+            }
+
+            match is_undo {
+                true => editor_history.redo_stack.push(entry),
+                false => editor_history.undo_stack.push(entry)
+            }
+        }
+    }
+}
+
+pub fn tilemap_layer_editor(mut commands: Commands, mut contexts: EguiContexts, mut map_data: ResMut<MapData>, mut map_structures: ResMut<MapStructures>, room_data: Res<RoomData>, mut editor_history: ResMut<EditorHistory>, mut tilemap_query: Query<(&mut TilemapTransform, &mut Depth)>, mut new_layer_name: Local<String>, mut new_layer_overlay: Local<bool>){
+    let ctx = contexts.ctx_mut();
+    let max_y = map_data.max_y;
+
+    egui::Window::new("Tilemap Layers")
+    .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(5.0 ,-5.0))
+    .resizable(false)
+    .title_bar(false)
+    .show(ctx, |ui| {
+        ui.label("Layers");
+
+        let mut remove_layer: Option<usize> = None;
+        for (layer_index, layer) in map_structures.layers.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.radio(map_data.place_layer_index == layer_index, layer.name.as_str()).clicked() {
+                    map_data.place_layer_index = layer_index;
+                }
+                let order_response = ui.add(egui::DragValue::new(&mut layer.order).range(-MAX_LAYER_ORDER..=MAX_LAYER_ORDER));
+                let overlay_response = ui.checkbox(&mut layer.is_overlay, "overlay");
+                if order_response.changed() || overlay_response.changed() {
+                    if let Some(tilemap) = layer.tilemap {
+                        if let Ok((mut tilemap_transform, mut depth)) = tilemap_query.get_mut(tilemap) {
+                            let new_depth = layer.get_depth(max_y);
+                            tilemap_transform.z_index = new_depth as i32;
+                            depth.index = new_depth;
+                        }
+                    }
+                }
+                if ui.button("x").clicked() {
+                    remove_layer = Some(layer_index);
+                }
+            });
+        }
+
+        if let Some(layer_index) = remove_layer {
+            if map_structures.layers.len() > 1 {
+                // tile actions refer to layers by index, which shift after a removal
+                editor_history.clear();
+                let layer = map_structures.layers.remove(layer_index);
+                if let Some(tilemap) = layer.tilemap {
+                    commands.entity(tilemap).despawn_recursive();
+                }
+                // keep the same layer selected, unless it was the one removed
+                if layer_index < map_data.place_layer_index {
+                    map_data.place_layer_index -= 1;
+                } else if layer_index == map_data.place_layer_index {
+                    map_data.place_layer_index = layer_index.min(map_structures.layers.len() - 1);
+                }
+            }
+        }
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut *new_layer_name);
+            ui.checkbox(&mut *new_layer_overlay, "overlay");
+            if ui.button("Add Layer").clicked() && !new_layer_name.is_empty() {
+                let tile_count = (map_data.map_col * map_data.map_row) as usize;
+                let order = map_structures.layers.iter().filter(|layer| layer.is_overlay == *new_layer_overlay).map(|layer| layer.order + 1).max().unwrap_or(0);
+                let mut layer = MapLayer::new(&new_layer_name, order, *new_layer_overlay, vec![EMPTY_TILE; tile_count]);
+                let tilemap_entity = spawn_tilemap_layer(&mut commands, &map_data, &mut layer, true);
+                if let Some(scene) = room_data.current_scene {
+                    commands.entity(scene).add_child(tilemap_entity);
+                }
+                map_structures.layers.push(layer);
+                map_data.place_layer_index = map_structures.layers.len() - 1;
+                new_layer_name.clear();
+            }
+        });
+    });
+}
+
 fn set_clean_tilemap(commands: &mut Commands, create_scene_event: &mut EventWriter<CreateSceneEvent>, map_data: &mut ResMut<MapData>, map_structures: &mut ResMut<MapStructures>, room_data: &mut ResMut<RoomData>){
 
     create_scene(commands, create_scene_event, room_data, "tilemap editor", HashMap::from([
         (StateName::EditorState as u8, 1)
     ]), None);
 
-    map_structures.tilemap = None;
-    map_structures.tiles.clear();
+    map_structures.layers.clear();
     map_structures.sprite.clear();
     map_structures.animated_sprite.clear();
     map_structures.hitbox.clear();
+    map_data.place_layer_index = 0;
 
     let (_, max_y) = from_index_to_xy(map_data.map_col * map_data.map_row, map_data.tile_width as i32, map_data.tile_height as i32, map_data.map_col);
     map_data.max_y = max_y as f32;
 
+    let tile_count = (map_data.map_col * map_data.map_row) as usize;
+    map_structures.layers.push(MapLayer::new("floor", 0, false, vec![1; tile_count]));
+    map_structures.layers.push(MapLayer::new("decoration", 1, false, vec![EMPTY_TILE; tile_count]));
+    map_structures.layers.push(MapLayer::new("overlay", 0, true, vec![EMPTY_TILE; tile_count]));
+
+    for layer in map_structures.layers.iter_mut() {
+        let tilemap_entity = spawn_tilemap_layer(commands, map_data, layer, true);
+        commands.entity(room_data.current_scene.unwrap()).add_child(tilemap_entity);
+    }
+}
+
+pub fn spawn_tilemap_layer(commands: &mut Commands, map_data: &MapData, layer: &mut MapLayer, is_editor: bool) -> Entity {
+    let depth = layer.get_depth(map_data.max_y);
     let tilemap_entity = commands.spawn(
 (        SpatialBundle {
-            transform: Transform::from_xyz(0.0, 0.0, -map_data.max_y - 10.0),
+            transform: Transform::from_xyz(0.0, 0.0, depth),
             ..default()
         },
+        Depth {
+            index: depth
+        },
         MapStructure)
     ).id();
 
     if let Some(tile_material) = &map_data.tile_material {
         if let Some(tile_texture) = &map_data.tile_texture {
             let mut tilemap = StandardTilemapBundle {
-                name: TilemapName("tilemap".to_string()),
-                transform: TilemapTransform{translation: Vec2::new(map_data.tile_width * -0.5, map_data.tile_height * -0.5), ..default()},
+                name: TilemapName(layer.name.to_string()),
+                transform: TilemapTransform{translation: Vec2::new(map_data.tile_width * -0.5, map_data.tile_height * -0.5), z_index: depth as i32, ..default()},
                 tile_render_size: TileRenderSize(Vec2 { x: map_data.tile_width, y: map_data.tile_height }),
                 slot_size: TilemapSlotSize(Vec2 { x: map_data.tile_width, y: map_data.tile_height }),
                 ty: TilemapType::Square,
@@ -477,96 +892,92 @@ fn set_clean_tilemap(commands: &mut Commands, create_scene_event: &mut EventWrit
                 textures: tile_texture.clone_weak(),
                 ..Default::default()
             };
-        
-            tilemap.storage.fill_rect(
-                commands,
-                TileArea::new(IVec2::ZERO, UVec2 { x: map_data.map_col as u32, y: map_data.map_row as u32 }),
-                TileBuilder::new().with_layer(0, TileLayer::no_flip(1)),
-            );
-            for _ in 0..map_data.map_col * map_data.map_row {
-                map_structures.tiles.push(1);
+
+            for (counter, index) in layer.tiles.iter().enumerate() {
+                if index.eq(&EMPTY_TILE) || (index.eq(&0) && !is_editor) {
+                    continue;
+                }
+                let (x, y) = from_index_to_grid_xy(counter as i32, map_data.map_col);
+
+                tilemap.storage.set(
+                    commands,
+                    IVec2 { x, y },
+                    TileBuilder::new().with_layer(0, TileLayer::no_flip(*index)),
+                );
             }
             commands.entity(tilemap_entity).insert(tilemap);
         }
     }
 
-    map_structures.tilemap = Some(tilemap_entity);
-    
-    commands.entity(room_data.current_scene.unwrap()).add_child(tilemap_entity);
+    layer.tilemap = Some(tilemap_entity);
+    return tilemap_entity;
+}
+
+pub fn build_map_file(map_data: &MapData, map_structures: &MapStructures, sprite_query: &Query<&SpriteTag>, animated_sprite_query: &Query<&AnimatedTag>) -> MapFile {
+    let mut map_file = MapFile {
+        col: map_data.map_col,
+        row: map_data.map_row,
+        tilesheet: map_data.tile_atlas_name.to_string(),
+        info: map_structures.info.clone(),
+        ..default()
+    };
+
+    for layer in map_structures.layers.iter() {
+        map_file.layers.push(MapFileLayer { name: layer.name.to_string(), order: layer.order, overlay: layer.is_overlay, tiles: layer.tiles.clone() });
+    }
+
+    for (pos, entity) in map_structures.sprite.iter() {
+        if let Ok(t) = sprite_query.get(*entity) {
+            let (x, y) = from_index_to_grid_xy(*pos, map_data.map_col);
+            map_file.entities.push(MapEntity { kind: MapEntityKind::Static, x, y, index: t.index });
+        }
+    }
+    for (pos, entity) in map_structures.animated_sprite.iter() {
+        if let Ok(t) = animated_sprite_query.get(*entity) {
+            let (x, y) = from_index_to_grid_xy(*pos, map_data.map_col);
+            map_file.entities.push(MapEntity { kind: MapEntityKind::Animated, x, y, index: t.index });
+        }
+    }
+    for (pos, (is_hitbox, _)) in map_structures.hitbox.iter() {
+        if *is_hitbox {
+            map_file.hitbox.push(from_index_to_grid_xy(*pos, map_data.map_col));
+        }
+    }
+
+    return map_file;
 }
 
-pub fn load_tilemap_from_saver(commands: &mut Commands, mut asset_server: &mut Res<AssetServer>, map_saver: MapSaver, map_data: &mut ResMut<MapData>, room_data:  &mut ResMut<RoomData>, map_structures: &mut ResMut<MapStructures>, editor_state: &mut Res<State<EditorState>>, texture_atlas_layouts: &mut ResMut<Assets<TextureAtlasLayout>>){
+pub fn load_tilemap_from_map_file(commands: &mut Commands, mut asset_server: &mut Res<AssetServer>, map_file: MapFile, map_data: &mut ResMut<MapData>, room_data:  &mut ResMut<RoomData>, map_structures: &mut ResMut<MapStructures>, editor_state: &mut Res<State<EditorState>>, texture_atlas_layouts: &mut ResMut<Assets<TextureAtlasLayout>>){
     map_data.is_map_loaded = false;
 
-    map_structures.tilemap = None;
-    map_structures.tiles.clear();
+    map_structures.layers.clear();
     map_structures.sprite.clear();
     map_structures.animated_sprite.clear();
     map_structures.hitbox.clear();
+    map_data.place_layer_index = 0;
     
     let current_editor_state = editor_state.get();
     
-    map_data.tile_atlas_name = map_saver.tilesheet;
-    map_data.map_col = map_saver.col;
-    map_data.map_col_str = map_saver.col.to_string();
-    if !map_saver.info.is_empty() {
-        map_structures.info = serde_json::from_str(&map_saver.info).unwrap();
-    }
+    map_data.tile_atlas_name = map_file.tilesheet.to_string();
+    map_data.map_col = map_file.col;
+    map_data.map_col_str = map_file.col.to_string();
+    map_structures.info = map_file.info.clone();
 
-    map_data.map_row = map_saver.row;
-    map_data.map_row_str = map_saver.row.to_string();
+    map_data.map_row = map_file.row;
+    map_data.map_row_str = map_file.row.to_string();
 
     let (_, max_y) = from_index_to_xy(map_data.map_col * map_data.map_row, map_data.tile_width as i32, map_data.tile_height as i32, map_data.map_col);
     map_data.max_y = max_y as f32;
 
-    let tilemap_entity = commands.spawn(
-    (        SpatialBundle {
-                transform: Transform::from_xyz(0.0, 0.0, -map_data.max_y - 10.0),
-                ..default()
-            },
-            MapStructure)
-        ).id();
-
-    let mut counter: i32 = 0;
-    if let Some(tile_material) = &map_data.tile_material {
-        if let Some(tile_texture) = &map_data.tile_texture {
-            let mut tilemap = StandardTilemapBundle {
-                name: TilemapName("tilemap".to_string()),
-                transform: TilemapTransform{translation: Vec2::new(map_data.tile_width * -0.5, map_data.tile_height * -0.5), ..default()},
-                tile_render_size: TileRenderSize(Vec2 { x: map_data.tile_width, y: map_data.tile_height }),
-                slot_size: TilemapSlotSize(Vec2 { x: map_data.tile_width, y: map_data.tile_height }),
-                ty: TilemapType::Square,
-                storage: TilemapStorage::new(16, tilemap_entity),
-                material: tile_material.clone_weak(),
-                textures: tile_texture.clone_weak(),
-                ..Default::default()
-            };
-        
-            if let Ok(groups) = group_numbers(map_saver.tilemap, 1) {
-                for group in groups.iter(){
-                    let index = group[0] as i32;
-                    if index.eq(&0) {
-                        if current_editor_state.ne(&EditorState::Open) {
-                            continue;
-                        }
-                    }
-                    let (x, y) = from_index_to_grid_xy(counter, map_data.map_col);
-
-                    tilemap.storage.set(
-                        commands,
-                        IVec2 { x, y },
-                        TileBuilder::new().with_layer(0, TileLayer::no_flip(index)),
-                    );
-                    map_structures.tiles.push(index);
-                    counter += 1;
-                }
-            }
-            commands.entity(tilemap_entity).insert(tilemap);
-        }
+    let mut file_layers = map_file.layers.clone();
+    file_layers.sort_by_key(|layer| (layer.overlay, layer.order));
+    let mut tilemap_entities: Vec<Entity> = Vec::new();
+    for file_layer in file_layers.into_iter() {
+        let mut layer = MapLayer::new(&file_layer.name, file_layer.order, file_layer.overlay, file_layer.tiles);
+        tilemap_entities.push(spawn_tilemap_layer(commands, map_data, &mut layer, current_editor_state.eq(&EditorState::Open)));
+        map_structures.layers.push(layer);
     }
 
-    map_structures.tilemap = Some(tilemap_entity);
-
     let sprite_entity = commands.spawn(
 (        SpatialBundle {
             transform: Transform::from_xyz(0.0, 0.0, 0.0),
@@ -575,114 +986,108 @@ pub fn load_tilemap_from_saver(commands: &mut Commands, mut asset_server: &mut R
         MapStructure)
     ).id();
 
-
-    if let Ok(groups) = group_numbers(map_saver.sprites, 2) {
-        for group in groups.iter(){
-            let pos = group[0] as i32;
-            let index = group[1] as i32;
-            let (x, y) = from_index_to_xy(pos, map_data.tile_width as i32, map_data.tile_height as i32, map_data.map_col);
-
-            if index.eq(&0) {
-                map_data.spawn_pos = pos;
-                if current_editor_state.ne(&EditorState::Open) {
-                    continue;
-                }
-            }
-
-            let image_handle = load_static_sprite(&mut asset_server, index.to_string());
-            let sprite =
-            commands.spawn((
-                SpriteBundle {
-                    transform: Transform::from_xyz(x as f32, y as f32, 0.0),
-                    texture: image_handle,
-                    ..default()
-                },
-                SpriteTag {
-                    index: index as i8,
-                    pos: pos
-                },
-                Depth {
-                    index: (max_y - y) as f32 + map_data.tile_height * 0.4
+    for map_entity in map_file.entities.iter() {
+        let pos = map_file.to_pos(map_entity.x, map_entity.y);
+        let index = map_entity.index;
+        let (x, y) = from_index_to_xy(pos, map_data.tile_width as i32, map_data.tile_height as i32, map_data.map_col);
+
+        match map_entity.kind {
+            MapEntityKind::Static => {
+                if index.eq(&0) {
+                    map_data.spawn_pos = pos;
+                    if current_editor_state.ne(&EditorState::Open) {
+                        continue;
+                    }
                 }
-            )).id();
 
-            let interactive = get_interactive_trigger("static", index);
-            if interactive.len() > 0 {
-                commands.entity(sprite).insert(InteractiveTrigger {trigger: interactive});
-            }
-            map_structures.sprite.insert(pos, sprite);
-            commands.entity(sprite_entity).add_child(sprite);
-        }
-    }
-
-    if let Ok(groups) = group_numbers(map_saver.animated_sprites, 2) {
-        for group in groups.iter(){
-            let pos = group[0] as i32;
-            let index = group[1] as i32;
-
-            if let Some((image_handle, layout_handle, image_count)) = load_animated_sprite(&mut asset_server, map_data, texture_atlas_layouts) {
-                let (x, y) = from_index_to_xy(pos, map_data.tile_width as i32, map_data.tile_height as i32, map_data.map_col);
+                let image_handle = load_static_sprite(&mut asset_server, index.to_string());
                 let sprite =
                 commands.spawn((
                     SpriteBundle {
                         transform: Transform::from_xyz(x as f32, y as f32, 0.0),
-                        texture: image_handle.clone_weak(),
+                        texture: image_handle,
                         ..default()
                     },
-                    TextureAtlas {
-                        layout: layout_handle.clone_weak(),
-                        index: 0
-                    },
-                    AnimatedTag {
-                        index: index as i8,
-                        pos: pos,
-                        current: 0,
-                        last: image_count,
-                        increment: 1
+                    SpriteTag {
+                        index: index,
+                        pos: pos
                     },
                     Depth {
                         index: (max_y - y) as f32 + map_data.tile_height * 0.4
                     }
                 )).id();
 
-                let interactive = get_interactive_trigger("animated", index);
+                let interactive = get_interactive_trigger("static", index);
                 if interactive.len() > 0 {
                     commands.entity(sprite).insert(InteractiveTrigger {trigger: interactive});
                 }
-                map_structures.animated_sprite.insert(pos, sprite);
+                map_structures.sprite.insert(pos, sprite);
                 commands.entity(sprite_entity).add_child(sprite);
-            }
-        }
-    }
-
-    if let Ok(groups) = group_numbers(map_saver.hitbox, 1) {
-        for group in groups.iter(){
-            let pos = group[0] as i32;
-            let mut hitbox: Option<Entity> = None;
-            if current_editor_state.eq(&EditorState::Open) {
-                if let Some((image_handle, atlas_layout_handle, _)) = map_data.atlas_hashmap.get("tile") {
-                    let (x, y) = from_index_to_xy(pos, map_data.tile_width as i32, map_data.tile_height as i32, map_data.map_col);
-                    hitbox = 
-                    Some(commands.spawn((
+            },
+            MapEntityKind::Animated => {
+                if let Some((image_handle, layout_handle, image_count)) = load_animated_sprite(&mut asset_server, map_data, texture_atlas_layouts, &index.to_string()) {
+                    let sprite =
+                    commands.spawn((
                         SpriteBundle {
-                            transform: Transform::from_xyz(x as f32, y as f32, 5.0),
+                            transform: Transform::from_xyz(x as f32, y as f32, 0.0),
                             texture: image_handle.clone_weak(),
                             ..default()
                         },
                         TextureAtlas {
-                            layout: atlas_layout_handle.clone_weak(),
-                            index: 0,
+                            layout: layout_handle.clone_weak(),
+                            index: 0
+                        },
+                        AnimatedTag {
+                            index: index,
+                            pos: pos,
+                            current: 0,
+                            last: image_count,
+                            increment: 1
+                        },
+                        Depth {
+                            index: (max_y - y) as f32 + map_data.tile_height * 0.4
                         }
-                    )).id());
-                    commands.entity(sprite_entity).add_child(hitbox.unwrap());
+                    )).id();
+
+                    let interactive = get_interactive_trigger("animated", index);
+                    if interactive.len() > 0 {
+                        commands.entity(sprite).insert(InteractiveTrigger {trigger: interactive});
+                    }
+                    map_structures.animated_sprite.insert(pos, sprite);
+                    commands.entity(sprite_entity).add_child(sprite);
                 }
             }
-            map_structures.hitbox.insert(pos, (true, hitbox));
         }
     }
 
+    for (grid_x, grid_y) in map_file.hitbox.iter() {
+        let pos = map_file.to_pos(*grid_x, *grid_y);
+        let mut hitbox: Option<Entity> = None;
+        if current_editor_state.eq(&EditorState::Open) {
+            if let Some((image_handle, atlas_layout_handle, _)) = map_data.atlas_hashmap.get("tile") {
+                let (x, y) = from_index_to_xy(pos, map_data.tile_width as i32, map_data.tile_height as i32, map_data.map_col);
+                hitbox = 
+                Some(commands.spawn((
+                    SpriteBundle {
+                        transform: Transform::from_xyz(x as f32, y as f32, 5.0),
+                        texture: image_handle.clone_weak(),
+                        ..default()
+                    },
+                    TextureAtlas {
+                        layout: atlas_layout_handle.clone_weak(),
+                        index: 0,
+                    }
+                )).id());
+                commands.entity(sprite_entity).add_child(hitbox.unwrap());
+            }
+        }
+        map_structures.hitbox.insert(pos, (true, hitbox));
+    }
+
     if let Some(scene) = room_data.current_scene {
-        commands.entity(scene).add_child(tilemap_entity);
+        for tilemap_entity in tilemap_entities.iter() {
+            commands.entity(scene).add_child(*tilemap_entity);
+        }
         commands.entity(scene).add_child(sprite_entity);
     }
     map_data.is_map_loaded = true;
@@ -690,22 +1095,70 @@ pub fn load_tilemap_from_saver(commands: &mut Commands, mut asset_server: &mut R
 
 pub fn load_tilemap_event_listener(events_query: Query<(Entity, &LoadTilemapTask)>, mut commands: Commands, mut asset_server: Res<AssetServer>, mut map_data: ResMut<MapData>, mut room_data: ResMut<RoomData>, mut editor_state: Res<State<EditorState>>, mut tile_storage: ResMut<MapStructures>, mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>, mut tilemap_loaded_event: EventWriter<TilemapLoadedEvent>, map_structures_query: Query<(Entity, &MapStructure)>, mut streaming_state: ResMut<NextState<StreamingState>>){
     for (entity, load_tilemap_task) in events_query.iter() {
+        // the failure itself is reported through DownloadFailedEvent, keep whatever map is on screen
+        if let Some(e) = &load_tilemap_task.error {
+            println!("load map {} failed: {}", load_tilemap_task.map_name, e);
+            commands.entity(entity).despawn();
+            map_data.is_map_loaded = true;
+            continue;
+        }
         if load_tilemap_task.map_str.is_empty() {
             commands.entity(entity).despawn();
             map_data.is_map_loaded = true;
             continue;
         }
+
+        let map_file = match MapFile::parse(&load_tilemap_task.map_str) {
+            Ok(map_file) => map_file,
+            Err(e) => {
+                println!("load map {} failed: {}", load_tilemap_task.map_name, e);
+                commands.entity(entity).despawn();
+                map_data.is_map_loaded = true;
+                continue;
+            }
+        };
+
         for (entity, _) in map_structures_query.iter(){
             commands.entity(entity).despawn_recursive();
         }
 
         map_data.map_name = load_tilemap_task.map_name.to_string();
-        if let Ok(map_saver) = serde_json::from_str::<MapSaver>(&load_tilemap_task.map_str) {
-            streaming_state.set(StreamingState::Close);
-            load_tilemap_from_saver(&mut commands, &mut asset_server, map_saver, &mut map_data, &mut room_data, &mut tile_storage, &mut editor_state, &mut texture_atlas_layouts);
-            tilemap_loaded_event.send(TilemapLoadedEvent());
-            commands.entity(entity).despawn();
+        streaming_state.set(StreamingState::Close);
+        load_tilemap_from_map_file(&mut commands, &mut asset_server, map_file, &mut map_data, &mut room_data, &mut tile_storage, &mut editor_state, &mut texture_atlas_layouts);
+        tilemap_loaded_event.send(TilemapLoadedEvent());
+        commands.entity(entity).despawn();
+    }
+}
+
+pub fn upload_tilemap_event_listener(events_query: Query<(Entity, &UploadTilemapTask)>, mut commands: Commands, mut editor_storage: ResMut<EditorStorage>){
+    for (entity, upload_tilemap_task) in events_query.iter() {
+        if upload_tilemap_task.is_success {
+            editor_storage.status = format!("Published map/{}.txt", upload_tilemap_task.map_name);
+            if !editor_storage.map_names.contains(&upload_tilemap_task.map_name) {
+                editor_storage.map_names.push(upload_tilemap_task.map_name.to_string());
+                editor_storage.map_names.sort();
+            }
+        } else {
+            editor_storage.status = format!("Publish map/{}.txt failed", upload_tilemap_task.map_name);
         }
+        commands.entity(entity).despawn();
+    }
+}
+
+pub fn list_tilemap_event_listener(events_query: Query<(Entity, &ListTilemapTask)>, mut commands: Commands, mut editor_storage: ResMut<EditorStorage>){
+    for (entity, list_tilemap_task) in events_query.iter() {
+        match &list_tilemap_task.map_names {
+            Ok(map_names) => {
+                editor_storage.map_names = map_names.clone();
+                editor_storage.is_list_loaded = true;
+            },
+            Err(e) => {
+                println!("list maps failed: {}", e);
+                editor_storage.status = format!("Could not list maps: {}", e);
+            }
+        }
+        editor_storage.is_listing = false;
+        commands.entity(entity).despawn();
     }
 }
 
@@ -17,12 +17,16 @@ impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app
         .init_resource::<GameResources>()
+        .init_resource::<LoadingScreen>()
         .init_state::<MultiplayerRoomState>()
         .init_state::<TriggerButtonState>()
         .add_systems(Startup, load_game_assets.run_if(in_state(EditorState::Close)))
         .add_systems(Update, update_depth)
         .add_systems( Update, display_room_ui.run_if(in_state(MultiplayerRoomState::Consumed)))
+        .add_systems(Update, display_reconnecting_indicator.run_if(in_state(MultiplayerRoomState::Consumed)))
+        .add_systems(Update, display_speech_bubbles.run_if(in_state(MultiplayerRoomState::Consumed)))
         .add_systems(OnEnter(MultiplayerRoomState::True), setup_room)
+        .add_systems(Update, display_loading_screen)
         .add_systems(Update, display_trigger_button.run_if(in_state(TriggerButtonState::Display)))
         .add_systems(OnEnter(TriggerButtonState::Hidden), hide_trigger_button)
         .add_systems(Update, trigger_button_onpress.run_if(in_state(TriggerButtonState::Display)))
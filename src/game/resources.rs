@@ -1,11 +1,95 @@
+use std::{collections::HashMap, fs::{self, OpenOptions}, io::Write, path::PathBuf};
+
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::networking::rtc::protocol::ChatKind;
+
+// older lines stay in the room's log file, only these are kept for scrolling back
+pub const MAX_CHAT_MESSAGES: usize = 200;
+pub const PROXIMITY_CHAT_TILES: u8 = 5;
+pub const SPEECH_BUBBLE_SECONDS: f64 = 5.0;
+// roughly the top of a character's head
+pub const SPEECH_BUBBLE_OFFSET_Y: f32 = 80.0;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ChatMode {
+    Room,
+    // only participants within PROXIMITY_CHAT_TILES get the message
+    Nearby
+}
+
+pub struct SpeechBubble {
+    pub text: String,
+    pub expires_at: f64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChatMessage {
+    // empty for system messages
+    pub sender: String,
+    pub display_name: String,
+    // unix seconds
+    pub timestamp: i64,
+    pub kind: ChatKind,
+    pub text: String,
+    // whom a whisper from this user went to
+    #[serde(default)]
+    pub whisper_to: String,
+    #[serde(default)]
+    pub is_nearby: bool
+}
 
+impl ChatMessage {
+    pub fn new(sender: &str, display_name: &str, kind: ChatKind, text: &str) -> ChatMessage {
+        return ChatMessage {
+            sender: sender.to_string(),
+            display_name: display_name.to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+            kind,
+            text: text.to_string(),
+            whisper_to: String::new(),
+            is_nearby: false
+        };
+    }
+
+    pub fn system(text: &str) -> ChatMessage {
+        return ChatMessage::new("", "", ChatKind::System, text);
+    }
+
+    pub fn get_display_text(&self, this_user_uuid: &str) -> String {
+        let time = match chrono::DateTime::from_timestamp(self.timestamp, 0) {
+            Some(date_time) => date_time.with_timezone(&chrono::Local).format("%H:%M").to_string(),
+            None => String::from("--:--")
+        };
+        let mut scope = "";
+        if self.is_nearby {
+            scope = "(nearby) ";
+        }
+        match self.kind {
+            ChatKind::Message => return format!("{} {}{}: {}", time, scope, self.display_name, self.text),
+            ChatKind::Emote => return format!("{} {}* {} {}", time, scope, self.display_name, self.text),
+            ChatKind::Whisper => {
+                if self.sender.eq(this_user_uuid) {
+                    return format!("{} to {}: {}", time, self.whisper_to, self.text);
+                }
+                return format!("{} {} whispers: {}", time, self.display_name, self.text);
+            },
+            ChatKind::System => return format!("{} {}", time, self.text)
+        }
+    }
+}
 
 #[derive(Resource)]
 pub struct GameResources {
     pub right_bottom_texts: Vec<String>,
     pub chatbar: String,
-    pub chat_messages: Vec<String>
+    pub chat_messages: Vec<ChatMessage>,
+    // one json line per message, None when the room is not worth keeping (offline)
+    pub chat_log: Option<PathBuf>,
+    pub chat_mode: ChatMode,
+    // the latest message of each participant, by uuid
+    pub speech_bubbles: HashMap<String, SpeechBubble>
 }
 
 impl Default for GameResources {
@@ -13,7 +97,114 @@ impl Default for GameResources {
         GameResources {
             right_bottom_texts: Vec::new(),
             chatbar: String::new(),
-            chat_messages: Vec::new()
+            chat_messages: Vec::new(),
+            chat_log: None,
+            chat_mode: ChatMode::Room,
+            speech_bubbles: HashMap::new()
+        }
+    }
+}
+
+impl GameResources {
+    pub fn get_chat_log_path(room_id: &str) -> Option<PathBuf> {
+        // room ids typed by hand end up in a file name
+        let file_name: String = room_id.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+        if file_name.is_empty() {
+            return None;
+        }
+        return dirs::data_dir().map(|data_dir| data_dir.join("shalloville").join("chat").join(format!("{}.jsonl", file_name)));
+    }
+
+    // starts the history over, with the tail of the log when there is one
+    pub fn open_chat_log(&mut self, chat_log: Option<PathBuf>) {
+        self.chat_messages.clear();
+        self.speech_bubbles.clear();
+        self.chat_log = chat_log;
+
+        if let Some(path) = &self.chat_log {
+            if let Ok(log_str) = fs::read_to_string(path) {
+                let lines: Vec<&str> = log_str.lines().collect();
+                let start = lines.len().saturating_sub(MAX_CHAT_MESSAGES);
+                for line in lines[start..].iter() {
+                    if let Ok(message) = serde_json::from_str::<ChatMessage>(line) {
+                        self.chat_messages.push(message);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn add_chat_message(&mut self, message: ChatMessage) {
+        if let Some(path) = &self.chat_log {
+            if let Some(parent) = path.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            match (OpenOptions::new().create(true).append(true).open(path), serde_json::to_string(&message)) {
+                (Ok(mut file), Ok(message_str)) => {
+                    if let Err(e) = writeln!(file, "{}", message_str) {
+                        println!("failed to write chat log: {}", e);
+                    }
+                },
+                (Err(e), _) => println!("failed to open chat log {}: {}", path.display(), e),
+                (_, Err(e)) => println!("failed to write chat log: {}", e)
+            }
+        }
+
+        self.chat_messages.push(message);
+        if self.chat_messages.len() > MAX_CHAT_MESSAGES {
+            self.chat_messages.remove(0);
         }
     }
-}
\ No newline at end of file
+
+    pub fn add_system_message(&mut self, text: &str) {
+        self.add_chat_message(ChatMessage::system(text));
+    }
+
+    pub fn show_speech_bubble(&mut self, message: &ChatMessage, now: f64) {
+        let text = match message.kind {
+            ChatKind::Message => message.text.to_string(),
+            ChatKind::Emote => format!("*{}*", message.text),
+            // whispers are private and system lines have nobody to stand above
+            _ => return
+        };
+        self.speech_bubbles.insert(message.sender.to_string(), SpeechBubble {
+            text,
+            expires_at: now + SPEECH_BUBBLE_SECONDS
+        });
+    }
+}
+
+pub const LOADING_TIMEOUT_SECONDS: f32 = 20.0;
+// avatar downloads are only queued once the map is in, so wait a moment before calling it done
+pub const LOADING_SETTLE_SECONDS: f32 = 0.5;
+
+#[derive(Resource)]
+pub struct LoadingScreen {
+    pub is_active: bool,
+    pub scene_uuid: String,
+    pub total: usize,
+    pub elapsed: f32,
+    pub idle: f32
+}
+
+impl Default for LoadingScreen {
+    fn default() -> LoadingScreen {
+        LoadingScreen {
+            is_active: false,
+            scene_uuid: String::new(),
+            total: 0,
+            elapsed: 0.0,
+            idle: 0.0
+        }
+    }
+}
+
+impl LoadingScreen {
+    pub fn start(&mut self, scene_uuid: &str) {
+        self.is_active = true;
+        self.scene_uuid = scene_uuid.to_string();
+        self.total = 0;
+        self.elapsed = 0.0;
+        self.idle = 0.0;
+    }
+}
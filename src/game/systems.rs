@@ -2,11 +2,10 @@ use std::collections::HashMap;
 
 use bevy::prelude::*;
 use bevy_egui::{egui::{self, Color32}, EguiContexts};
-use livekit::DataPacketKind;
 
-use crate::{character::{resources::CharacterAnimation, systems::push_character}, editor::{resources::{Scene, StateName}, systems::create_scene, CreateSceneEvent}, main_menu::resources::RoomData, map_structures::{components::{InteractiveEvent, InteractiveType}, resources::MapData}, networking::{rtc::{resources::RTCResource, systems::{create_room, leave_room}}, s3::{resources::S3Data, systems::load_tilemap_from_s3}}, utils::{from_grid_xy_to_index, from_xy_to_grid}, wardrobe::{resources::{OnWardrobeConfirmed, WardrobeResources}, WardrobeState}, FocusState};
+use crate::{camera::components::CameraTag, config::resources::AppConfig, character::{resources::CharacterAnimation, systems::push_character}, editor::{resources::{Scene, StateName}, systems::create_scene, CreateSceneEvent}, main_menu::resources::{RoomData, RoomHistory, RoomRole, SavedRoom}, map_structures::{components::{InteractiveEvent, InteractiveType}, resources::{get_ui_depth, MapData}}, networking::{rtc::{access::{InviteCode, INVITE_TTL_SECONDS}, resources::{RTCResource, MAX_RECONNECT_ATTEMPTS}, systems::{create_room, get_display_name, leave_room, send_moderation}, protocol::{ChatKind, ModerationAction, RoomMessage}, ModerationEvent}, s3::{components::{ComputeTask, LoadTilemapTask}, resources::S3Data, systems::load_tilemap_from_s3}}, nokhwa::resources::NokhwaCamera, utils::{from_grid_xy_to_index, from_xy_to_grid}, wardrobe::{resources::{OnWardrobeConfirmed, WardrobeResources}, WardrobeState}, FocusState};
 
-use super::{components::{Depth, TriggerButton}, GameResources, MultiplayerRoomState};
+use super::{components::{Depth, TriggerButton}, resources::{ChatMessage, ChatMode, LoadingScreen, LOADING_SETTLE_SECONDS, LOADING_TIMEOUT_SECONDS, PROXIMITY_CHAT_TILES, SPEECH_BUBBLE_OFFSET_Y}, GameResources, MultiplayerRoomState};
 
 pub fn load_game_assets(mut commands: Commands, asset_server: Res<AssetServer>){
     let z_button_handle: Handle<Image> = asset_server.load("icon/z_button.png");
@@ -20,7 +19,7 @@ pub fn load_game_assets(mut commands: Commands, asset_server: Res<AssetServer>){
     ));
 }
 
-pub fn display_room_ui(mut commands: Commands, mut game_resources: ResMut<GameResources>, mut room_data: ResMut<RoomData>, mut create_scene_event: EventWriter<CreateSceneEvent>, mut contexts: EguiContexts, mut wardrobe_resources: ResMut<WardrobeResources>, mut rtc_resource: ResMut<RTCResource>, mut focus_state: ResMut<NextState<FocusState>>, mut wardrobe_state: ResMut<NextState<WardrobeState>>, mut is_wardrobe_opened: Local<bool>, mut is_change_map_opened: Local<bool>){
+pub fn display_room_ui(mut commands: Commands, mut game_resources: ResMut<GameResources>, mut room_data: ResMut<RoomData>, mut create_scene_event: EventWriter<CreateSceneEvent>, mut contexts: EguiContexts, mut wardrobe_resources: ResMut<WardrobeResources>, mut rtc_resource: ResMut<RTCResource>, mut focus_state: ResMut<NextState<FocusState>>, mut wardrobe_state: ResMut<NextState<WardrobeState>>, mut is_wardrobe_opened: Local<bool>, mut is_change_map_opened: Local<bool>, config: Res<AppConfig>, mut moderation_event: EventWriter<ModerationEvent>, map_data: Res<MapData>, time: Res<Time>){
 
     let ctx: &mut egui::Context = contexts.ctx_mut();
 
@@ -71,7 +70,86 @@ pub fn display_room_ui(mut commands: Commands, mut game_resources: ResMut<GameRe
                 }
             });
 
-            if ui.button("invite")
+            egui::menu::menu_button(ui, "people", |ui| {
+                let this_user_uuid = room_data.this_user_uuid.to_string();
+                let mut actions: Vec<(ModerationAction, String)> = Vec::new();
+
+                if room_data.roles.can_moderate(&this_user_uuid) {
+                    let (lock_text, lock_action) = match room_data.roles.is_locked {
+                        true => ("unlock room", ModerationAction::Unlock),
+                        false => ("lock room", ModerationAction::Lock)
+                    };
+                    if ui.button(lock_text).clicked() {
+                        actions.push((lock_action, String::new()));
+                    }
+                    ui.separator();
+                }
+
+                let mut uuids: Vec<String> = room_data.room_users.keys().map(|uuid| uuid.to_string()).collect();
+                uuids.sort();
+                for uuid in uuids.iter() {
+                    ui.horizontal(|ui| {
+                        let mut label = get_display_name(&room_data, uuid);
+                        if uuid.eq(&this_user_uuid) {
+                            label = format!("{} (you)", label);
+                        }
+                        match room_data.roles.get_role(uuid) {
+                            RoomRole::Host => label = format!("{} - host", label),
+                            RoomRole::Moderator => label = format!("{} - moderator", label),
+                            RoomRole::Participant => {}
+                        }
+                        let is_muted = room_data.roles.muted.contains(uuid);
+                        if is_muted {
+                            label = format!("{} - muted", label);
+                        }
+                        ui.label(label);
+
+                        if room_data.roles.is_allowed(&this_user_uuid, ModerationAction::Promote, uuid) {
+                            if room_data.roles.get_role(uuid).eq(&RoomRole::Moderator) {
+                                if ui.button("demote").clicked() {
+                                    actions.push((ModerationAction::Demote, uuid.to_string()));
+                                }
+                            } else if ui.button("promote").clicked() {
+                                actions.push((ModerationAction::Promote, uuid.to_string()));
+                            }
+                        }
+                        if room_data.roles.is_allowed(&this_user_uuid, ModerationAction::Kick, uuid) {
+                            if is_muted {
+                                if ui.button("unmute").clicked() {
+                                    actions.push((ModerationAction::Unmute, uuid.to_string()));
+                                }
+                            } else if ui.button("mute").clicked() {
+                                actions.push((ModerationAction::Mute, uuid.to_string()));
+                            }
+                            if ui.button("stop camera").clicked() {
+                                actions.push((ModerationAction::StopCamera, uuid.to_string()));
+                            }
+                            if ui.button("kick").clicked() {
+                                actions.push((ModerationAction::Kick, uuid.to_string()));
+                            }
+                        }
+                    });
+                }
+
+                for (action, target) in actions {
+                    send_moderation(&mut rtc_resource, &room_data, &mut moderation_event, action, target);
+                }
+            });
+
+            if config.offline {
+                ui.add_enabled(false, egui::Button::new("invite"))
+                .on_disabled_hover_text("Offline mode, nobody else can join");
+            } else if let Some(signing_key) = &room_data.invite_signing_key {
+                // private rooms hand out a signed code instead of the bare id
+                if ui.button("invite")
+                .on_hover_text("Copy an invite code, valid for 24 hours")
+                .clicked() {
+                    let invite_code = InviteCode::create(signing_key, &room_data.room_id, INVITE_TTL_SECONDS);
+                    ui.output_mut(|output| {
+                        output.copied_text = invite_code;
+                    });
+                }
+            } else if ui.button("invite")
                 .on_hover_text(room_data.room_id.to_string())
                 .clicked() {
                 ui.output_mut(|output| {
@@ -100,30 +178,39 @@ pub fn display_room_ui(mut commands: Commands, mut game_resources: ResMut<GameRe
     .show(ctx, |ui| {
         ui.vertical(|ui| {
 
-            for message in game_resources.chat_messages.iter() {
-                ui.label(message);
+            egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for message in game_resources.chat_messages.iter() {
+                    let text = egui::RichText::new(message.get_display_text(&room_data.this_user_uuid));
+                    match message.kind {
+                        ChatKind::System => ui.label(text.color(Color32::GRAY)),
+                        ChatKind::Whisper => ui.label(text.color(Color32::LIGHT_BLUE)),
+                        _ => ui.label(text)
+                    };
+                }
+            });
+
+            if room_data.roles.muted.contains(&room_data.this_user_uuid) {
+                ui.label("You are muted");
+                return;
             }
 
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut game_resources.chat_mode, ChatMode::Room, "room");
+                ui.selectable_value(&mut game_resources.chat_mode, ChatMode::Nearby, "nearby")
+                .on_hover_text(format!("Only people within {} tiles see it", PROXIMITY_CHAT_TILES));
+            });
             let chatbar_response = ui.text_edit_singleline(&mut game_resources.chatbar);
             if chatbar_response.gained_focus() {
                 focus_state.set(FocusState::UI);
             }
             if chatbar_response.lost_focus() {
                 focus_state.set(FocusState::Game);
-                if !game_resources.chatbar.is_empty() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
-                    let mut username = String::from("anonymous");
-                    if !room_data.this_user_name.is_empty() {
-                        username = room_data.this_user_name.to_string();
-                    }
-                    let message = game_resources.chatbar.to_string();
-                    let fmt_message = format!("{}: {}", username, message);
-
-                    game_resources.chat_messages.push(fmt_message.to_string());
-                    rtc_resource.send_message(room_data.room_users.len(), &room_data.room_id, &room_data.this_user_uuid, "chat", &fmt_message, DataPacketKind::Reliable);
-                    
-                    if game_resources.chat_messages.len() > 15 {
-                        game_resources.chat_messages.remove(0);
-                    }
+                if !game_resources.chatbar.trim().is_empty() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    let input = game_resources.chatbar.trim().to_string();
+                    submit_chat(&mut game_resources, &mut rtc_resource, &room_data, &map_data, &input, time.elapsed_seconds_f64());
                     game_resources.chatbar.clear();
                 }
             }
@@ -131,26 +218,249 @@ pub fn display_room_ui(mut commands: Commands, mut game_resources: ResMut<GameRe
     });
 }
 
-pub fn setup_room(mut commands: Commands, asset_server: Res<AssetServer>, scene: Res<Scene>, mut map_data: ResMut<MapData>, mut room_data: ResMut<RoomData>, mut s3_data: ResMut<S3Data>, mut character_animation: Res<CharacterAnimation>, mut multiplayer_room_state: ResMut<NextState<MultiplayerRoomState>>){
+// plain text goes to everyone, or to those nearby in that mode; /me, /w <name> and /clear are handled here
+fn submit_chat(game_resources: &mut ResMut<GameResources>, rtc_resource: &mut ResMut<RTCResource>, room_data: &RoomData, map_data: &MapData, input: &str, now: f64) {
+    let this_user_uuid = room_data.this_user_uuid.to_string();
+    let display_name = get_display_name(room_data, &this_user_uuid);
+
+    let (command, rest) = match input.split_once(' ') {
+        Some((command, rest)) => (command, rest.trim()),
+        None => (input, "")
+    };
+
+    match command {
+        "/clear" => {
+            // only the scrollback, the log file keeps everything
+            game_resources.chat_messages.clear();
+        },
+        "/me" => {
+            if rest.is_empty() {
+                game_resources.add_system_message("usage: /me <action>");
+                return;
+            }
+            send_chat(game_resources, rtc_resource, room_data, map_data, ChatMessage::new(&this_user_uuid, &display_name, ChatKind::Emote, rest), now);
+        },
+        "/w" => {
+            let (target_name, text) = match rest.split_once(' ') {
+                Some((target_name, text)) => (target_name, text.trim()),
+                None => (rest, "")
+            };
+            if target_name.is_empty() || text.is_empty() {
+                game_resources.add_system_message("usage: /w <name> <message>");
+                return;
+            }
+            let target = room_data.room_users.keys()
+            .find(|uuid| uuid.ne(&&this_user_uuid) && get_display_name(room_data, uuid).eq_ignore_ascii_case(target_name))
+            .map(|uuid| uuid.to_string());
+            match target {
+                Some(target) => {
+                    let mut message = ChatMessage::new(&this_user_uuid, &display_name, ChatKind::Whisper, text);
+                    message.whisper_to = get_display_name(room_data, &target);
+                    game_resources.add_chat_message(message);
+                    rtc_resource.send_message_to(&this_user_uuid, RoomMessage::Chat {
+                        kind: ChatKind::Whisper,
+                        text: text.to_string(),
+                        range_tiles: 0
+                    }, Vec::from([target]));
+                },
+                None => game_resources.add_system_message(&format!("nobody called {} is here", target_name))
+            }
+        },
+        _ if command.starts_with('/') => {
+            game_resources.add_system_message(&format!("unknown command {}, try /me, /w or /clear", command));
+        },
+        _ => {
+            send_chat(game_resources, rtc_resource, room_data, map_data, ChatMessage::new(&this_user_uuid, &display_name, ChatKind::Message, input), now);
+        }
+    }
+}
+
+fn send_chat(game_resources: &mut ResMut<GameResources>, rtc_resource: &mut ResMut<RTCResource>, room_data: &RoomData, map_data: &MapData, mut message: ChatMessage, now: f64) {
+    let mut range_tiles = 0;
+    if game_resources.chat_mode.eq(&ChatMode::Nearby) {
+        range_tiles = PROXIMITY_CHAT_TILES;
+        message.is_nearby = true;
+    }
+    let chat = RoomMessage::Chat {
+        kind: message.kind,
+        text: message.text.to_string(),
+        range_tiles
+    };
+
+    game_resources.show_speech_bubble(&message, now);
+    game_resources.add_chat_message(message);
+
+    if range_tiles > 0 {
+        let destinations = room_data.get_nearby_users(map_data, range_tiles);
+        // an empty destination list would reach the whole room
+        if destinations.is_empty() {
+            game_resources.add_system_message("Nobody is close enough to hear you");
+            return;
+        }
+        rtc_resource.send_message_to(&room_data.this_user_uuid, chat, destinations);
+    } else {
+        rtc_resource.send_message(room_data.room_users.len(), &room_data.this_user_uuid, chat);
+    }
+}
+
+pub fn display_speech_bubbles(mut contexts: EguiContexts, mut game_resources: ResMut<GameResources>, room_data: Res<RoomData>, camera_query: Query<(&Camera, &GlobalTransform), With<CameraTag>>, transform_query: Query<&GlobalTransform>, time: Res<Time>){
+    let now = time.elapsed_seconds_f64();
+    game_resources.speech_bubbles.retain(|_, speech_bubble| speech_bubble.expires_at > now);
+    if game_resources.speech_bubbles.is_empty() {
+        return;
+    }
+
+    let (camera, camera_transform) = match camera_query.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return
+    };
+
+    let ctx: &mut egui::Context = contexts.ctx_mut();
+    for (uuid, speech_bubble) in game_resources.speech_bubbles.iter() {
+        let character_entity = match room_data.room_users.get(uuid).and_then(|user_data| user_data.character) {
+            Some(character_entity) => character_entity,
+            None => continue
+        };
+        let character_transform = match transform_query.get(character_entity) {
+            Ok(character_transform) => character_transform,
+            Err(_) => continue
+        };
+        let world_pos = character_transform.translation() + Vec3::new(0.0, SPEECH_BUBBLE_OFFSET_Y, 0.0);
+        let screen_pos = match camera.world_to_viewport(camera_transform, world_pos) {
+            Some(screen_pos) => screen_pos,
+            None => continue
+        };
+
+        egui::Area::new(egui::Id::new(("speech bubble", uuid)))
+        .fixed_pos(egui::pos2(screen_pos.x, screen_pos.y))
+        .pivot(egui::Align2::CENTER_BOTTOM)
+        .order(egui::Order::Background)
+        .interactable(false)
+        .show(ctx, |ui| {
+            egui::Frame::none()
+            .fill(Color32::from_rgba_premultiplied(255, 255, 255, 230))
+            .rounding(egui::Rounding::same(5.0))
+            .inner_margin(egui::Margin::same(6.0))
+            .show(ui, |ui| {
+                ui.set_max_width(180.0);
+                ui.label(egui::RichText::new(speech_bubble.text.to_string()).color(Color32::BLACK));
+            });
+        });
+    }
+}
+
+pub fn setup_room(mut commands: Commands, asset_server: Res<AssetServer>, scene: Res<Scene>, mut map_data: ResMut<MapData>, mut room_data: ResMut<RoomData>, mut s3_data: ResMut<S3Data>, mut character_animation: Res<CharacterAnimation>, mut multiplayer_room_state: ResMut<NextState<MultiplayerRoomState>>, mut loading_screen: ResMut<LoadingScreen>, mut room_history: ResMut<RoomHistory>, config: Res<AppConfig>, mut game_resources: ResMut<GameResources>){
+
+    // offline rooms are gone when the app closes, there is no point in listing them or keeping their chat
+    let mut chat_log = None;
+    if !config.offline && !room_data.room_id.is_empty() {
+        room_history.add_recent(SavedRoom {
+            room_id: room_data.room_id.to_string(),
+            name: room_data.room_name.to_string(),
+            map: map_data.map_name.to_string()
+        });
+        chat_log = GameResources::get_chat_log_path(&room_data.room_id);
+    }
+    game_resources.open_chat_log(chat_log);
 
     map_data.is_map_loaded = false;
-    load_tilemap_from_s3(&mut commands, &scene.scene_uuid, &mut s3_data, "shalloville".to_string(), map_data.map_name.to_string());
+    loading_screen.start(&scene.scene_uuid);
+    load_tilemap_from_s3(&mut commands, &scene.scene_uuid, &mut s3_data, map_data.map_name.to_string());
 
     room_data.load_ready_users(&mut commands, &asset_server, &scene.scene_uuid, &mut s3_data, &mut map_data, &mut character_animation);
 
     multiplayer_room_state.set(MultiplayerRoomState::Consumed);
 }
 
+// covers the world until the map and the avatar sprites of this scene have finished downloading
+pub fn display_loading_screen(mut contexts: EguiContexts, scene: Res<Scene>, map_data: Res<MapData>, time: Res<Time>, mut loading_screen: ResMut<LoadingScreen>, tasks_query: Query<&ComputeTask>, loaded_tilemap_query: Query<&LoadTilemapTask>){
+    if !loading_screen.is_active {
+        return;
+    }
+    if scene.scene_uuid.ne(&loading_screen.scene_uuid) {
+        loading_screen.is_active = false;
+        return;
+    }
+
+    let pending = tasks_query.iter().filter(|task| task.0.0.eq(&loading_screen.scene_uuid)).count() + loaded_tilemap_query.iter().count();
+    loading_screen.total = loading_screen.total.max(pending);
+    loading_screen.elapsed += time.delta_seconds();
+
+    if pending == 0 && map_data.is_map_loaded {
+        loading_screen.idle += time.delta_seconds();
+        if loading_screen.idle > LOADING_SETTLE_SECONDS {
+            loading_screen.is_active = false;
+            return;
+        }
+    } else {
+        loading_screen.idle = 0.0;
+    }
+    if loading_screen.elapsed > LOADING_TIMEOUT_SECONDS {
+        println!("loading timed out with {} downloads pending", pending);
+        loading_screen.is_active = false;
+        return;
+    }
+
+    let progress = match loading_screen.total {
+        0 => 0.0,
+        total => (total - pending) as f32 / total as f32
+    };
+
+    let ctx: &mut egui::Context = contexts.ctx_mut();
+    let screen_rect = ctx.screen_rect();
+
+    egui::Area::new(egui::Id::new("Loading Screen"))
+    .order(egui::Order::Foreground)
+    .fixed_pos(screen_rect.min)
+    .show(ctx, |ui| {
+        ui.allocate_rect(screen_rect, egui::Sense::click_and_drag());
+        ui.painter().rect_filled(screen_rect, 0.0, Color32::from_rgb(20, 20, 20));
+
+        let bar_rect = egui::Rect::from_center_size(screen_rect.center(), egui::vec2(screen_rect.width() * 0.4, 60.0));
+        ui.allocate_ui_at_rect(bar_rect, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.label(format!("Loading {}...", map_data.map_name));
+                ui.add(egui::ProgressBar::new(progress).show_percentage());
+            });
+        });
+    });
+}
+
+pub fn display_reconnecting_indicator(mut contexts: EguiContexts, rtc_resource: Res<RTCResource>){
+    let reconnect = &rtc_resource.reconnect;
+    if !reconnect.is_reconnecting && !reconnect.is_resuming {
+        return;
+    }
+
+    let mut text = String::from("Connection unstable, reconnecting...");
+    if reconnect.is_reconnecting {
+        text = format!("Reconnecting... (attempt {}/{})", reconnect.attempt + 1, MAX_RECONNECT_ATTEMPTS);
+    }
+
+    let ctx: &mut egui::Context = contexts.ctx_mut();
+    egui::Window::new("Reconnecting")
+    .frame(egui::Frame{rounding: egui::Rounding::same(5.0), fill: Color32::from_rgba_premultiplied(120, 60, 0, 220), inner_margin: egui::Margin::same(8.0), ..default()})
+    .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0 ,5.0))
+    .resizable(false)
+    .title_bar(false)
+    .show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            ui.spinner();
+            ui.colored_label(Color32::WHITE, text);
+        });
+    });
+}
+
 pub fn update_depth(mut depth_query: Query<(&Depth, &mut Transform), Changed<Depth>>){
     for (depth, mut transform) in depth_query.iter_mut() {
         transform.translation.z = depth.index;
     }
 }
 
-pub fn display_trigger_button(mut trigger_button_query: Query<(&TriggerButton, &mut Transform, &mut Visibility), Changed<TriggerButton>>, map_data: Res<MapData>, mut game_resources: ResMut<GameResources>){
+pub fn display_trigger_button(mut trigger_button_query: Query<(&TriggerButton, &mut Transform, &mut Visibility), Changed<TriggerButton>>, map_data: Res<MapData>, mut game_resources: ResMut<GameResources>, nokhwa_camera: Res<NokhwaCamera>){
     if let Ok((z_button, mut transform, mut visibility)) = trigger_button_query.get_single_mut() {
         *visibility = Visibility::Visible;
-        transform.translation = Vec3::new(z_button.pos_x, z_button.pos_y, map_data.max_y + 10.0);
+        transform.translation = Vec3::new(z_button.pos_x, z_button.pos_y, get_ui_depth(map_data.max_y));
         game_resources.right_bottom_texts.clear();
         for (keycode, interactive_type) in z_button.trigger.iter() {
             let mut message = match keycode {
@@ -162,6 +472,7 @@ pub fn display_trigger_button(mut trigger_button_query: Query<(&TriggerButton, &
             message.push(' ');
             message.push_str(match interactive_type {
                 InteractiveType::SwitchScreenShare => "On/Off Screen Share",
+                InteractiveType::SwitchCameraShare if !nokhwa_camera.is_available() => "Camera unavailable",
                 InteractiveType::SwitchCameraShare => "On/Off Camera Share",
                 InteractiveType::None => ""
             });
@@ -1,6 +1,8 @@
 mod build;
 
-use bevy::prelude::*;
+use std::time::Duration;
+
+use bevy::{app::ScheduleRunnerPlugin, prelude::*};
 use bevy_egui::EguiPlugin;
 
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
@@ -40,10 +42,36 @@ use game::GamePlugin;
 mod nokhwa;
 use nokhwa::NokhwaPlugin;
 
+mod config;
+use config::{resources::{AppConfig, LaunchOptions}, ConfigPlugin};
+
+mod bot;
+use bot::{resources::BotSettings, BotPlugin};
+
 fn main() {
     init();
 
-    App::new()
+    let launch_options = LaunchOptions::from_args(std::env::args().collect());
+    let mut app_config = AppConfig::load(launch_options.config_path.clone());
+    if launch_options.offline {
+        app_config.offline = true;
+    }
+
+    // no window, no egui, just participants walking around a room
+    if launch_options.bots > 0 {
+        App::new()
+        .insert_resource(app_config)
+        .insert_resource(BotSettings::from_launch_options(&launch_options))
+        .add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1.0 / 30.0))))
+        .add_plugins(BotPlugin)
+        .run();
+        return;
+    }
+
+    let mut app = App::new();
+    app
+    .insert_resource(app_config)
+    .insert_resource(launch_options.clone())
     .add_plugins(DefaultPlugins
         .set(ImagePlugin::default_nearest())
         .set(WindowPlugin {
@@ -53,8 +81,18 @@ fn main() {
             }),
             ..Default::default()
         })
-    )
+    );
+
+    // --editor skips the lobby, init_state in the plugins keeps these
+    if launch_options.editor {
+        app
+        .insert_state(editor::EditorState::Open)
+        .insert_state(main_menu::MainMenuState::None);
+    }
+
+    app
     .add_plugins(EguiPlugin)
+    .add_plugins(ConfigPlugin)
     .add_plugins(NetworkingPlugin)
     // .add_plugins(WorldInspectorPlugin::new())
     .add_plugins(EditorPlugin)
@@ -13,10 +13,14 @@ impl Plugin for MainMenuPlugin {
     fn build(&self, app: &mut App) {
         app
         .init_resource::<RoomData>()
+        .init_resource::<RoomBrowser>()
+        .insert_resource(RoomHistory::load())
         .init_state::<MainMenuState>()
         .add_systems(OnEnter(MainMenuState::InitInputRoom), init_select_room)
         .add_systems(OnEnter(MainMenuState::InputRoom), setup_select_room)
         .add_systems(Update, select_room.run_if(in_state(MainMenuState::InputRoom)))
+        .add_systems(Update, refresh_room_browser.run_if(in_state(MainMenuState::InputRoom)))
+        .add_systems(Update, list_rooms_event_listener)
         .add_systems(OnEnter(MainMenuState::InputUserSkin), setup_select_user_skin)
         .add_systems(Update, select_user_skin.run_if(in_state(MainMenuState::InputUserSkin)));
     }
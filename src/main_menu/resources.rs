@@ -1,18 +1,30 @@
-use std::collections::HashMap;
+use std::{collections::{HashMap, HashSet, VecDeque}, fs, path::PathBuf};
 
 use bevy::prelude::*;
+use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Serialize};
 
-use crate::{character::{resources::CharacterAnimation, systems::create_character}, map_structures::resources::MapData, networking::s3::resources::S3Data, wardrobe::resources::BodyParts};
+use crate::{character::{resources::CharacterAnimation, systems::create_character}, map_structures::resources::MapData, networking::{rtc::{access::is_host_proof_valid, protocol::ModerationAction}, s3::resources::S3Data}, utils::{is_within_tiles, lerp}, wardrobe::resources::BodyParts};
 
 #[derive(Resource)]
 pub struct RoomData {
     pub current_scene: Option<Entity>,
     pub room_id: String,
+    pub room_name: String,
+
+    // what this user answers a private room with
+    pub room_password: String,
+    pub invite_code: String,
+    // set when creating a room: no password, only people with an invite get in
+    pub is_invite_only: bool,
+    // only the host has it, invite codes are signed with it
+    pub invite_signing_key: Option<SigningKey>,
 
     pub this_user_name: String,
     pub this_user_uuid: String,
 
-    pub room_users: HashMap<String, UserData>
+    pub room_users: HashMap<String, UserData>,
+    pub roles: RoomRoles
 }
 
 #[derive(PartialEq, Eq, Clone)]
@@ -27,10 +39,312 @@ impl Default for RoomData{
         RoomData {
             current_scene: None,
             room_id: String::new(),
+            room_name: String::new(),
+            room_password: String::new(),
+            invite_code: String::new(),
+            is_invite_only: false,
+            invite_signing_key: None,
             this_user_name: String::new(),
             this_user_uuid: String::new(),
-            room_users: HashMap::new()
+            room_users: HashMap::new(),
+            roles: RoomRoles::default()
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RoomRole {
+    Host,
+    Moderator,
+    Participant
+}
+
+// who may moderate the room. The host is whoever carries a proof signed with the host key from the
+// room metadata, and its attributes carry the rest, so every client agrees on the roles without trusting the sender
+pub struct RoomRoles {
+    pub host: String,
+    pub host_key: String,
+    // only set on the host's own client, published with the other role attributes
+    pub host_proof: String,
+    pub moderators: HashSet<String>,
+    pub muted: HashSet<String>,
+    pub kicked: HashSet<String>,
+    pub is_locked: bool,
+
+    // everyone who was in the room before it got locked may come back after a dropped connection
+    pub admitted: HashSet<String>
+}
+
+impl Default for RoomRoles {
+    fn default() -> RoomRoles {
+        RoomRoles {
+            host: String::new(),
+            host_key: String::new(),
+            host_proof: String::new(),
+            moderators: HashSet::new(),
+            muted: HashSet::new(),
+            kicked: HashSet::new(),
+            is_locked: false,
+            admitted: HashSet::new()
+        }
+    }
+}
+
+impl RoomRoles {
+    pub fn get_role(&self, identity: &str) -> RoomRole {
+        if !self.host.is_empty() && self.host.eq(identity) {
+            return RoomRole::Host;
+        }
+        if self.moderators.contains(identity) {
+            return RoomRole::Moderator;
+        }
+        return RoomRole::Participant;
+    }
+
+    // a client picks its own identity, so only the signature says who the host is
+    pub fn has_host_proof(&self, room_id: &str, identity: &str, attributes: &HashMap<String, String>) -> bool {
+        match attributes.get("host_proof") {
+            Some(host_proof) => return is_host_proof_valid(&self.host_key, room_id, identity, host_proof),
+            None => return false
+        }
+    }
+
+    pub fn can_moderate(&self, identity: &str) -> bool {
+        return self.get_role(identity).ne(&RoomRole::Participant);
+    }
+
+    pub fn is_allowed(&self, sender: &str, action: ModerationAction, target: &str) -> bool {
+        let sender_role = self.get_role(sender);
+        match action {
+            ModerationAction::Lock | ModerationAction::Unlock => return sender_role.ne(&RoomRole::Participant),
+            // only the host hands out roles
+            ModerationAction::Promote | ModerationAction::Demote => return sender_role.eq(&RoomRole::Host) && self.get_role(target).ne(&RoomRole::Host),
+            // moderators can't act on each other or on the host
+            _ => {
+                match sender_role {
+                    RoomRole::Host => return self.get_role(target).ne(&RoomRole::Host),
+                    RoomRole::Moderator => return self.get_role(target).eq(&RoomRole::Participant),
+                    RoomRole::Participant => return false
+                }
+            }
+        }
+    }
+
+    pub fn apply(&mut self, action: ModerationAction, target: &str) {
+        match action {
+            ModerationAction::Promote => {
+                self.moderators.insert(target.to_string());
+            },
+            ModerationAction::Demote => {
+                self.moderators.remove(target);
+            },
+            ModerationAction::Kick => {
+                self.kicked.insert(target.to_string());
+                self.admitted.remove(target);
+            },
+            ModerationAction::Mute => {
+                self.muted.insert(target.to_string());
+            },
+            ModerationAction::Unmute => {
+                self.muted.remove(target);
+            },
+            ModerationAction::Lock => self.is_locked = true,
+            ModerationAction::Unlock => self.is_locked = false,
+            ModerationAction::StopCamera => {}
+        }
+    }
+
+    pub fn can_join(&self, identity: &str) -> bool {
+        if self.kicked.contains(identity) {
+            return false;
+        }
+        return !self.is_locked || self.admitted.contains(identity) || self.can_moderate(identity);
+    }
+
+    // published by the host whenever the roles change
+    pub fn to_attributes(&self) -> HashMap<String, String> {
+        return HashMap::from([
+            (String::from("role"), String::from("host")),
+            (String::from("host_proof"), self.host_proof.to_string()),
+            (String::from("moderators"), join_identities(&self.moderators)),
+            (String::from("muted"), join_identities(&self.muted)),
+            (String::from("kicked"), join_identities(&self.kicked)),
+            (String::from("locked"), String::from(if self.is_locked { "1" } else { "0" }))
+        ]);
+    }
+
+    // changed attributes only carry the keys that changed
+    pub fn apply_host_attributes(&mut self, attributes: &HashMap<String, String>) {
+        if let Some(moderators) = attributes.get("moderators") {
+            self.moderators = split_identities(moderators);
+        }
+        if let Some(muted) = attributes.get("muted") {
+            self.muted = split_identities(muted);
+        }
+        if let Some(kicked) = attributes.get("kicked") {
+            self.kicked = split_identities(kicked);
+        }
+        if let Some(locked) = attributes.get("locked") {
+            self.is_locked = locked.eq("1");
+        }
+    }
+}
+
+fn join_identities(identities: &HashSet<String>) -> String {
+    return identities.iter().map(|identity| identity.to_string()).collect::<Vec<String>>().join(",");
+}
+
+fn split_identities(identities: &str) -> HashSet<String> {
+    return identities.split(',').filter(|identity| !identity.is_empty()).map(|identity| identity.to_string()).collect();
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum RoomBrowserTab {
+    Active,
+    Recent,
+    Favourites
+}
+
+pub struct RoomBrowserEntry {
+    pub room_id: String,
+    pub name: String,
+    pub map: String,
+    pub participants: u32,
+    pub is_private: bool
+}
+
+// active rooms from the transport, refreshed when the lobby opens or on request
+#[derive(Resource)]
+pub struct RoomBrowser {
+    // why the last room was left, e.g. after a kick
+    pub notice: String,
+    pub entries: Vec<RoomBrowserEntry>,
+    pub filter: String,
+    pub tab: RoomBrowserTab,
+    pub is_loading: bool,
+    pub is_refresh_requested: bool,
+    pub status: String,
+    // the last room tried wants a password
+    pub is_password_requested: bool
+}
+
+impl Default for RoomBrowser {
+    fn default() -> RoomBrowser {
+        RoomBrowser {
+            notice: String::new(),
+            entries: Vec::new(),
+            filter: String::new(),
+            tab: RoomBrowserTab::Active,
+            is_loading: false,
+            is_refresh_requested: false,
+            status: String::new(),
+            is_password_requested: false
+        }
+    }
+}
+
+impl RoomBrowserEntry {
+    pub fn get_display_name(&self) -> String {
+        if self.name.is_empty() {
+            return self.room_id.to_string();
+        }
+        return self.name.to_string();
+    }
+
+    // filter matches the name, the id or the map
+    pub fn is_match(&self, filter: &str) -> bool {
+        let filter = filter.trim().to_lowercase();
+        if filter.is_empty() {
+            return true;
+        }
+        return self.name.to_lowercase().contains(&filter) || self.room_id.to_lowercase().contains(&filter) || self.map.to_lowercase().contains(&filter);
+    }
+}
+
+pub const MAX_RECENT_ROOMS: usize = 10;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SavedRoom {
+    pub room_id: String,
+    pub name: String,
+    pub map: String
+}
+
+// recently joined and favourite rooms, kept in <config dir>/shalloville/rooms.json
+#[derive(Resource, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct RoomHistory {
+    // newest first
+    pub recent: Vec<SavedRoom>,
+    pub favourites: Vec<SavedRoom>
+}
+
+impl Default for RoomHistory {
+    fn default() -> RoomHistory {
+        RoomHistory {
+            recent: Vec::new(),
+            favourites: Vec::new()
+        }
+    }
+}
+
+impl RoomHistory {
+    pub fn get_path() -> Option<PathBuf> {
+        return dirs::config_dir().map(|config_dir| config_dir.join("shalloville").join("rooms.json"));
+    }
+
+    pub fn load() -> RoomHistory {
+        if let Some(path) = RoomHistory::get_path() {
+            if let Ok(history_str) = fs::read_to_string(&path) {
+                match serde_json::from_str::<RoomHistory>(&history_str) {
+                    Ok(room_history) => return room_history,
+                    Err(e) => println!("{} is invalid, starting without room history: {}", path.display(), e)
+                }
+            }
+        }
+        return RoomHistory::default();
+    }
+
+    pub fn save(&self) {
+        if let Some(path) = RoomHistory::get_path() {
+            if let Some(parent) = path.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+            match serde_json::to_string_pretty(self) {
+                Ok(history_str) => {
+                    if let Err(e) = fs::write(&path, history_str) {
+                        println!("failed to save room history: {}", e);
+                    }
+                },
+                Err(e) => println!("failed to save room history: {}", e)
+            }
+        }
+    }
+
+    pub fn add_recent(&mut self, saved_room: SavedRoom) {
+        self.recent.retain(|recent_room| recent_room.room_id.ne(&saved_room.room_id));
+        // keep a favourite's name and map up to date as well
+        for favourite in self.favourites.iter_mut() {
+            if favourite.room_id.eq(&saved_room.room_id) {
+                *favourite = saved_room.clone();
+            }
+        }
+        self.recent.insert(0, saved_room);
+        self.recent.truncate(MAX_RECENT_ROOMS);
+        self.save();
+    }
+
+    pub fn is_favourite(&self, room_id: &str) -> bool {
+        return self.favourites.iter().any(|favourite| favourite.room_id.eq(room_id));
+    }
+
+    pub fn toggle_favourite(&mut self, saved_room: SavedRoom) {
+        if self.is_favourite(&saved_room.room_id) {
+            self.favourites.retain(|favourite| favourite.room_id.ne(&saved_room.room_id));
+        } else {
+            self.favourites.push(saved_room);
         }
+        self.save();
     }
 }
 
@@ -62,6 +376,9 @@ pub struct UserData {
     pub character_controller: CharacterController,
 
     pub user_status: UserStatus,
+    pub position_buffer: PositionBuffer,
+    // true once the user's own snapshot arrived, so the map spawn point no longer applies
+    pub is_state_synced: bool
 }
 
 pub struct CharacterController {
@@ -151,6 +468,34 @@ impl CharacterController {
     pub fn set_changed(&mut self, is_changed: bool) {
         self.is_changed = is_changed;
     }
+    // places a remote avatar exactly, facing the way it moved since the last frame
+    pub fn set_interpolated_pos(&mut self, x: f32, y: f32) {
+        if self.pos_x.ne(&x) {
+            let mut scale_x = 1.0;
+            if self.pos_x > x {
+                scale_x = -1.0;
+            }
+            self.scale_x = scale_x;
+        }
+        if self.pos_x.ne(&x) || self.pos_y.ne(&y) {
+            self.is_changed = true;
+        }
+        self.prev_pos_x = x;
+        self.prev_pos_y = y;
+        self.pos_x = x;
+        self.pos_y = y;
+        self.pos_time = 1.0;
+    }
+    pub fn teleport(&mut self, x: f32, y: f32, scale_x: f32, animation: &str) {
+        self.prev_pos_x = x;
+        self.prev_pos_y = y;
+        self.pos_x = x;
+        self.pos_y = y;
+        self.pos_time = 1.0;
+        self.scale_x = scale_x;
+        self.set_animation(animation);
+        self.is_changed = true;
+    }
     pub fn set_pos_time(&mut self, t: f32) {
         if t <= 1.0 {
             self.pos_time = t;
@@ -159,6 +504,91 @@ impl CharacterController {
     }
 }
 
+// remote avatars are drawn this far in the past, so there is usually a later position to move towards
+pub const INTERPOLATION_DELAY_SECONDS: f64 = 0.25;
+// how long an avatar keeps its last velocity when no new position arrives
+pub const MAX_EXTRAPOLATION_SECONDS: f64 = 0.25;
+const MAX_BUFFERED_POSITIONS: usize = 32;
+
+#[derive(Clone)]
+pub struct PositionBuffer {
+    // (local time in seconds, x, y), oldest first
+    positions: VecDeque<(f64, f32, f32)>,
+    // local time minus sender time, the smallest seen so far is the least delayed packet
+    clock_offset: Option<f64>
+}
+
+impl Default for PositionBuffer {
+    fn default() -> PositionBuffer {
+        PositionBuffer {
+            positions: VecDeque::new(),
+            clock_offset: None
+        }
+    }
+}
+
+impl PositionBuffer {
+    pub fn push(&mut self, sender_time_ms: u32, local_time: f64, x: f32, y: f32) {
+        let sender_time = sender_time_ms as f64 / 1000.0;
+        let offset = local_time - sender_time;
+        let clock_offset = match self.clock_offset {
+            Some(clock_offset) => clock_offset.min(offset),
+            None => offset
+        };
+        self.clock_offset = Some(clock_offset);
+
+        let time = sender_time + clock_offset;
+        if let Some((last_time, _, _)) = self.positions.back() {
+            // lossy packets can arrive out of order
+            if time <= *last_time {
+                return;
+            }
+        }
+        self.positions.push_back((time, x, y));
+        if self.positions.len() > MAX_BUFFERED_POSITIONS {
+            self.positions.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.clock_offset = None;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.positions.is_empty();
+    }
+
+    pub fn sample(&mut self, local_time: f64) -> Option<(f32, f32)> {
+        let render_time = local_time - INTERPOLATION_DELAY_SECONDS;
+
+        // keep one position before the render time to interpolate from
+        while self.positions.len() > 2 && self.positions[1].0 <= render_time {
+            self.positions.pop_front();
+        }
+
+        let (first_time, first_x, first_y) = *self.positions.front()?;
+        if render_time <= first_time || self.positions.len() == 1 {
+            return Some((first_x, first_y));
+        }
+
+        let (last_time, last_x, last_y) = *self.positions.back()?;
+        if render_time <= last_time {
+            let (next_time, next_x, next_y) = self.positions[1];
+            let t = ((render_time - first_time) / (next_time - first_time)) as f32;
+            return Some((lerp(first_x, next_x, t), lerp(first_y, next_y, t)));
+        }
+
+        // no newer position yet, keep moving the same way for a short while
+        let (prev_time, prev_x, prev_y) = self.positions[self.positions.len() - 2];
+        let elapsed = (render_time - last_time).min(MAX_EXTRAPOLATION_SECONDS) as f32;
+        let duration = (last_time - prev_time) as f32;
+        let velocity_x = (last_x - prev_x) / duration;
+        let velocity_y = (last_y - prev_y) / duration;
+        return Some((last_x + velocity_x * elapsed, last_y + velocity_y * elapsed));
+    }
+}
+
 impl UserData {
     pub fn create_empty(uuid: &str, body_parts: BodyParts) -> UserData {
         UserData {
@@ -170,7 +600,9 @@ impl UserData {
             character: None,
             character_controller: CharacterController::default(),
 
-            user_status: UserStatus::Ready
+            user_status: UserStatus::Ready,
+            position_buffer: PositionBuffer::default(),
+            is_state_synced: false
         }
     }
 
@@ -192,12 +624,29 @@ impl Clone for UserData {
             character: None,
             character_controller: self.character_controller.clone(),
 
-            user_status: self.user_status.clone()
+            user_status: self.user_status.clone(),
+            position_buffer: self.position_buffer.clone(),
+            is_state_synced: self.is_state_synced
         }
     }
 }
 
 impl RoomData {
+    // everyone else whose character stands within range_tiles of this user's
+    pub fn get_nearby_users(&self, map_data: &MapData, range_tiles: u8) -> Vec<String> {
+        let this_pos = match self.room_users.get(&self.this_user_uuid) {
+            Some(this_user_data) => this_user_data.character_controller.get_pos(),
+            None => return Vec::new()
+        };
+        return self.room_users.iter()
+        .filter(|(uuid, user_data)| {
+            let pos = user_data.character_controller.get_pos();
+            uuid.ne(&&self.this_user_uuid) && is_within_tiles(this_pos.0, this_pos.1, pos.0, pos.1, map_data.tile_width, map_data.tile_height, range_tiles as f32)
+        })
+        .map(|(uuid, _)| uuid.to_string())
+        .collect();
+    }
+
     pub fn load_ready_users(&mut self, commands: &mut Commands, assets_server: &Res<AssetServer>, scene_uuid: &str, s3_data: &mut ResMut<S3Data>, map_data: &mut ResMut<MapData>, character_animation: &mut Res<CharacterAnimation>){
         if let Some(scene_entity) = self.current_scene {
             for (_, user_data) in self.room_users.iter_mut() {
@@ -237,4 +686,202 @@ impl RoomData {
 
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networking::rtc::access::{create_host_proof, get_public_key, new_signing_key};
+
+    fn assert_near(actual: Option<(f32, f32)>, expected: (f32, f32)) {
+        let actual = actual.unwrap();
+        assert!((actual.0 - expected.0).abs() < 0.01 && (actual.1 - expected.1).abs() < 0.01, "{:?} is not {:?}", actual, expected);
+    }
+
+    // three positions 100ms apart, moving 10 to the right each time, received at local time 1.0
+    fn get_walking_buffer() -> PositionBuffer {
+        let mut buffer = PositionBuffer::default();
+        buffer.push(0, 1.0, 0.0, 0.0);
+        buffer.push(100, 1.1, 10.0, 0.0);
+        buffer.push(200, 1.2, 20.0, 0.0);
+        return buffer;
+    }
+
+    #[test]
+    fn empty_buffer_has_no_position() {
+        let mut buffer = PositionBuffer::default();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.sample(10.0), None);
+    }
+
+    #[test]
+    fn samples_are_interpolated_behind_the_latest_position() {
+        let mut buffer = get_walking_buffer();
+        // before the first position there is nothing to move from
+        assert_near(buffer.sample(1.0), (0.0, 0.0));
+        assert_near(buffer.sample(1.0 + INTERPOLATION_DELAY_SECONDS + 0.05), (5.0, 0.0));
+        assert_near(buffer.sample(1.0 + INTERPOLATION_DELAY_SECONDS + 0.15), (15.0, 0.0));
+        assert_near(buffer.sample(1.0 + INTERPOLATION_DELAY_SECONDS + 0.2), (20.0, 0.0));
+    }
+
+    #[test]
+    fn out_of_order_positions_are_dropped() {
+        let mut buffer = PositionBuffer::default();
+        buffer.push(0, 1.0, 0.0, 0.0);
+        buffer.push(200, 1.2, 20.0, 0.0);
+        // older than the last one, a lossy packet that overtook it
+        buffer.push(100, 1.25, 99.0, 0.0);
+        buffer.push(200, 1.3, 99.0, 0.0);
+        assert_eq!(buffer.positions.len(), 2);
+        assert_near(buffer.sample(1.0 + INTERPOLATION_DELAY_SECONDS + 0.1), (10.0, 0.0));
+    }
+
+    #[test]
+    fn late_packets_keep_their_sender_spacing() {
+        let mut buffer = PositionBuffer::default();
+        buffer.push(0, 1.0, 0.0, 0.0);
+        // held up in the network for 200ms, it still belongs 100ms after the first
+        buffer.push(100, 1.3, 10.0, 0.0);
+        assert!((buffer.positions[1].0 - 1.1).abs() < 0.001);
+
+        // a packet faster than any before moves the clock offset down
+        buffer.push(300, 1.2, 30.0, 0.0);
+        assert!((buffer.positions[2].0 - 1.2).abs() < 0.001);
+    }
+
+    #[test]
+    fn samples_past_the_latest_position_are_extrapolated() {
+        let mut buffer = get_walking_buffer();
+        assert_near(buffer.sample(1.2 + INTERPOLATION_DELAY_SECONDS + 0.05), (25.0, 0.0));
+        // after MAX_EXTRAPOLATION_SECONDS the avatar stops instead of walking off
+        let max_x = 20.0 + 100.0 * MAX_EXTRAPOLATION_SECONDS as f32;
+        assert_near(buffer.sample(1.2 + INTERPOLATION_DELAY_SECONDS + 5.0), (max_x, 0.0));
+    }
+
+    #[test]
+    fn buffer_is_capped() {
+        let mut buffer = PositionBuffer::default();
+        for i in 0..(MAX_BUFFERED_POSITIONS as u32 + 10) {
+            buffer.push(i * 100, 1.0 + i as f64 * 0.1, i as f32, 0.0);
+        }
+        assert_eq!(buffer.positions.len(), MAX_BUFFERED_POSITIONS);
+        buffer.clear();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.clock_offset, None);
+    }
+
+    fn get_roles() -> RoomRoles {
+        let mut roles = RoomRoles::default();
+        roles.host = "host".to_string();
+        roles.moderators.insert("moderator".to_string());
+        roles.moderators.insert("other-moderator".to_string());
+        return roles;
+    }
+
+    #[test]
+    fn roles_come_from_the_host_and_moderator_list() {
+        let roles = get_roles();
+        assert_eq!(roles.get_role("host"), RoomRole::Host);
+        assert_eq!(roles.get_role("moderator"), RoomRole::Moderator);
+        assert_eq!(roles.get_role("someone"), RoomRole::Participant);
+        // a room without a host has nobody with host rights
+        assert_eq!(RoomRoles::default().get_role(""), RoomRole::Participant);
+    }
+
+    #[test]
+    fn moderator_cannot_act_on_the_host_or_other_moderators() {
+        let roles = get_roles();
+        for action in [ModerationAction::Kick, ModerationAction::Mute, ModerationAction::Unmute, ModerationAction::StopCamera] {
+            assert!(!roles.is_allowed("moderator", action, "host"));
+            assert!(!roles.is_allowed("moderator", action, "other-moderator"));
+            assert!(roles.is_allowed("moderator", action, "someone"));
+            assert!(roles.is_allowed("host", action, "moderator"));
+            assert!(!roles.is_allowed("host", action, "host"));
+            assert!(!roles.is_allowed("someone", action, "someone-else"));
+        }
+    }
+
+    #[test]
+    fn only_the_host_hands_out_roles() {
+        let roles = get_roles();
+        assert!(roles.is_allowed("host", ModerationAction::Promote, "someone"));
+        assert!(roles.is_allowed("host", ModerationAction::Demote, "moderator"));
+        assert!(!roles.is_allowed("host", ModerationAction::Demote, "host"));
+        assert!(!roles.is_allowed("moderator", ModerationAction::Promote, "someone"));
+        assert!(!roles.is_allowed("moderator", ModerationAction::Demote, "host"));
+        assert!(!roles.is_allowed("someone", ModerationAction::Promote, "someone"));
+    }
+
+    #[test]
+    fn moderators_lock_the_room() {
+        let roles = get_roles();
+        assert!(roles.is_allowed("host", ModerationAction::Lock, ""));
+        assert!(roles.is_allowed("moderator", ModerationAction::Unlock, ""));
+        assert!(!roles.is_allowed("someone", ModerationAction::Lock, ""));
+    }
+
+    #[test]
+    fn locked_room_only_lets_back_in_who_was_there() {
+        let mut roles = get_roles();
+        roles.admitted.insert("someone".to_string());
+        assert!(roles.can_join("newcomer"));
+
+        roles.apply(ModerationAction::Lock, "");
+        assert!(roles.can_join("someone"));
+        assert!(roles.can_join("moderator"));
+        assert!(!roles.can_join("newcomer"));
+
+        // kicked stays out even after the room is unlocked
+        roles.apply(ModerationAction::Kick, "someone");
+        roles.apply(ModerationAction::Unlock, "");
+        assert!(!roles.can_join("someone"));
+        assert!(roles.can_join("newcomer"));
+    }
+
+    #[test]
+    fn host_attributes_round_trip() {
+        let mut roles = get_roles();
+        roles.muted.insert("loud".to_string());
+        roles.kicked.insert("rude".to_string());
+        roles.is_locked = true;
+
+        let mut copy = RoomRoles::default();
+        copy.apply_host_attributes(&roles.to_attributes());
+        assert_eq!(copy.moderators, roles.moderators);
+        assert_eq!(copy.muted, roles.muted);
+        assert_eq!(copy.kicked, roles.kicked);
+        assert!(copy.is_locked);
+    }
+
+    #[test]
+    fn changed_attributes_only_touch_their_keys() {
+        let mut roles = get_roles();
+        roles.muted.insert("loud".to_string());
+        roles.apply_host_attributes(&HashMap::from([
+            (String::from("moderators"), String::new()),
+            (String::from("locked"), String::from("1"))
+        ]));
+        assert!(roles.moderators.is_empty());
+        assert!(roles.muted.contains("loud"));
+        assert!(roles.is_locked);
+        // keys other than the role lists are ignored
+        roles.apply_host_attributes(&HashMap::from([(String::from("name"), String::from("host"))]));
+        assert!(roles.is_locked);
+    }
+
+    #[test]
+    fn host_is_whoever_carries_the_signed_proof() {
+        let signing_key = new_signing_key().unwrap();
+        let mut host = RoomRoles::default();
+        host.host_key = get_public_key(&signing_key);
+        host.host_proof = create_host_proof(&signing_key, "room", "host");
+        let attributes = host.to_attributes();
+
+        let mut roles = RoomRoles::default();
+        roles.host_key = host.host_key.to_string();
+        assert!(roles.has_host_proof("room", "host", &attributes));
+        // the same attributes published under another identity claim nothing
+        assert!(!roles.has_host_proof("room", "someone", &attributes));
+        assert!(!roles.has_host_proof("room", "host", &HashMap::from([(String::from("role"), String::from("host"))])));
+    }
+}
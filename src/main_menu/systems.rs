@@ -4,9 +4,9 @@ use uuid::Uuid;
 use bevy::{prelude::*, window::PrimaryWindow};
 use bevy_egui::{egui, EguiContexts};
 
-use crate::{character::{resources::CharacterAnimation, systems::push_character}, editor::{resources::{Scene, StateName}, systems::create_scene, CreateSceneEvent}, map_structures::resources::MapData, networking::{rtc::{resources::RTCResource, systems::check_room}, s3::{resources::S3Data, systems::load_tilemap_from_s3}}, wardrobe::resources::{OnWardrobeConfirmed, WardrobeResources}};
+use crate::{config::resources::AppConfig, character::{resources::CharacterAnimation, systems::push_character}, editor::{resources::{Scene, StateName}, systems::create_scene, CreateSceneEvent}, map_structures::resources::MapData, networking::{rtc::{components::ListRoomsTask, resources::{RTCResource, RoomMetadata}, systems::check_room}, s3::{resources::S3Data, systems::load_tilemap_from_s3}}, wardrobe::resources::{OnWardrobeConfirmed, WardrobeResources}};
 
-use super::{ MainMenuImages, RoomData, UserData, UserStatus};
+use super::{ MainMenuImages, RoomBrowser, RoomBrowserEntry, RoomBrowserTab, RoomData, RoomHistory, SavedRoom, UserData, UserStatus};
 
 pub fn init_select_room(mut commands: Commands, mut room_data: ResMut<RoomData>, mut create_scene_event: EventWriter<CreateSceneEvent>) {
     create_scene(&mut commands, &mut create_scene_event, &mut room_data, "lobby", HashMap::from([
@@ -15,8 +15,11 @@ pub fn init_select_room(mut commands: Commands, mut room_data: ResMut<RoomData>,
     ]), None);
 }
 
-pub fn setup_select_room(mut commands: Commands, window_query: Query<&Window, With<PrimaryWindow>>, room_data: ResMut<RoomData>, images: Local<MainMenuImages>) {
-    
+pub fn setup_select_room(mut commands: Commands, window_query: Query<&Window, With<PrimaryWindow>>, room_data: ResMut<RoomData>, mut room_browser: ResMut<RoomBrowser>, images: Local<MainMenuImages>) {
+    // a listing from the previous lobby scene was dropped with it
+    room_browser.is_loading = false;
+    room_browser.is_refresh_requested = true;
+
     let window = window_query.get_single().unwrap();
 
     let bg_handle: Handle<Image> = images.background.clone_weak();
@@ -52,7 +55,7 @@ pub fn setup_select_room(mut commands: Commands, window_query: Query<&Window, Wi
     commands.entity(room_data.current_scene.unwrap()).add_child(bg).add_child(canvas).add_child(icon);
 }
 
-pub fn select_room(mut commands: Commands, mut contexts: EguiContexts, scene: Res<Scene>, window_query: Query<&Window, With<PrimaryWindow>>, mut create_scene_event: EventWriter<CreateSceneEvent>, mut room_data: ResMut<RoomData>, mut rtc_resource: ResMut<RTCResource>, mut wardrobe_resources: ResMut<WardrobeResources>){
+pub fn select_room(mut commands: Commands, mut contexts: EguiContexts, scene: Res<Scene>, window_query: Query<&Window, With<PrimaryWindow>>, mut create_scene_event: EventWriter<CreateSceneEvent>, mut room_data: ResMut<RoomData>, mut rtc_resource: ResMut<RTCResource>, mut wardrobe_resources: ResMut<WardrobeResources>, mut room_browser: ResMut<RoomBrowser>, mut room_history: ResMut<RoomHistory>, config: Res<AppConfig>){
     let window = window_query.get_single().unwrap();
     let ctx: &mut egui::Context = contexts.ctx_mut();
 
@@ -61,13 +64,19 @@ pub fn select_room(mut commands: Commands, mut contexts: EguiContexts, scene: Re
     .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0 ,0.0))
     .min_width(window.width() * 0.3)
     .max_width(500.0)
-    .max_height(100.0)
+    .max_height(window.height() * 0.5)
     .resizable(false)
     .title_bar(false)
     .show(ctx, |ui| {
 
         ui.vertical_centered(|ui| {
+            if !room_browser.notice.is_empty() {
+                ui.label(egui::RichText::new(room_browser.notice.to_string()).color(egui::Color32::LIGHT_RED));
+                ui.add_space(10.0);
+            }
+
             if ui.button("New Room").clicked() {
+                room_browser.notice.clear();
 
                 wardrobe_resources.is_name_changable = true;
                 wardrobe_resources.is_clothes_changable = true;
@@ -92,21 +101,181 @@ pub fn select_room(mut commands: Commands, mut contexts: EguiContexts, scene: Re
             ui.add_space(20.0);
 
             ui.vertical_centered(|ui| {
+                // rooms only exist in this process, there is nothing to join
+                if config.offline {
+                    ui.label("Offline mode: joining other rooms is unavailable");
+                    return;
+                }
+                // a room id or an invite code
                 ui.text_edit_singleline(&mut room_data.room_id);
+                if room_browser.is_password_requested {
+                    ui.label("Password");
+                    ui.add(egui::TextEdit::singleline(&mut room_data.room_password).password(true));
+                }
                 if ui.button("Enter").clicked() {
-                    check_room(&mut commands, &scene.scene_uuid, &mut rtc_resource, room_data.room_id.to_string());
+                    room_browser.notice.clear();
+                    check_room(&mut commands, &scene.scene_uuid, &mut rtc_resource, &mut room_data);
+                }
+
+                ui.add_space(20.0);
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut room_browser.tab, RoomBrowserTab::Active, "Active");
+                    ui.selectable_value(&mut room_browser.tab, RoomBrowserTab::Recent, "Recent");
+                    ui.selectable_value(&mut room_browser.tab, RoomBrowserTab::Favourites, "Favourites");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Filter");
+                    ui.text_edit_singleline(&mut room_browser.filter);
+                    if ui.add_enabled(!room_browser.is_loading, egui::Button::new("Refresh")).clicked() {
+                        room_browser.is_refresh_requested = true;
+                    }
+                });
+                if !room_browser.status.is_empty() {
+                    ui.label(room_browser.status.to_string());
+                }
+
+                // saved rooms have no live occupancy, use the active listing when the room shows up there
+                let entries: Vec<RoomBrowserEntry> = match room_browser.tab {
+                    RoomBrowserTab::Active => room_browser.entries.iter().map(|entry| RoomBrowserEntry {
+                        room_id: entry.room_id.to_string(),
+                        name: entry.name.to_string(),
+                        map: entry.map.to_string(),
+                        participants: entry.participants,
+                        is_private: entry.is_private
+                    }).collect(),
+                    RoomBrowserTab::Recent => get_saved_room_entries(&room_history.recent, &room_browser.entries),
+                    RoomBrowserTab::Favourites => get_saved_room_entries(&room_history.favourites, &room_browser.entries)
+                };
+
+                let mut selected_room: Option<String> = None;
+                let mut toggled_room: Option<SavedRoom> = None;
+
+                egui::ScrollArea::vertical().max_height(window.height() * 0.25).show(ui, |ui| {
+                    let filtered_entries: Vec<&RoomBrowserEntry> = entries.iter().filter(|entry| entry.is_match(&room_browser.filter)).collect();
+                    if filtered_entries.is_empty() {
+                        ui.label("No rooms");
+                    }
+                    for entry in filtered_entries {
+                        ui.horizontal(|ui| {
+                            let star = match room_history.is_favourite(&entry.room_id) {
+                                true => "★",
+                                false => "☆"
+                            };
+                            if ui.button(star).clicked() {
+                                toggled_room = Some(SavedRoom {
+                                    room_id: entry.room_id.to_string(),
+                                    name: entry.name.to_string(),
+                                    map: entry.map.to_string()
+                                });
+                            }
+                            ui.label(entry.get_display_name());
+                            if entry.is_private {
+                                ui.label("private");
+                            }
+                            ui.label(format!("map {}", entry.map));
+                            if room_browser.tab.eq(&RoomBrowserTab::Active) || entry.participants > 0 {
+                                ui.label(format!("{} online", entry.participants));
+                            }
+                            if ui.button("Join").clicked() {
+                                selected_room = Some(entry.room_id.to_string());
+                            }
+                        });
+                    }
+                });
+
+                if let Some(saved_room) = toggled_room {
+                    room_history.toggle_favourite(saved_room);
+                }
+                if let Some(room_id) = selected_room {
+                    room_browser.notice.clear();
+                    room_browser.is_password_requested = false;
+                    room_data.room_id = room_id;
+                    room_data.room_password.clear();
+                    room_data.invite_code.clear();
+                    check_room(&mut commands, &scene.scene_uuid, &mut rtc_resource, &mut room_data);
                 }
             });
         });
     });
 }
 
+fn get_saved_room_entries(saved_rooms: &[SavedRoom], active_entries: &[RoomBrowserEntry]) -> Vec<RoomBrowserEntry> {
+    return saved_rooms.iter().map(|saved_room| {
+        let (participants, is_private) = match active_entries.iter().find(|entry| entry.room_id.eq(&saved_room.room_id)) {
+            Some(entry) => (entry.participants, entry.is_private),
+            None => (0, false)
+        };
+        RoomBrowserEntry {
+            room_id: saved_room.room_id.to_string(),
+            name: saved_room.name.to_string(),
+            map: saved_room.map.to_string(),
+            participants,
+            is_private
+        }
+    }).collect();
+}
+
+pub fn refresh_room_browser(mut commands: Commands, scene: Res<Scene>, mut room_browser: ResMut<RoomBrowser>, mut rtc_resource: ResMut<RTCResource>, config: Res<AppConfig>){
+    if !room_browser.is_refresh_requested || room_browser.is_loading {
+        return;
+    }
+    room_browser.is_refresh_requested = false;
+    // nobody else can be found offline
+    if config.offline {
+        return;
+    }
+    room_browser.is_loading = true;
+    room_browser.status = "Loading rooms...".to_string();
+    rtc_resource.list_rooms(&mut commands, &scene.scene_uuid);
+}
+
+pub fn list_rooms_event_listener(events_query: Query<(Entity, &ListRoomsTask)>, mut commands: Commands, mut room_browser: ResMut<RoomBrowser>){
+    for (entity, list_rooms_task) in events_query.iter() {
+        room_browser.is_loading = false;
+        match &list_rooms_task.rooms {
+            Ok(rooms) => {
+                room_browser.entries = rooms.iter().map(|room| {
+                    // rooms created by other tools may carry no metadata
+                    let (name, map, is_private) = match serde_json::from_str::<RoomMetadata>(&room.metadata) {
+                        Ok(metadata) => {
+                            let is_private = metadata.is_private();
+                            (metadata.name, metadata.map, is_private)
+                        },
+                        Err(_) => (String::new(), String::new(), false)
+                    };
+                    RoomBrowserEntry {
+                        room_id: room.room_id.to_string(),
+                        name,
+                        map,
+                        participants: room.participants,
+                        is_private
+                    }
+                }).collect();
+                // busiest rooms first
+                room_browser.entries.sort_by(|a, b| b.participants.cmp(&a.participants));
+                room_browser.status = String::new();
+            },
+            Err(e) => {
+                println!("list rooms failed: {}", e);
+                room_browser.status = "Could not load the room list".to_string();
+            }
+        }
+        commands.entity(entity).despawn();
+    }
+}
+
 pub fn setup_select_user_skin(mut commands: Commands, assets_server: Res<AssetServer>, scene: Res<Scene>, mut character_animation: Res<CharacterAnimation>, mut room_data: ResMut<RoomData>, wardrobe_resources: Res<WardrobeResources>, mut map_data: ResMut<MapData>, mut s3_data: ResMut<S3Data>) {
 
     room_data.room_users.clear();
 
     map_data.is_map_loaded = false;
-    load_tilemap_from_s3(&mut commands, &scene.scene_uuid, &mut s3_data, "shalloville".to_string(), "0".to_string());
+    let mut map_name = map_data.map_name.to_string();
+    if map_name.is_empty() {
+        map_name = "0".to_string();
+    }
+    load_tilemap_from_s3(&mut commands, &scene.scene_uuid, &mut s3_data, map_name);
 
     let uuid = Uuid::new_v4().to_string();
 
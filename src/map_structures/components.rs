@@ -5,17 +5,17 @@ pub struct MapStructure;
 
 #[derive(Component)]
 pub struct SpriteTag {
-    pub index: i8,
+    pub index: i32,
     pub pos: i32
 }
 
 #[derive(Component)]
 pub struct AnimatedTag {
-    pub index: i8,
+    pub index: i32,
     pub pos: i32,
-    pub current: i8,
-    pub last: i8,
-    pub increment: i8
+    pub current: i32,
+    pub last: i32,
+    pub increment: i32
 }
 
 #[derive(Component, Clone, PartialEq, Eq)]
@@ -23,7 +23,7 @@ pub struct InteractiveTrigger {
     pub trigger: Vec<(KeyCode, InteractiveType)>
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum InteractiveType {
     None,
     SwitchScreenShare,
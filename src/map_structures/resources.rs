@@ -1,8 +1,22 @@
 use std::collections::HashMap;
-use bevy::prelude::*;
+use bevy::{asset::io::file::FileAssetReader, prelude::*};
 use bevy_entitiles::{render::material::StandardTilemapMaterial, tilemap::map::TilemapTextures};
 use ::serde::{Deserialize, Serialize};
 
+use crate::{config::resources::AppConfig, utils::{from_grid_xy_to_index, from_index_to_grid_xy, group_numbers}};
+
+pub const MAP_FILE_VERSION: u32 = 3;
+pub const EMPTY_TILE: i32 = -1;
+// tilemaps only take a whole z index, so every layer order gets its own step between -MAX_LAYER_ORDER and MAX_LAYER_ORDER
+pub const MAX_LAYER_ORDER: i32 = 50;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BrushTool {
+    Drag,
+    Rectangle,
+    FloodFill
+}
+
 #[derive(Resource)]
 pub struct MapData {
     pub map_name: String,
@@ -29,6 +43,8 @@ pub struct MapData {
     pub tile_texture: Option<Handle<TilemapTextures>>,
 
     pub place_tile_index: String,
+    pub place_layer_index: usize,
+    pub brush_tool: BrushTool,
     pub place_entity_index: String,
     pub place_animated_index: String,
     pub place_info_index: String,
@@ -38,11 +54,20 @@ pub struct MapData {
     pub info_window_title: String,
     pub info_window_content: String,
 
-    pub atlas_hashmap: HashMap<String, (Handle<Image>, Handle<TextureAtlasLayout>, i8)>,
+    pub atlas_hashmap: HashMap<String, (Handle<Image>, Handle<TextureAtlasLayout>, i32)>,
+
+    // number of images under assets/sprite and assets/animated_sprite, for the palette
+    pub static_sprite_count: i32,
+    pub animated_sprite_count: i32
 }
 
-impl Default for MapData{
-    fn default() -> MapData {
+// tile and atlas sizes come from the config file
+impl FromWorld for MapData {
+    fn from_world(world: &mut World) -> Self {
+        let config = match world.get_resource::<AppConfig>() {
+            Some(config) => config.clone(),
+            None => AppConfig::default()
+        };
         MapData {
             map_name: "".to_string(),
             tile_atlas_name: "".to_string(),
@@ -53,15 +78,17 @@ impl Default for MapData{
             map_row_str: "0".to_string(),
             map_col: 0,
 
-            tile_atlas_width: 640,
-            tile_atlas_height: 128,
+            tile_atlas_width: config.tile_atlas_width,
+            tile_atlas_height: config.tile_atlas_height,
 
-            tile_width: 64.0,
-            tile_height: 64.0,
+            tile_width: config.tile_width,
+            tile_height: config.tile_height,
 
             max_y: 0.0,
 
             place_tile_index: "0".to_string(),
+            place_layer_index: 0,
+            brush_tool: BrushTool::Drag,
             place_entity_index: "0".to_string(),
             place_animated_index: "0".to_string(),
             place_info_index: "0".to_string(),
@@ -74,6 +101,9 @@ impl Default for MapData{
 
             atlas_hashmap: HashMap::new(),
 
+            static_sprite_count: count_numbered_images("sprite"),
+            animated_sprite_count: count_numbered_images("animated_sprite"),
+
             is_map_loaded: false,
             spawn_pos: 0,
 
@@ -84,10 +114,53 @@ impl Default for MapData{
 
 }
 
+// sprites are numbered 0.png, 1.png, ... so the first missing number ends the folder
+fn count_numbered_images(folder: &str) -> i32 {
+    let dir = FileAssetReader::get_base_path().join("assets").join(folder);
+    let mut count = 0;
+    while dir.join(format!("{}.png", count)).is_file() {
+        count += 1;
+    }
+    return count;
+}
+
+pub struct MapLayer {
+    pub name: String,
+    pub order: i32,
+    pub is_overlay: bool,
+    pub tilemap: Option<Entity>,
+    pub tiles: Vec<i32>
+}
+
+impl MapLayer {
+    pub fn new(name: &str, order: i32, is_overlay: bool, tiles: Vec<i32>) -> MapLayer {
+        MapLayer {
+            name: name.to_string(),
+            order: order,
+            is_overlay: is_overlay,
+            tilemap: None,
+            tiles: tiles
+        }
+    }
+
+    // characters use max_y - y as depth, so base layers sit below 0 and overlays above max_y
+    pub fn get_depth(&self, max_y: f32) -> f32 {
+        let order = self.order.clamp(-MAX_LAYER_ORDER, MAX_LAYER_ORDER);
+        if self.is_overlay {
+            return max_y.ceil() + 5.0 + (MAX_LAYER_ORDER + order) as f32;
+        }
+        return -max_y.ceil() - 10.0 - (MAX_LAYER_ORDER - order) as f32;
+    }
+}
+
+// screens and buttons placed on the map are drawn above every overlay layer
+pub fn get_ui_depth(max_y: f32) -> f32 {
+    return max_y.ceil() + 10.0 + (MAX_LAYER_ORDER * 2) as f32;
+}
+
 #[derive(Resource)]
 pub struct MapStructures {
-    pub tilemap: Option<Entity>,
-    pub tiles: Vec<i32>,
+    pub layers: Vec<MapLayer>,
     pub sprite: HashMap<i32, Entity>,
     pub animated_sprite: HashMap<i32, Entity>,
     pub hitbox: HashMap<i32, (bool, Option<Entity>)>,
@@ -97,8 +170,7 @@ pub struct MapStructures {
 impl Default for MapStructures {
     fn default() -> MapStructures {
         MapStructures {
-            tilemap: None,
-            tiles: Vec::new(),
+            layers: Vec::new(),
             sprite: HashMap::new(),
             animated_sprite: HashMap::new(),
             hitbox: HashMap::new(),
@@ -107,6 +179,7 @@ impl Default for MapStructures {
     }
 }
 
+// v1 map format, kept so that old rooms can still be read and upgraded
 #[derive(Serialize, Deserialize, Debug)]
 pub struct MapSaver {
     pub col: i32,
@@ -123,4 +196,241 @@ impl Default for MapSaver {
     fn default() -> MapSaver {
         return MapSaver { col: 0, row: 0, tilemap: String::new(), sprites: String::new(), animated_sprites: String::new(), hitbox: String::new(), tilesheet: String::new(), info: String::new() }
     }
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MapEntityKind {
+    Static,
+    Animated
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MapEntity {
+    pub kind: MapEntityKind,
+    pub x: i32,
+    pub y: i32,
+    pub index: i32
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MapFileLayer {
+    pub name: String,
+    pub order: i32,
+    pub overlay: bool,
+    pub tiles: Vec<i32>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MapFile {
+    pub version: u32,
+    pub col: i32,
+    pub row: i32,
+    pub tilesheet: String,
+    pub layers: Vec<MapFileLayer>,
+    pub entities: Vec<MapEntity>,
+    pub hitbox: Vec<(i32, i32)>,
+    pub info: HashMap<String, Vec<(String, String)>>
+}
+
+impl Default for MapFile {
+    fn default() -> MapFile {
+        MapFile {
+            version: MAP_FILE_VERSION,
+            col: 0,
+            row: 0,
+            tilesheet: String::new(),
+            layers: Vec::new(),
+            entities: Vec::new(),
+            hitbox: Vec::new(),
+            info: HashMap::new()
+        }
+    }
+}
+
+impl MapFile {
+    // accepts every known version and upgrades it to the current one
+    pub fn parse(data: &str) -> Result<MapFile, String> {
+        let mut value: serde_json::Value = serde_json::from_str(data).map_err(|e| format!("Invalid map file: {}", e))?;
+
+        let version = match value.get("version") {
+            Some(version) => version.as_u64().ok_or("Map file version is not a number".to_string())? as u32,
+            None => 1
+        };
+
+        match version {
+            1 => {
+                let map_saver: MapSaver = serde_json::from_value(value).map_err(|e| format!("Invalid v1 map file: {}", e))?;
+                MapFile::from_v1(map_saver)
+            },
+            2 => {
+                // v2 had a single tile array, which becomes the floor layer
+                if let Some(object) = value.as_object_mut() {
+                    let tiles = object.remove("tiles").unwrap_or(serde_json::Value::Array(Vec::new()));
+                    object.insert("layers".to_string(), serde_json::json!([{ "name": "floor", "order": 0, "overlay": false, "tiles": tiles }]));
+                    object.insert("version".to_string(), serde_json::json!(MAP_FILE_VERSION));
+                }
+                let map_file: MapFile = serde_json::from_value(value).map_err(|e| format!("Invalid v2 map file: {}", e))?;
+                Ok(map_file)
+            },
+            MAP_FILE_VERSION => {
+                let map_file: MapFile = serde_json::from_value(value).map_err(|e| format!("Invalid v{} map file: {}", version, e))?;
+                Ok(map_file)
+            },
+            _ => Err(format!("Unsupported map file version: {}", version))
+        }
+    }
+
+    pub fn from_v1(map_saver: MapSaver) -> Result<MapFile, String> {
+        let mut map_file = MapFile {
+            col: map_saver.col,
+            row: map_saver.row,
+            tilesheet: map_saver.tilesheet,
+            ..default()
+        };
+
+        let mut tiles: Vec<i32> = Vec::new();
+        for group in group_numbers(map_saver.tilemap, 1)?.iter() {
+            tiles.push(group[0] as i32);
+        }
+        map_file.layers.push(MapFileLayer { name: "floor".to_string(), order: 0, overlay: false, tiles });
+
+        for group in group_numbers(map_saver.sprites, 2)?.iter() {
+            let (x, y) = from_index_to_grid_xy(group[0] as i32, map_file.col);
+            map_file.entities.push(MapEntity { kind: MapEntityKind::Static, x, y, index: group[1] as i32 });
+        }
+
+        for group in group_numbers(map_saver.animated_sprites, 2)?.iter() {
+            let (x, y) = from_index_to_grid_xy(group[0] as i32, map_file.col);
+            map_file.entities.push(MapEntity { kind: MapEntityKind::Animated, x, y, index: group[1] as i32 });
+        }
+
+        for group in group_numbers(map_saver.hitbox, 1)?.iter() {
+            map_file.hitbox.push(from_index_to_grid_xy(group[0] as i32, map_file.col));
+        }
+
+        if !map_saver.info.is_empty() {
+            map_file.info = serde_json::from_str(&map_saver.info).map_err(|e| format!("Invalid v1 map info: {}", e))?;
+        }
+
+        Ok(map_file)
+    }
+
+    pub fn to_pos(&self, x: i32, y: i32) -> i32 {
+        return from_grid_xy_to_index(x as f32, y as f32, self.col as f32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn v1_map_is_upgraded() {
+        let data = r#"{
+            "col": 3,
+            "row": 2,
+            "tilemap": "0 1 2 3 4 5",
+            "tilesheet": "tiles",
+            "sprites": "4 1",
+            "animated_sprites": "2 0",
+            "hitbox": "1 5",
+            "info": "{\"3\": [[\"title\", \"hello\"]]}"
+        }"#;
+        let map_file = MapFile::parse(data).unwrap();
+
+        assert_eq!(map_file.version, MAP_FILE_VERSION);
+        assert_eq!((map_file.col, map_file.row), (3, 2));
+        assert_eq!(map_file.tilesheet, "tiles");
+        assert_eq!(map_file.layers.len(), 1);
+        assert_eq!(map_file.layers[0].name, "floor");
+        assert_eq!(map_file.layers[0].order, 0);
+        assert!(!map_file.layers[0].overlay);
+        assert_eq!(map_file.layers[0].tiles, Vec::from([0, 1, 2, 3, 4, 5]));
+        assert_eq!(map_file.entities, Vec::from([
+            MapEntity { kind: MapEntityKind::Static, x: 1, y: 1, index: 1 },
+            MapEntity { kind: MapEntityKind::Animated, x: 2, y: 0, index: 0 }
+        ]));
+        assert_eq!(map_file.hitbox, Vec::from([(1, 0), (2, 1)]));
+        assert_eq!(map_file.info.get("3"), Some(&Vec::from([("title".to_string(), "hello".to_string())])));
+    }
+
+    #[test]
+    fn empty_v1_map_is_upgraded() {
+        let data = r#"{"col": 2, "row": 2, "tilemap": "", "tilesheet": "", "sprites": "", "animated_sprites": "", "hitbox": "", "info": ""}"#;
+        let map_file = MapFile::parse(data).unwrap();
+
+        assert_eq!(map_file.layers.len(), 1);
+        assert!(map_file.layers[0].tiles.is_empty());
+        assert!(map_file.entities.is_empty());
+        assert!(map_file.hitbox.is_empty());
+        assert!(map_file.info.is_empty());
+    }
+
+    #[test]
+    fn v2_map_is_upgraded() {
+        let data = r#"{
+            "version": 2,
+            "col": 2,
+            "row": 2,
+            "tilesheet": "tiles",
+            "tiles": [3, -1, 0, 7],
+            "entities": [{"kind": "animated", "x": 1, "y": 0, "index": 0}],
+            "hitbox": [[0, 1]],
+            "info": {}
+        }"#;
+        let map_file = MapFile::parse(data).unwrap();
+
+        assert_eq!(map_file.version, MAP_FILE_VERSION);
+        assert_eq!(map_file.layers.len(), 1);
+        assert_eq!(map_file.layers[0].name, "floor");
+        assert_eq!(map_file.layers[0].tiles, Vec::from([3, EMPTY_TILE, 0, 7]));
+        assert_eq!(map_file.entities, Vec::from([MapEntity { kind: MapEntityKind::Animated, x: 1, y: 0, index: 0 }]));
+        assert_eq!(map_file.hitbox, Vec::from([(0, 1)]));
+    }
+
+    #[test]
+    fn current_map_round_trips() {
+        let mut map_file = MapFile {
+            col: 2,
+            row: 1,
+            tilesheet: "tiles".to_string(),
+            ..default()
+        };
+        map_file.layers.push(MapFileLayer { name: "floor".to_string(), order: 0, overlay: false, tiles: Vec::from([1, 2]) });
+        map_file.layers.push(MapFileLayer { name: "roof".to_string(), order: 5, overlay: true, tiles: Vec::from([EMPTY_TILE, 4]) });
+
+        let parsed = MapFile::parse(&serde_json::to_string(&map_file).unwrap()).unwrap();
+        assert_eq!(parsed.layers.len(), 2);
+        assert_eq!(parsed.layers[1].name, "roof");
+        assert!(parsed.layers[1].overlay);
+        assert_eq!(parsed.layers[1].tiles, Vec::from([EMPTY_TILE, 4]));
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        assert!(MapFile::parse(r#"{"version": 99}"#).is_err());
+        assert!(MapFile::parse(r#"{"version": "3"}"#).is_err());
+        assert!(MapFile::parse("not json").is_err());
+    }
+
+    #[test]
+    fn layer_orders_get_whole_depth_steps_outside_the_characters() {
+        let max_y = 320.0;
+        for order in -MAX_LAYER_ORDER..MAX_LAYER_ORDER {
+            for is_overlay in [false, true] {
+                let depth = MapLayer::new("layer", order, is_overlay, Vec::new()).get_depth(max_y);
+                let next_depth = MapLayer::new("layer", order + 1, is_overlay, Vec::new()).get_depth(max_y);
+                assert_eq!(depth.fract(), 0.0);
+                assert_eq!(next_depth - depth, 1.0);
+                if is_overlay {
+                    assert!(depth > max_y && next_depth < get_ui_depth(max_y));
+                } else {
+                    assert!(next_depth < 0.0);
+                }
+            }
+        }
+        // orders past the limit share the last step
+        assert_eq!(MapLayer::new("layer", 80, true, Vec::new()).get_depth(max_y), MapLayer::new("layer", MAX_LAYER_ORDER, true, Vec::new()).get_depth(max_y));
+    }
+}
@@ -1,13 +1,15 @@
 use bevy::prelude::*;
 use bevy::render::render_resource::FilterMode;
-use bevy_entitiles::prelude::{TilemapTexture, TilemapTextureDescriptor};
+use bevy_entitiles::prelude::{TileBuilder, TileLayer, TilemapStorage, TilemapTexture, TilemapTextureDescriptor};
 use bevy_entitiles::render::material::StandardTilemapMaterial;
 use bevy_entitiles::tilemap::map::TilemapTextures;
 
+use crate::game::components::Depth;
+use crate::utils::{from_index_to_grid_xy, from_index_to_xy};
 use crate::AnimationTimer;
 
-use super::components::{AnimatedTag, InteractiveType};
-use super::resources::MapData;
+use super::components::{AnimatedTag, InteractiveType, SpriteTag};
+use super::resources::{MapData, MapEntityKind, MapStructures, EMPTY_TILE};
 
 pub fn load_spritesheet(asset_server: Res<AssetServer>, mut map_data: ResMut<MapData>, mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>, mut materials: ResMut<Assets<StandardTilemapMaterial>>, mut textures: ResMut<Assets<TilemapTextures>>) {
     // tilemap
@@ -15,7 +17,7 @@ pub fn load_spritesheet(asset_server: Res<AssetServer>, mut map_data: ResMut<Map
     let layout = TextureAtlasLayout::from_grid(UVec2::new(map_data.tile_width as u32, map_data.tile_height as u32), (map_data.tile_atlas_width / map_data.tile_width as i32) as u32, (map_data.tile_atlas_height / map_data.tile_height as i32) as u32, None, None);
     let layout_count = layout.len() - 1;
     let layout_handle = texture_atlas_layouts.add(layout);
-    map_data.atlas_hashmap.insert("tile".to_string(), (image_handle.clone(), layout_handle, layout_count as i8));
+    map_data.atlas_hashmap.insert("tile".to_string(), (image_handle.clone(), layout_handle, layout_count as i32));
 
     let tile_material = materials.add(StandardTilemapMaterial::default());
     let tile_texture =  textures.add(TilemapTextures::single(
@@ -30,16 +32,15 @@ pub fn load_spritesheet(asset_server: Res<AssetServer>, mut map_data: ResMut<Map
     map_data.tile_texture = Some(tile_texture);
 }
 
-pub fn load_animated_sprite(asset_server: &mut Res<AssetServer>, map_data: &mut ResMut<MapData>, texture_atlas_layouts: &mut ResMut<Assets<TextureAtlasLayout>>) 
--> Option<(Handle<Image>, Handle<TextureAtlasLayout>, i8)>
+pub fn load_animated_sprite(asset_server: &mut Res<AssetServer>, map_data: &mut ResMut<MapData>, texture_atlas_layouts: &mut ResMut<Assets<TextureAtlasLayout>>, index: &str) 
+-> Option<(Handle<Image>, Handle<TextureAtlasLayout>, i32)>
 {
-    let index = map_data.place_animated_index.clone();
     if let Some((image_handle, layout_handle, image_count)) = map_data.atlas_hashmap.get(&format!("anim{}", index)) {
-        return Some((image_handle.clone_weak(), layout_handle.clone_weak(), image_count.clone() as i8));
+        return Some((image_handle.clone_weak(), layout_handle.clone_weak(), image_count.clone()));
     }
-    if let Some(layout) = get_animated_sprite_layout(&index) {
+    if let Some(layout) = get_animated_sprite_layout(index) {
         let image_handle: Handle<Image> = asset_server.load(format!("animated_sprite/{}.png", index));
-        let image_count = (layout.len() - 1) as i8;
+        let image_count = (layout.len() - 1) as i32;
         let layout_handle = texture_atlas_layouts.add(layout);
         
         let image_handle_clone = image_handle.clone_weak();
@@ -60,6 +61,129 @@ pub fn load_static_sprite(asset_server: &mut Res<AssetServer>, index: String)
     return image_handle;
 }
 
+// returns the tile index that was replaced
+pub fn set_map_tile(commands: &mut Commands, map_data: &MapData, map_structures: &mut MapStructures, tilemap_query: &mut Query<&mut TilemapStorage>, layer_index: usize, pos: i32, index: i32) -> Option<i32> {
+    if let Some(layer) = map_structures.layers.get_mut(layer_index) {
+        if let Some(tilemap) = layer.tilemap {
+            if let Ok(mut tilemap_storage) = tilemap_query.get_mut(tilemap) {
+                if let Some(before) = layer.tiles.get(pos as usize).cloned() {
+                    let (x, y) = from_index_to_grid_xy(pos, map_data.map_col);
+                    if index.eq(&EMPTY_TILE) {
+                        tilemap_storage.remove(commands, IVec2 { x, y });
+                    } else {
+                        tilemap_storage.set(
+                            commands,
+                            IVec2 { x, y },
+                            TileBuilder::new()
+                                .with_layer(0, TileLayer::no_flip(index))
+                        );
+                    }
+                    layer.tiles[pos as usize] = index;
+                    return Some(before);
+                }
+            }
+        }
+    }
+    return None;
+}
+
+// returns whether the position was a hitbox before
+pub fn set_map_hitbox(commands: &mut Commands, map_data: &MapData, map_structures: &mut MapStructures, pos: i32, is_hitbox: bool) -> bool {
+    let before = match map_structures.hitbox.get(&pos) {
+        Some((is_hitbox, _)) => *is_hitbox,
+        None => false
+    };
+
+    if is_hitbox && !before {
+        if let Some((image_handle, texture_atlas, _count)) = map_data.atlas_hashmap.get("tile") {
+            let (x, y) = from_index_to_xy(pos, map_data.tile_width as i32, map_data.tile_height as i32, map_data.map_col);
+            let hitbox =
+            commands.spawn((
+                SpriteBundle {
+                    transform: Transform::from_xyz(x as f32, y as f32, 1.0),
+                    texture: image_handle.clone_weak(),
+                    ..default()
+                },
+                TextureAtlas {
+                    layout: texture_atlas.clone_weak(),
+                    index: 0
+                }
+            )).id();
+            map_structures.hitbox.insert(pos, (true, Some(hitbox)));
+        }
+    } else if !is_hitbox && before {
+        if let Some((_, Some(hitbox))) = map_structures.hitbox.get(&pos) {
+            commands.entity(*hitbox).despawn_recursive();
+        }
+        map_structures.hitbox.remove(&pos);
+    }
+    return before;
+}
+
+// replaces whatever entity of the same kind sits at pos, or only removes it when index is None
+pub fn set_map_entity(commands: &mut Commands, asset_server: &mut Res<AssetServer>, map_data: &mut ResMut<MapData>, texture_atlas_layouts: &mut ResMut<Assets<TextureAtlasLayout>>, map_structures: &mut MapStructures, kind: MapEntityKind, pos: i32, index: Option<i32>) {
+    let placed = match kind {
+        MapEntityKind::Static => &mut map_structures.sprite,
+        MapEntityKind::Animated => &mut map_structures.animated_sprite
+    };
+    if let Some(sprite) = placed.remove(&pos) {
+        commands.entity(sprite).despawn_recursive();
+    }
+
+    if let Some(index) = index {
+        let (x, y) = from_index_to_xy(pos, map_data.tile_width as i32, map_data.tile_height as i32, map_data.map_col);
+        let (x, y) = (x as f32, y as f32);
+        match kind {
+            MapEntityKind::Static => {
+                let image_handle = load_static_sprite(asset_server, index.to_string());
+                let sprite =
+                commands.spawn((
+                    SpriteBundle {
+                        transform: Transform::from_xyz(x, y, 0.0),
+                        texture: image_handle,
+                        ..default()
+                    },
+                    SpriteTag {
+                        index: index,
+                        pos: pos
+                    },
+                    Depth {
+                        index: map_data.max_y - y
+                    }
+                )).id();
+                map_structures.sprite.insert(pos, sprite);
+            },
+            MapEntityKind::Animated => {
+                if let Some((image_handle, layout_handle, image_count)) = load_animated_sprite(asset_server, map_data, texture_atlas_layouts, &index.to_string()) {
+                    let sprite =
+                    commands.spawn((
+                        SpriteBundle {
+                            transform: Transform::from_xyz(x, y, 0.0),
+                            texture: image_handle.clone_weak(),
+                            ..default()
+                        },
+                        TextureAtlas {
+                            layout: layout_handle.clone_weak(),
+                            index: 0
+                        },
+                        AnimatedTag {
+                            index: index,
+                            pos: pos,
+                            current: 0,
+                            last: image_count,
+                            increment: 1
+                        },
+                        Depth {
+                            index: map_data.max_y - y
+                        }
+                    )).id();
+                    map_structures.animated_sprite.insert(pos, sprite);
+                }
+            }
+        }
+    }
+}
+
 pub fn update_animated_sprite(mut sprite_query: Query<(&mut AnimatedTag, &mut TextureAtlas)>, time: Res<Time>, mut local_timer: Local<AnimationTimer>){
     local_timer.collasped_timer += time.delta_seconds();
     if local_timer.collasped_timer > 0.2 {
@@ -1,4 +1,7 @@
 use bevy::prelude::*;
 
 #[derive(Component)]
-pub struct MouseTag {}
\ No newline at end of file
+pub struct MouseTag {}
+
+#[derive(Component)]
+pub struct BrushAreaTag {}
\ No newline at end of file
@@ -17,8 +17,12 @@ impl Plugin for MousePlugin {
     fn build(&self, app: &mut App) {
         app
         .init_resource::<MouseData>()
+        .init_resource::<BrushStroke>()
         .init_state::<FocusState>()
         .add_systems(Startup, init_mouse)
+        .add_systems(OnExit(FocusState::Game), cancel_brush_stroke)
+        .add_systems(OnExit(TilemapEditorState::PlaceTile), cancel_brush_stroke)
+        .add_systems(OnExit(TilemapEditorState::PlaceHitbox), cancel_brush_stroke)
         .add_systems(Update, update_window_size)
         .add_systems(Update, update_mouse_pointer)
         .add_systems(Update, onclick_place_tile.run_if(in_state(TilemapEditorState::PlaceTile).and_then(in_state(FocusState::Game))))
@@ -26,4 +26,12 @@ impl Default for MouseData{
             mouse_y: 0.
         }
     }
-}
\ No newline at end of file
+}
+
+// shared by the tile and hitbox brushes, dropped whenever the map loses focus so a release over the UI is never committed
+#[derive(Resource, Default)]
+pub struct BrushStroke {
+    pub start: Option<(i32, i32)>,
+    pub last: Option<(i32, i32)>,
+    pub is_erasing: bool
+}
@@ -1,8 +1,8 @@
 use bevy::{prelude::*, window::WindowResized};
-use bevy_entitiles::prelude::{TileBuilder, TileLayer, TilemapStorage};
+use bevy_entitiles::prelude::TilemapStorage;
 
-use crate::{camera::components::CameraTag, game::components::Depth, systems::{load_animated_sprite, load_static_sprite}, map_structures::{components::{AnimatedTag, SpriteTag}, resources::{MapData, MapStructures}}, utils::{from_grid_xy_to_index, from_xy_to_grid}, TilemapEditorState};
-use super::{components::MouseTag, MouseData};
+use crate::{camera::components::CameraTag, editor::resources::{EditorAction, EditorHistory}, systems::{set_map_entity, set_map_hitbox, set_map_tile}, map_structures::{components::{AnimatedTag, SpriteTag}, resources::{BrushTool, MapData, MapEntityKind, MapStructures, EMPTY_TILE}}, utils::{flood_fill, from_grid_xy_to_index, from_xy_to_grid, grid_line, grid_rect}, TilemapEditorState};
+use super::{components::{BrushAreaTag, MouseTag}, resources::BrushStroke, MouseData};
 
 
 pub fn init_mouse(mut commands: Commands, asset_server: Res<AssetServer>, mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>){
@@ -26,6 +26,19 @@ pub fn init_mouse(mut commands: Commands, asset_server: Res<AssetServer>, mut te
         },
         MouseTag {}
     ));
+
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::srgba(1.0, 1.0, 1.0, 0.3),
+                custom_size: Some(Vec2::ONE),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        BrushAreaTag {}
+    ));
 }
 
 pub fn update_window_size(mut resize_reader: EventReader<WindowResized>, mut mouse_data: ResMut<MouseData>){
@@ -56,64 +69,126 @@ pub fn update_mouse_pointer(map_data: Res<MapData>, mut mouse_data: ResMut<Mouse
     }
 }
 
-pub fn onclick_place_tile(mut commands: Commands, buttons: Res<ButtonInput<MouseButton>>, mouse_data: Res<MouseData>, map_data: Res<MapData>, mut map_structures: ResMut<MapStructures>, mut tilemap_query: Query<&mut TilemapStorage>){
-    
-    if buttons.pressed(MouseButton::Left) {
-        if mouse_data.grid_x as i32 >= map_data.map_col || mouse_data.grid_y as i32 >= map_data.map_row || mouse_data.grid_x < 0.0 || mouse_data.grid_y < 0.0 {
-            return;
-        }
-        let pos = from_grid_xy_to_index(mouse_data.grid_x, mouse_data.grid_y, map_data.map_col as f32) as usize;
-        if let Some(tilemap) = map_structures.tilemap {
-            if let Ok(mut tilemap_storage) = tilemap_query.get_mut(tilemap) {
-                let index = match map_data.place_tile_index.parse::<i32>() {
-                    Ok(n) => {n},
-                    Err(_) => {0},
-                };
-                tilemap_storage.set(
-                    &mut commands,
-                    IVec2 { x: mouse_data.grid_x as i32, y: mouse_data.grid_y as i32 },
-                    TileBuilder::new()
-                        .with_layer(0, TileLayer::no_flip(index))
-                );
-                map_structures.tiles[pos] = index;
+pub fn cancel_brush_stroke(mut brush_stroke: ResMut<BrushStroke>, mut brush_area_query: Query<&mut Visibility, With<BrushAreaTag>>){
+    brush_stroke.start = None;
+    brush_stroke.last = None;
+    if let Ok(mut visibility) = brush_area_query.get_single_mut() {
+        *visibility = Visibility::Hidden;
+    }
+}
+
+// turns the current brush tool and mouse state into the map positions to paint this frame
+fn collect_brush_cells(buttons: &ButtonInput<MouseButton>, mouse_data: &MouseData, map_data: &MapData, brush_stroke: &mut BrushStroke, brush_area_query: &mut Query<(&mut Transform, &mut Sprite, &mut Visibility), With<BrushAreaTag>>, get_value: impl Fn(i32) -> i32) -> Vec<i32> {
+    let is_pressed = buttons.pressed(MouseButton::Left) || buttons.pressed(MouseButton::Right);
+    let is_just_pressed = buttons.just_pressed(MouseButton::Left) || buttons.just_pressed(MouseButton::Right);
+    if is_pressed {
+        brush_stroke.is_erasing = buttons.pressed(MouseButton::Right);
+    }
+
+    let grid = (mouse_data.grid_x as i32, mouse_data.grid_y as i32);
+    let is_inside = grid.0 < map_data.map_col && grid.1 < map_data.map_row && mouse_data.grid_x >= 0.0 && mouse_data.grid_y >= 0.0;
+    let to_index = |(x, y): (i32, i32)| from_grid_xy_to_index(x as f32, y as f32, map_data.map_col as f32);
+
+    match map_data.brush_tool {
+        BrushTool::Drag => {
+            if !is_pressed || !is_inside {
+                brush_stroke.last = None;
+                return Vec::new();
+            }
+            let start = brush_stroke.last.unwrap_or(grid);
+            brush_stroke.last = Some(grid);
+            return grid_line(start, grid).into_iter().map(to_index).collect();
+        },
+        BrushTool::Rectangle => {
+            if is_just_pressed && is_inside {
+                brush_stroke.start = Some(grid);
+            }
+            if let Some(start) = brush_stroke.start {
+                let end = (grid.0.clamp(0, map_data.map_col - 1), grid.1.clamp(0, map_data.map_row - 1));
+                if let Ok((mut transform, mut sprite, mut visibility)) = brush_area_query.get_single_mut() {
+                    if is_pressed {
+                        *visibility = Visibility::Visible;
+                        transform.translation = Vec3::new((start.0 + end.0) as f32 * 0.5 * map_data.tile_width, (start.1 + end.1) as f32 * 0.5 * map_data.tile_height, 6.0);
+                        sprite.custom_size = Some(Vec2::new(((start.0 - end.0).abs() + 1) as f32 * map_data.tile_width, ((start.1 - end.1).abs() + 1) as f32 * map_data.tile_height));
+                    } else {
+                        *visibility = Visibility::Hidden;
+                    }
+                }
+                if is_pressed {
+                    return Vec::new();
+                }
+                brush_stroke.start = None;
+                return grid_rect(start, end).into_iter().map(to_index).collect();
             }
+            return Vec::new();
+        },
+        BrushTool::FloodFill => {
+            if !is_just_pressed || !is_inside {
+                return Vec::new();
+            }
+            let target = get_value(to_index(grid));
+            return flood_fill(grid, map_data.map_col, map_data.map_row, |pos| get_value(pos) == target);
         }
     }
 }
 
-pub fn onclick_place_hitbox(mut commands: Commands, buttons: Res<ButtonInput<MouseButton>>, mouse_data: Res<MouseData>, map_data: Res<MapData>, mut tile_storage: ResMut<MapStructures>){
-    
-    if buttons.pressed(MouseButton::Left) {
-        if mouse_data.grid_x as i32 >= map_data.map_col || mouse_data.grid_y as i32 >= map_data.map_row || mouse_data.grid_x < 0.0 || mouse_data.grid_y < 0.0 {
-            return;
+pub fn onclick_place_tile(mut commands: Commands, buttons: Res<ButtonInput<MouseButton>>, mouse_data: Res<MouseData>, map_data: Res<MapData>, mut map_structures: ResMut<MapStructures>, mut tilemap_query: Query<&mut TilemapStorage>, mut editor_history: ResMut<EditorHistory>, mut brush_area_query: Query<(&mut Transform, &mut Sprite, &mut Visibility), With<BrushAreaTag>>, mut brush_stroke: ResMut<BrushStroke>){
+    let layer = map_data.place_layer_index;
+    let cells = match map_structures.layers.get(layer) {
+        Some(map_layer) => collect_brush_cells(&buttons, &mouse_data, &map_data, &mut brush_stroke, &mut brush_area_query, |pos| map_layer.tiles.get(pos as usize).cloned().unwrap_or(EMPTY_TILE)),
+        None => Vec::new()
+    };
+
+    let index = match brush_stroke.is_erasing {
+        true => EMPTY_TILE,
+        false => match map_data.place_tile_index.parse::<i32>() {
+            Ok(n) => {n},
+            Err(_) => {0},
         }
-        let x = mouse_data.grid_x * map_data.tile_width;
-        let y = mouse_data.grid_y * map_data.tile_height;
-        let pos = from_grid_xy_to_index(mouse_data.grid_x, mouse_data.grid_y, map_data.map_col as f32);
-        match tile_storage.hitbox.get_mut(&pos) {
-            None => {
-                if let Some((image_handle, texture_atlas, _count)) = map_data.atlas_hashmap.get("tile") {
-                    let hitbox =
-                    commands.spawn((
-                        SpriteBundle {
-                            transform: Transform::from_xyz(x, y, 1.0),
-                            texture: image_handle.clone_weak(),
-                            ..default()
-                        },
-                        TextureAtlas {
-                            layout: texture_atlas.clone_weak(),
-                            index: 0
-                        }
-                    )).id();
-                    tile_storage.hitbox.insert(pos, (true, Some(hitbox)));
-                }
-            },
-            Some(_) => {}
+    };
+    let history_name = match map_data.brush_tool {
+        BrushTool::Drag => "Paint Tile",
+        BrushTool::Rectangle => "Fill Tile Rectangle",
+        BrushTool::FloodFill => "Flood Fill Tile"
+    };
+
+    for pos in cells.iter() {
+        if let Some(before) = set_map_tile(&mut commands, &map_data, &mut map_structures, &mut tilemap_query, layer, *pos, index) {
+            if before.ne(&index) {
+                editor_history.push(history_name, EditorAction::Tile { layer, pos: *pos, before, after: index });
+            }
+        }
+    }
+    if map_data.brush_tool.ne(&BrushTool::Drag) && !cells.is_empty() {
+        editor_history.close_entry();
+    }
+}
+
+pub fn onclick_place_hitbox(mut commands: Commands, buttons: Res<ButtonInput<MouseButton>>, mouse_data: Res<MouseData>, map_data: Res<MapData>, mut tile_storage: ResMut<MapStructures>, mut editor_history: ResMut<EditorHistory>, mut brush_area_query: Query<(&mut Transform, &mut Sprite, &mut Visibility), With<BrushAreaTag>>, mut brush_stroke: ResMut<BrushStroke>){
+    let cells = collect_brush_cells(&buttons, &mouse_data, &map_data, &mut brush_stroke, &mut brush_area_query, |pos| match tile_storage.hitbox.get(&pos) {
+        Some((is_hitbox, _)) => *is_hitbox as i32,
+        None => 0
+    });
+
+    let is_hitbox = !brush_stroke.is_erasing;
+    let history_name = match map_data.brush_tool {
+        BrushTool::Drag => "Paint Hitbox",
+        BrushTool::Rectangle => "Fill Hitbox Rectangle",
+        BrushTool::FloodFill => "Flood Fill Hitbox"
+    };
+
+    for pos in cells.iter() {
+        let before = set_map_hitbox(&mut commands, &map_data, &mut tile_storage, *pos, is_hitbox);
+        if before.ne(&is_hitbox) {
+            editor_history.push(history_name, EditorAction::Hitbox { pos: *pos, before, after: is_hitbox });
         }
     }
+    if map_data.brush_tool.ne(&BrushTool::Drag) && !cells.is_empty() {
+        editor_history.close_entry();
+    }
 }
 
-pub fn onclick_place_entity(mut commands: Commands, buttons: Res<ButtonInput<MouseButton>>, mouse_data: Res<MouseData>, mut map_data: ResMut<MapData>, mut asset_server: Res<AssetServer>, mut tilemap_editor_state: ResMut<NextState<TilemapEditorState>>, mut tile_storage: ResMut<MapStructures>){
+pub fn onclick_place_entity(mut commands: Commands, buttons: Res<ButtonInput<MouseButton>>, mouse_data: Res<MouseData>, mut map_data: ResMut<MapData>, mut asset_server: Res<AssetServer>, mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>, mut tilemap_editor_state: ResMut<NextState<TilemapEditorState>>, mut tile_storage: ResMut<MapStructures>, sprite_query: Query<&SpriteTag>, mut editor_history: ResMut<EditorHistory>){
     
     if buttons.pressed(MouseButton::Left) {
         tilemap_editor_state.set(TilemapEditorState::Free);
@@ -122,40 +197,20 @@ pub fn onclick_place_entity(mut commands: Commands, buttons: Res<ButtonInput<Mou
             return;
         }
         
-        let x = mouse_data.grid_x * map_data.tile_width;
-        let y = mouse_data.grid_y * map_data.tile_height;
         let pos = from_grid_xy_to_index(mouse_data.grid_x, mouse_data.grid_y, map_data.map_col as f32);
-        let index = match map_data.place_entity_index.parse::<i8>() {
+        let index = match map_data.place_entity_index.parse::<i32>() {
             Ok(n) => {n},
             Err(_) => {0},
         };
-        if let Some(sprite) = tile_storage.sprite.get(&pos) {
-            commands.entity(*sprite).despawn();
-            tile_storage.sprite.remove(&pos);
-        }
-
+        let before = tile_storage.sprite.get(&pos).and_then(|sprite| sprite_query.get(*sprite).ok()).map(|tag| tag.index);
 
-        let image_handle = load_static_sprite(&mut asset_server, map_data.place_entity_index.clone());
-        let sprite =
-        commands.spawn((
-            SpriteBundle {
-                transform: Transform::from_xyz(x, y, 0.0),
-                texture: image_handle,
-                ..default()
-            },
-            SpriteTag {
-                index: index,
-                pos: pos
-            },
-            Depth {
-                index: map_data.max_y - y
-            }
-        )).id();
-        tile_storage.sprite.insert(pos, sprite);
+        set_map_entity(&mut commands, &mut asset_server, &mut map_data, &mut texture_atlas_layouts, &mut tile_storage, MapEntityKind::Static, pos, Some(index));
+        editor_history.push("Place Entity", EditorAction::Entity { kind: MapEntityKind::Static, pos, before, after: Some(index) });
+        editor_history.close_entry();
     }
 }
 
-pub fn onclick_place_animated_entity(mut commands: Commands, buttons: Res<ButtonInput<MouseButton>>, mouse_data: Res<MouseData>, mut map_data: ResMut<MapData>, mut asset_server: Res<AssetServer>, mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>, mut tilemap_editor_state: ResMut<NextState<TilemapEditorState>>, mut tile_storage: ResMut<MapStructures>){
+pub fn onclick_place_animated_entity(mut commands: Commands, buttons: Res<ButtonInput<MouseButton>>, mouse_data: Res<MouseData>, mut map_data: ResMut<MapData>, mut asset_server: Res<AssetServer>, mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>, mut tilemap_editor_state: ResMut<NextState<TilemapEditorState>>, mut tile_storage: ResMut<MapStructures>, animated_sprite_query: Query<&AnimatedTag>, mut editor_history: ResMut<EditorHistory>){
     
     if buttons.pressed(MouseButton::Left) {
         tilemap_editor_state.set(TilemapEditorState::Free);
@@ -164,47 +219,17 @@ pub fn onclick_place_animated_entity(mut commands: Commands, buttons: Res<Button
             return;
         }
 
-        if let Some((image_handle, layout_handle, image_count)) = load_animated_sprite(&mut asset_server, &mut map_data, &mut texture_atlas_layouts) {
-            let x = mouse_data.grid_x * map_data.tile_width;
-            let y = mouse_data.grid_y * map_data.tile_height;
-            let index = match map_data.place_animated_index.parse::<i8>() {
-                Ok(n) => {n},
-                Err(_) => {0},
-            };
-            let pos = from_grid_xy_to_index(mouse_data.grid_x, mouse_data.grid_y, map_data.map_col as f32);
-
-            if let Some(sprite) = tile_storage.animated_sprite.get(&pos) {
-                commands.entity(*sprite).despawn();
-                tile_storage.animated_sprite.remove(&pos);
-            }
+        let index = match map_data.place_animated_index.parse::<i32>() {
+            Ok(n) => {n},
+            Err(_) => {0},
+        };
+        let pos = from_grid_xy_to_index(mouse_data.grid_x, mouse_data.grid_y, map_data.map_col as f32);
+        let before = tile_storage.animated_sprite.get(&pos).and_then(|sprite| animated_sprite_query.get(*sprite).ok()).map(|tag| tag.index);
 
-            let sprite =
-            commands.spawn((
-                SpriteBundle {
-                    transform: Transform::from_xyz(x, y, 0.0),
-                    texture: image_handle.clone_weak(),
-                    ..default()
-                },
-                TextureAtlas {
-                    layout: layout_handle.clone_weak(),
-                    index: 0
-                },
-                AnimatedTag {
-                    index: index,
-                    pos: pos,
-                    current: 0,
-                    last: image_count,
-                    increment: 1
-                },
-                Depth {
-                    index: map_data.max_y - y
-                }
-            )).id();
-            tile_storage.animated_sprite.insert(pos, sprite);
-        }}
-        else {
-            return;
-        }
+        set_map_entity(&mut commands, &mut asset_server, &mut map_data, &mut texture_atlas_layouts, &mut tile_storage, MapEntityKind::Animated, pos, Some(index));
+        editor_history.push("Place Animated Entity", EditorAction::Entity { kind: MapEntityKind::Animated, pos, before, after: Some(index) });
+        editor_history.close_entry();
+    }
 }
 
 pub fn onclick_place_info(buttons: Res<ButtonInput<MouseButton>>, mouse_data: Res<MouseData>, mut map_data: ResMut<MapData>, mut tilemap_editor_state: ResMut<NextState<TilemapEditorState>>) {
@@ -222,19 +247,20 @@ pub fn onclick_place_info(buttons: Res<ButtonInput<MouseButton>>, mouse_data: Re
     }
 }
 
-pub fn onpress_remove_entity(mut commands: Commands, keys: Res<ButtonInput<KeyCode>>, map_data: Res<MapData>, mouse_data: Res<MouseData>, mut map_structures: ResMut<MapStructures>){
+pub fn onpress_remove_entity(mut commands: Commands, keys: Res<ButtonInput<KeyCode>>, mut map_data: ResMut<MapData>, mouse_data: Res<MouseData>, mut asset_server: Res<AssetServer>, mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>, mut map_structures: ResMut<MapStructures>, sprite_query: Query<&SpriteTag>, animated_sprite_query: Query<&AnimatedTag>, mut editor_history: ResMut<EditorHistory>){
     if keys.just_pressed(KeyCode::KeyX) {
         if mouse_data.grid_x as i32 >= map_data.map_col || mouse_data.grid_y as i32 >= map_data.map_row || mouse_data.grid_x < 0.0 || mouse_data.grid_y < 0.0 {
             return;
         }
-        let index = from_grid_xy_to_index(mouse_data.grid_x, mouse_data.grid_y, map_data.map_col as f32);
-        if let Some(sprite) = map_structures.sprite.get(&index) {
-            commands.entity(*sprite).despawn_recursive();
-            map_structures.sprite.remove(&index);
+        let pos = from_grid_xy_to_index(mouse_data.grid_x, mouse_data.grid_y, map_data.map_col as f32);
+        if let Some(tag) = map_structures.sprite.get(&pos).and_then(|sprite| sprite_query.get(*sprite).ok()) {
+            editor_history.push("Remove Entity", EditorAction::Entity { kind: MapEntityKind::Static, pos, before: Some(tag.index), after: None });
+            set_map_entity(&mut commands, &mut asset_server, &mut map_data, &mut texture_atlas_layouts, &mut map_structures, MapEntityKind::Static, pos, None);
         }
-        if let Some(animated_sprite) = map_structures.animated_sprite.get(&index) {
-            commands.entity(*animated_sprite).despawn_recursive();
-            map_structures.animated_sprite.remove(&index);
+        if let Some(tag) = map_structures.animated_sprite.get(&pos).and_then(|sprite| animated_sprite_query.get(*sprite).ok()) {
+            editor_history.push("Remove Entity", EditorAction::Entity { kind: MapEntityKind::Animated, pos, before: Some(tag.index), after: None });
+            set_map_entity(&mut commands, &mut asset_server, &mut map_data, &mut texture_atlas_layouts, &mut map_structures, MapEntityKind::Animated, pos, None);
         }
+        editor_history.close_entry();
     }
-}
\ No newline at end of file
+}
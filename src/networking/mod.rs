@@ -14,7 +14,7 @@ pub struct NetworkingPlugin;
 impl Plugin for NetworkingPlugin {
     fn build(&self, app: &mut App) {
         app
-        .add_systems(Startup, setup_aws_clients)
+        .add_systems(Startup, (setup_storage_backend, setup_room_transport))
         .add_plugins(S3Plugin)
         .add_plugins(RTCPlugin);
 
@@ -0,0 +1,337 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+use super::metadata::RoomMetadata;
+
+pub const INVITE_TTL_SECONDS: u64 = 24 * 60 * 60;
+// the metadata can be read by anyone who lists the rooms, so make guessing slow
+const PASSWORD_HASH_ROUNDS: u32 = 10_000;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RoomAccess {
+    Granted,
+    PasswordRequired,
+    WrongPassword,
+    InviteRequired,
+    InviteExpired,
+    InvalidInvite,
+    // someone else already joined under this identity
+    IdentityInUse,
+    // the token server or LiveKit refused this identity, never returned by check_room_access
+    Denied
+}
+
+impl RoomAccess {
+    // how the token server names a refused join, read back by the client
+    pub fn get_code(&self) -> &'static str {
+        match self {
+            RoomAccess::Granted => return "granted",
+            RoomAccess::PasswordRequired => return "password_required",
+            RoomAccess::WrongPassword => return "wrong_password",
+            RoomAccess::InviteRequired => return "invite_required",
+            RoomAccess::InviteExpired => return "invite_expired",
+            RoomAccess::InvalidInvite => return "invalid_invite",
+            RoomAccess::IdentityInUse => return "identity_in_use",
+            RoomAccess::Denied => return "denied"
+        }
+    }
+
+    pub fn from_code(code: &str) -> RoomAccess {
+        match code.trim() {
+            "granted" => return RoomAccess::Granted,
+            "password_required" => return RoomAccess::PasswordRequired,
+            "wrong_password" => return RoomAccess::WrongPassword,
+            "invite_required" => return RoomAccess::InviteRequired,
+            "invite_expired" => return RoomAccess::InviteExpired,
+            "invalid_invite" => return RoomAccess::InvalidInvite,
+            "identity_in_use" => return RoomAccess::IdentityInUse,
+            _ => return RoomAccess::Denied
+        }
+    }
+}
+
+// <room id>.<expires at, unix seconds>.<signature>, signed by the key the host made with the room
+pub struct InviteCode {
+    pub room_id: String,
+    pub expires_at: u64,
+    signature: Vec<u8>
+}
+
+impl InviteCode {
+    pub fn parse(code: &str) -> Option<InviteCode> {
+        let parts: Vec<&str> = code.trim().split('.').collect();
+        if parts.len() != 3 || parts[0].is_empty() {
+            return None;
+        }
+        return Some(InviteCode {
+            room_id: parts[0].to_string(),
+            expires_at: parts[1].parse::<u64>().ok()?,
+            signature: from_hex(parts[2])?
+        });
+    }
+
+    pub fn create(signing_key: &SigningKey, room_id: &str, ttl_seconds: u64) -> String {
+        let expires_at = get_unix_seconds() + ttl_seconds;
+        let signature = signing_key.sign(get_invite_payload(room_id, expires_at).as_bytes());
+        return format!("{}.{}.{}", room_id, expires_at, to_hex(&signature.to_bytes()));
+    }
+
+    fn is_signed_by(&self, invite_key: &str) -> bool {
+        return verify_signature(invite_key, get_invite_payload(&self.room_id, self.expires_at).as_bytes(), &self.signature);
+    }
+}
+
+fn get_invite_payload(room_id: &str, expires_at: u64) -> String {
+    return format!("{}.{}", room_id, expires_at);
+}
+
+pub fn new_signing_key() -> Result<SigningKey, String> {
+    let mut secret = [0u8; 32];
+    getrandom::getrandom(&mut secret).map_err(|e| e.to_string())?;
+    return Ok(SigningKey::from_bytes(&secret));
+}
+
+// published in the room metadata as the invite key or the host key, it can only check signatures
+pub fn get_public_key(signing_key: &SigningKey) -> String {
+    return to_hex(&signing_key.verifying_key().to_bytes());
+}
+
+// the host signs its own identity with the key it made with the room and publishes it in its attributes,
+// so a participant is only treated as the host when the signature matches the host key in the metadata
+pub fn create_host_proof(signing_key: &SigningKey, room_id: &str, identity: &str) -> String {
+    return to_hex(&signing_key.sign(get_host_payload(room_id, identity).as_bytes()).to_bytes());
+}
+
+pub fn is_host_proof_valid(host_key: &str, room_id: &str, identity: &str, host_proof: &str) -> bool {
+    if host_key.is_empty() || identity.is_empty() {
+        return false;
+    }
+    match from_hex(host_proof) {
+        Some(signature) => return verify_signature(host_key, get_host_payload(room_id, identity).as_bytes(), &signature),
+        None => return false
+    }
+}
+
+fn get_host_payload(room_id: &str, identity: &str) -> String {
+    return format!("host.{}.{}", room_id, identity);
+}
+
+fn verify_signature(public_key: &str, payload: &[u8], signature: &[u8]) -> bool {
+    let key_bytes: [u8; 32] = match from_hex(public_key).and_then(|bytes| bytes.try_into().ok()) {
+        Some(key_bytes) => key_bytes,
+        None => return false
+    };
+    let signature_bytes: [u8; 64] = match signature.try_into() {
+        Ok(signature_bytes) => signature_bytes,
+        Err(_) => return false
+    };
+    match VerifyingKey::from_bytes(&key_bytes) {
+        Ok(verifying_key) => return verifying_key.verify(payload, &Signature::from_bytes(&signature_bytes)).is_ok(),
+        Err(_) => return false
+    }
+}
+
+pub fn new_salt() -> String {
+    let mut salt = [0u8; 16];
+    if getrandom::getrandom(&mut salt).is_err() {
+        // still unique per room, just predictable
+        return uuid::Uuid::new_v4().simple().to_string();
+    }
+    return to_hex(&salt);
+}
+
+pub fn hash_password(salt: &str, password: &str) -> String {
+    let mut digest = Sha256::digest(format!("{}:{}", salt, password).as_bytes());
+    for _ in 1..PASSWORD_HASH_ROUNDS {
+        digest = Sha256::digest(digest);
+    }
+    return to_hex(&digest);
+}
+
+// rooms without a password or invite key are open; otherwise either a matching password or a valid invite lets you in
+pub fn check_room_access(metadata: &RoomMetadata, room_id: &str, password: &str, invite_code: &str) -> RoomAccess {
+    if !metadata.is_private() {
+        return RoomAccess::Granted;
+    }
+
+    if !invite_code.is_empty() && !metadata.invite_key.is_empty() {
+        if let Some(invite) = InviteCode::parse(invite_code) {
+            if invite.room_id.eq(room_id) && invite.is_signed_by(&metadata.invite_key) {
+                if invite.expires_at < get_unix_seconds() {
+                    return RoomAccess::InviteExpired;
+                }
+                return RoomAccess::Granted;
+            }
+        }
+        if password.is_empty() {
+            return RoomAccess::InvalidInvite;
+        }
+    }
+
+    // invite only
+    if metadata.password_hash.is_empty() {
+        return RoomAccess::InviteRequired;
+    }
+    if password.is_empty() {
+        return RoomAccess::PasswordRequired;
+    }
+    if hash_password(&metadata.password_salt, password).eq(&metadata.password_hash) {
+        return RoomAccess::Granted;
+    }
+    return RoomAccess::WrongPassword;
+}
+
+// a token server strips the secrets from its listings and checks them itself before handing out a join token,
+// so without them only ask for what is missing
+pub fn check_listed_room_access(metadata: &RoomMetadata, room_id: &str, password: &str, invite_code: &str) -> RoomAccess {
+    if metadata.has_secrets() {
+        return check_room_access(metadata, room_id, password, invite_code);
+    }
+    if !metadata.is_private() || !password.is_empty() || !invite_code.is_empty() {
+        return RoomAccess::Granted;
+    }
+    if metadata.has_password {
+        return RoomAccess::PasswordRequired;
+    }
+    return RoomAccess::InviteRequired;
+}
+
+fn get_unix_seconds() -> u64 {
+    return SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    return bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+    let mut bytes: Vec<u8> = Vec::new();
+    for i in (0..hex.len()).step_by(2) {
+        bytes.push(u8::from_str_radix(&hex[i..i + 2], 16).ok()?);
+    }
+    return Some(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_private_metadata(password: &str, signing_key: &SigningKey) -> RoomMetadata {
+        let mut metadata = RoomMetadata {
+            map: "map".to_string(),
+            name: String::new(),
+            host_key: String::new(),
+            password_hash: String::new(),
+            password_salt: String::new(),
+            invite_key: get_public_key(signing_key),
+            has_password: false,
+            has_invite_key: true
+        };
+        if !password.is_empty() {
+            metadata.password_salt = new_salt();
+            metadata.password_hash = hash_password(&metadata.password_salt, password);
+            metadata.has_password = true;
+        }
+        return metadata;
+    }
+
+    #[test]
+    fn valid_invite_is_granted() {
+        let signing_key = new_signing_key().unwrap();
+        let metadata = get_private_metadata("", &signing_key);
+        let invite_code = InviteCode::create(&signing_key, "room", INVITE_TTL_SECONDS);
+        assert_eq!(check_room_access(&metadata, "room", "", &invite_code), RoomAccess::Granted);
+    }
+
+    #[test]
+    fn expired_invite_is_refused() {
+        let signing_key = new_signing_key().unwrap();
+        let metadata = get_private_metadata("", &signing_key);
+        let expires_at = get_unix_seconds() - 60;
+        let signature = signing_key.sign(get_invite_payload("room", expires_at).as_bytes());
+        let invite_code = format!("room.{}.{}", expires_at, to_hex(&signature.to_bytes()));
+        assert_eq!(check_room_access(&metadata, "room", "", &invite_code), RoomAccess::InviteExpired);
+    }
+
+    #[test]
+    fn invite_for_another_room_is_refused() {
+        let signing_key = new_signing_key().unwrap();
+        let metadata = get_private_metadata("", &signing_key);
+        let invite_code = InviteCode::create(&signing_key, "other-room", INVITE_TTL_SECONDS);
+        assert_eq!(check_room_access(&metadata, "room", "", &invite_code), RoomAccess::InvalidInvite);
+    }
+
+    #[test]
+    fn tampered_invite_is_refused() {
+        let signing_key = new_signing_key().unwrap();
+        let metadata = get_private_metadata("", &signing_key);
+        let invite_code = InviteCode::create(&signing_key, "room", INVITE_TTL_SECONDS);
+
+        // flip the last hex digit of the signature
+        let mut tampered = invite_code[..invite_code.len() - 1].to_string();
+        tampered.push(if invite_code.ends_with('0') { '1' } else { '0' });
+        assert_eq!(check_room_access(&metadata, "room", "", &tampered), RoomAccess::InvalidInvite);
+
+        // a later expiry under the old signature
+        let invite = InviteCode::parse(&invite_code).unwrap();
+        let extended = invite_code.replacen(&invite.expires_at.to_string(), &(invite.expires_at + 1).to_string(), 1);
+        assert_eq!(check_room_access(&metadata, "room", "", &extended), RoomAccess::InvalidInvite);
+
+        // signed by a key the room does not know
+        let other_key = new_signing_key().unwrap();
+        let foreign_code = InviteCode::create(&other_key, "room", INVITE_TTL_SECONDS);
+        assert_eq!(check_room_access(&metadata, "room", "", &foreign_code), RoomAccess::InvalidInvite);
+    }
+
+    #[test]
+    fn password_is_checked() {
+        let signing_key = new_signing_key().unwrap();
+        let metadata = get_private_metadata("secret", &signing_key);
+        assert_eq!(check_room_access(&metadata, "room", "", ""), RoomAccess::PasswordRequired);
+        assert_eq!(check_room_access(&metadata, "room", "wrong", ""), RoomAccess::WrongPassword);
+        assert_eq!(check_room_access(&metadata, "room", "secret", ""), RoomAccess::Granted);
+    }
+
+    #[test]
+    fn invite_only_room_ignores_a_password() {
+        let signing_key = new_signing_key().unwrap();
+        let metadata = get_private_metadata("", &signing_key);
+        assert_eq!(check_room_access(&metadata, "room", "secret", ""), RoomAccess::InviteRequired);
+        assert_eq!(check_room_access(&metadata, "room", "secret", "not-an-invite"), RoomAccess::InviteRequired);
+    }
+
+    #[test]
+    fn listed_room_without_secrets_leaves_the_check_to_the_token_server() {
+        let signing_key = new_signing_key().unwrap();
+        let mut metadata = get_private_metadata("secret", &signing_key);
+        metadata.strip_secrets();
+        assert!(metadata.is_private());
+        assert_eq!(check_listed_room_access(&metadata, "room", "", ""), RoomAccess::PasswordRequired);
+        assert_eq!(check_listed_room_access(&metadata, "room", "wrong", ""), RoomAccess::Granted);
+    }
+
+    #[test]
+    fn host_proof_only_holds_for_the_signed_identity() {
+        let signing_key = new_signing_key().unwrap();
+        let host_key = get_public_key(&signing_key);
+        let host_proof = create_host_proof(&signing_key, "room", "host");
+        assert!(is_host_proof_valid(&host_key, "room", "host", &host_proof));
+
+        // copied into someone else's attributes, or into another room
+        assert!(!is_host_proof_valid(&host_key, "room", "guest", &host_proof));
+        assert!(!is_host_proof_valid(&host_key, "other-room", "host", &host_proof));
+
+        // signed by a key the room does not know
+        let other_key = new_signing_key().unwrap();
+        assert!(!is_host_proof_valid(&host_key, "room", "guest", &create_host_proof(&other_key, "room", "guest")));
+
+        // rooms without a host key have no host
+        assert!(!is_host_proof_valid("", "room", "host", &host_proof));
+        assert!(!is_host_proof_valid(&host_key, "room", "host", "not-a-proof"));
+    }
+}
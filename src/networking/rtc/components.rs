@@ -1,14 +1,73 @@
+use std::collections::HashMap;
+
 use bevy::prelude::*;
 
 use crate::wardrobe::resources::BodyParts;
 
+use super::{access::RoomAccess, resources::RoomMetadata, transport::{RoomListing, TransportError}};
+
+// what came of checking, joining or creating a room, Admitted sends the user to the wardrobe, Joined and Created into the game
+#[derive(Debug)]
+pub enum JoinOutcome {
+    Admitted,
+    Joined(RoomMetadata),
+    Created,
+    NotFound,
+    Full,
+    AuthFailed(RoomAccess),
+    Timeout,
+    NetworkError(String)
+}
+
+impl JoinOutcome {
+    pub fn from_transport_error(error: TransportError) -> JoinOutcome {
+        match error {
+            TransportError::RoomNotFound => return JoinOutcome::NotFound,
+            TransportError::RoomFull => return JoinOutcome::Full,
+            // the token server names the reason when it turns down a private room
+            TransportError::Unauthorized(reason) => return JoinOutcome::AuthFailed(RoomAccess::from_code(&reason)),
+            TransportError::Timeout => return JoinOutcome::Timeout,
+            TransportError::Network(reason) => return JoinOutcome::NetworkError(reason)
+        }
+    }
+
+    // shown above the room list when the join does not go through
+    pub fn get_message(&self) -> String {
+        match self {
+            JoinOutcome::Admitted | JoinOutcome::Joined(_) | JoinOutcome::Created => return String::new(),
+            JoinOutcome::NotFound => return "Room not found, check the room id".to_string(),
+            JoinOutcome::Full => return "This room is full".to_string(),
+            JoinOutcome::AuthFailed(access) => {
+                match access {
+                    RoomAccess::PasswordRequired => return "This room needs a password".to_string(),
+                    RoomAccess::WrongPassword => return "Wrong password".to_string(),
+                    RoomAccess::InviteRequired => return "This room is invite only".to_string(),
+                    RoomAccess::InviteExpired => return "Invite expired, ask for a new one".to_string(),
+                    RoomAccess::InvalidInvite => return "This invite is not valid for the room".to_string(),
+                    RoomAccess::IdentityInUse => return "Someone with your identity is already in this room".to_string(),
+                    RoomAccess::Denied | RoomAccess::Granted => return "The server did not let you into this room".to_string()
+                }
+            },
+            JoinOutcome::Timeout => return "The room took too long to answer, try again".to_string(),
+            JoinOutcome::NetworkError(reason) => return format!("Could not reach the room: {}", reason)
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct JoinRoomTask {
+    pub outcome: JoinOutcome
+}
+
 #[derive(Component)]
-pub struct LoadMetadataTask {
-    pub metadata: String
+pub struct ListRoomsTask {
+    pub rooms: Result<Vec<RoomListing>, TransportError>
 }
 
 #[derive(Component)]
 pub struct MultiplayerUserAttribute {
     pub username: String,
-    pub body_part: BodyParts
+    pub body_part: BodyParts,
+    // role attributes, only the host starts with any
+    pub roles: HashMap<String, String>
 }
\ No newline at end of file
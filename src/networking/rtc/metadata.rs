@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+
+// the token server includes this file as well, so it stays free of anything from the client
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RoomMetadata {
+    pub map: String,
+    // shown in the room browser, rooms made before it existed have none
+    #[serde(default)]
+    pub name: String,
+    // public key of the creator, who proves it is the host by signing its identity with it, see access.rs
+    #[serde(default)]
+    pub host_key: String,
+    // private rooms have a password, an invite key or both, see access.rs
+    #[serde(default)]
+    pub password_hash: String,
+    #[serde(default)]
+    pub password_salt: String,
+    #[serde(default)]
+    pub invite_key: String,
+    // what is left to show once a token server strips the secrets above from its listings
+    #[serde(default)]
+    pub has_password: bool,
+    #[serde(default)]
+    pub has_invite_key: bool
+}
+
+impl RoomMetadata {
+    pub fn is_private(&self) -> bool {
+        return self.has_password || self.has_invite_key || !self.password_hash.is_empty() || !self.invite_key.is_empty();
+    }
+
+    // only checked by whoever holds the secrets, everyone else gets the flags
+    pub fn has_secrets(&self) -> bool {
+        return !self.password_hash.is_empty() || !self.invite_key.is_empty();
+    }
+
+    pub fn strip_secrets(&mut self) {
+        self.has_password = self.has_password || !self.password_hash.is_empty();
+        self.has_invite_key = self.has_invite_key || !self.invite_key.is_empty();
+        self.password_hash.clear();
+        self.password_salt.clear();
+        self.invite_key.clear();
+    }
+}
@@ -9,11 +9,18 @@ use systems::*;
 pub mod resources;
 use resources::*;
 
+pub mod access;
+pub mod metadata;
+pub mod protocol;
+pub mod transport;
+
 pub mod video;
 pub mod video_renderer;
 
 use crate::game::MultiplayerRoomState;
 
+use protocol::ModerationAction;
+
 pub struct RTCPlugin;
 
 impl Plugin for RTCPlugin {
@@ -21,11 +28,22 @@ impl Plugin for RTCPlugin {
         app
         .init_resource::<RTCResource>()
         .init_state::<RoomMetadataListener>()
-        .add_systems(Update, load_room_metadata_event_listener.run_if(in_state(RoomMetadataListener::Open)))
-        .add_systems(Update, on_room_event_received.run_if(in_state(MultiplayerRoomState::Consumed)));
+        .add_event::<ModerationEvent>()
+        .add_systems(Update, join_room_event_listener.run_if(in_state(RoomMetadataListener::Open)))
+        .add_systems(Update, on_room_event_received.run_if(in_state(MultiplayerRoomState::Consumed)))
+        .add_systems(Update, reconnect_room.run_if(in_state(MultiplayerRoomState::Consumed)))
+        .add_systems(Update, apply_moderation.run_if(in_state(MultiplayerRoomState::Consumed)));
     }
 }
 
+// a moderation request from a remote participant or from this user, checked against the roles before it is applied
+#[derive(Event)]
+pub struct ModerationEvent {
+    pub sender: String,
+    pub action: ModerationAction,
+    pub target: String
+}
+
 #[repr(u8)]
 #[derive(States, Default, Debug, Clone, PartialEq, Eq, Hash, TryFromPrimitive)]
 pub enum RoomMetadataListener {
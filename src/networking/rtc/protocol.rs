@@ -0,0 +1,393 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::transport::TransportReliability;
+
+// bump when the layout of an existing message changes, peers drop packets with any other version
+pub const PROTOCOL_VERSION: u8 = 5;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RoomMessage {
+    // the display name is looked up by the receiver, it is not part of the text; range_tiles 0 is the whole room
+    Chat { kind: ChatKind, text: String, range_tiles: u8 },
+    // time_ms is the sender's clock in milliseconds since its app started
+    Move { x: f32, y: f32, time_ms: u32 },
+    Animation { name: String, x: f32, y: f32, time_ms: u32 },
+    // full avatar state, sent to participants who join after it last changed
+    Snapshot { x: f32, y: f32, scale_x: f32, animation: String },
+    // only acted on when the sender's role allows it, target is empty for room-wide actions
+    Moderate { action: ModerationAction, target: String }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModerationAction {
+    Promote,
+    Demote,
+    Kick,
+    Mute,
+    Unmute,
+    StopCamera,
+    Lock,
+    Unlock
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChatKind {
+    Message,
+    Emote,
+    // only sent to the one participant it is meant for
+    Whisper,
+    // made locally for joins, leaves and moderation, never accepted from a packet
+    System
+}
+
+impl ChatKind {
+    fn to_u8(&self) -> u8 {
+        match self {
+            ChatKind::Message => 0,
+            ChatKind::Emote => 1,
+            ChatKind::Whisper => 2,
+            ChatKind::System => 3
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<ChatKind> {
+        match value {
+            0 => return Some(ChatKind::Message),
+            1 => return Some(ChatKind::Emote),
+            2 => return Some(ChatKind::Whisper),
+            _ => return None
+        }
+    }
+}
+
+impl ModerationAction {
+    fn to_u8(&self) -> u8 {
+        match self {
+            ModerationAction::Promote => 0,
+            ModerationAction::Demote => 1,
+            ModerationAction::Kick => 2,
+            ModerationAction::Mute => 3,
+            ModerationAction::Unmute => 4,
+            ModerationAction::StopCamera => 5,
+            ModerationAction::Lock => 6,
+            ModerationAction::Unlock => 7
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<ModerationAction> {
+        match value {
+            0 => return Some(ModerationAction::Promote),
+            1 => return Some(ModerationAction::Demote),
+            2 => return Some(ModerationAction::Kick),
+            3 => return Some(ModerationAction::Mute),
+            4 => return Some(ModerationAction::Unmute),
+            5 => return Some(ModerationAction::StopCamera),
+            6 => return Some(ModerationAction::Lock),
+            7 => return Some(ModerationAction::Unlock),
+            _ => return None
+        }
+    }
+}
+
+impl RoomMessage {
+    pub fn topic(&self) -> &'static str {
+        match self {
+            RoomMessage::Chat { .. } => "chat",
+            RoomMessage::Move { .. } => "move",
+            RoomMessage::Animation { .. } => "anime",
+            RoomMessage::Snapshot { .. } => "snapshot",
+            RoomMessage::Moderate { .. } => "moderate"
+        }
+    }
+
+    pub fn reliability(&self) -> TransportReliability {
+        match self {
+            RoomMessage::Move { .. } => TransportReliability::Lossy,
+            _ => TransportReliability::Reliable
+        }
+    }
+
+    fn tag(&self) -> u8 {
+        match self {
+            RoomMessage::Chat { .. } => 0,
+            RoomMessage::Move { .. } => 1,
+            RoomMessage::Animation { .. } => 2,
+            RoomMessage::Snapshot { .. } => 3,
+            RoomMessage::Moderate { .. } => 4
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    Empty,
+    UnsupportedVersion(u8),
+    UnknownMessage(u8),
+    UnknownAction(u8),
+    UnknownChatKind(u8),
+    Truncated,
+    InvalidText,
+    InvalidNumber
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Empty => write!(f, "empty packet"),
+            DecodeError::UnsupportedVersion(version) => write!(f, "unsupported protocol version {}", version),
+            DecodeError::UnknownMessage(tag) => write!(f, "unknown message type {}", tag),
+            DecodeError::UnknownAction(action) => write!(f, "unknown moderation action {}", action),
+            DecodeError::UnknownChatKind(kind) => write!(f, "unknown chat kind {}", kind),
+            DecodeError::Truncated => write!(f, "packet is truncated"),
+            DecodeError::InvalidText => write!(f, "text is not utf-8"),
+            DecodeError::InvalidNumber => write!(f, "number is not finite")
+        }
+    }
+}
+
+// layout: [version u8][tag u8][sender string][message fields], strings are a u16 length followed by utf-8
+#[derive(Clone, Debug)]
+pub struct RoomPacket {
+    pub sender: String,
+    pub message: RoomMessage
+}
+
+impl RoomPacket {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::from([PROTOCOL_VERSION, self.message.tag()]);
+        write_string(&mut bytes, &self.sender);
+
+        match &self.message {
+            RoomMessage::Chat { kind, text, range_tiles } => {
+                bytes.push(kind.to_u8());
+                write_string(&mut bytes, text);
+                bytes.push(*range_tiles);
+            },
+            RoomMessage::Move { x, y, time_ms } => {
+                bytes.extend_from_slice(&x.to_le_bytes());
+                bytes.extend_from_slice(&y.to_le_bytes());
+                bytes.extend_from_slice(&time_ms.to_le_bytes());
+            },
+            RoomMessage::Animation { name, x, y, time_ms } => {
+                write_string(&mut bytes, name);
+                bytes.extend_from_slice(&x.to_le_bytes());
+                bytes.extend_from_slice(&y.to_le_bytes());
+                bytes.extend_from_slice(&time_ms.to_le_bytes());
+            },
+            RoomMessage::Snapshot { x, y, scale_x, animation } => {
+                bytes.extend_from_slice(&x.to_le_bytes());
+                bytes.extend_from_slice(&y.to_le_bytes());
+                bytes.extend_from_slice(&scale_x.to_le_bytes());
+                write_string(&mut bytes, animation);
+            },
+            RoomMessage::Moderate { action, target } => {
+                bytes.push(action.to_u8());
+                write_string(&mut bytes, target);
+            }
+        }
+        return bytes;
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<RoomPacket, DecodeError> {
+        let mut reader = PacketReader {
+            bytes,
+            offset: 0
+        };
+
+        let version = reader.read_u8().map_err(|_| DecodeError::Empty)?;
+        if version != PROTOCOL_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let tag = reader.read_u8()?;
+        let sender = reader.read_string()?;
+
+        let message = match tag {
+            0 => {
+                let kind = reader.read_u8()?;
+                RoomMessage::Chat {
+                    kind: ChatKind::from_u8(kind).ok_or(DecodeError::UnknownChatKind(kind))?,
+                    text: reader.read_string()?,
+                    range_tiles: reader.read_u8()?
+                }
+            },
+            1 => RoomMessage::Move {
+                x: reader.read_f32()?,
+                y: reader.read_f32()?,
+                time_ms: reader.read_u32()?
+            },
+            2 => RoomMessage::Animation {
+                name: reader.read_string()?,
+                x: reader.read_f32()?,
+                y: reader.read_f32()?,
+                time_ms: reader.read_u32()?
+            },
+            3 => RoomMessage::Snapshot {
+                x: reader.read_f32()?,
+                y: reader.read_f32()?,
+                scale_x: reader.read_f32()?,
+                animation: reader.read_string()?
+            },
+            4 => {
+                let action = reader.read_u8()?;
+                RoomMessage::Moderate {
+                    action: ModerationAction::from_u8(action).ok_or(DecodeError::UnknownAction(action))?,
+                    target: reader.read_string()?
+                }
+            },
+            _ => return Err(DecodeError::UnknownMessage(tag))
+        };
+
+        return Ok(RoomPacket {
+            sender,
+            message
+        });
+    }
+}
+
+fn write_string(bytes: &mut Vec<u8>, value: &str) {
+    // longer strings are cut at the last char boundary that fits the u16 length
+    let mut end = value.len().min(u16::MAX as usize);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    bytes.extend_from_slice(&(end as u16).to_le_bytes());
+    bytes.extend_from_slice(&value.as_bytes()[..end]);
+}
+
+struct PacketReader<'a> {
+    bytes: &'a [u8],
+    offset: usize
+}
+
+impl<'a> PacketReader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.offset + len > self.bytes.len() {
+            return Err(DecodeError::Truncated);
+        }
+        let slice = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+        return Ok(slice);
+    }
+
+    fn read_u8(&mut self) -> Result<u8, DecodeError> {
+        return Ok(self.read_bytes(1)?[0]);
+    }
+
+    fn read_u32(&mut self) -> Result<u32, DecodeError> {
+        let slice = self.read_bytes(4)?;
+        return Ok(u32::from_le_bytes([slice[0], slice[1], slice[2], slice[3]]));
+    }
+
+    fn read_f32(&mut self) -> Result<f32, DecodeError> {
+        let slice = self.read_bytes(4)?;
+        let value = f32::from_le_bytes([slice[0], slice[1], slice[2], slice[3]]);
+        if !value.is_finite() {
+            return Err(DecodeError::InvalidNumber);
+        }
+        return Ok(value);
+    }
+
+    fn read_string(&mut self) -> Result<String, DecodeError> {
+        let len_bytes = self.read_bytes(2)?;
+        let len = u16::from_le_bytes([len_bytes[0], len_bytes[1]]) as usize;
+        let slice = self.read_bytes(len)?;
+        return String::from_utf8(slice.to_vec()).map_err(|_| DecodeError::InvalidText);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_packets() -> Vec<RoomPacket> {
+        let mut messages: Vec<RoomMessage> = Vec::new();
+        for kind in [ChatKind::Message, ChatKind::Emote, ChatKind::Whisper] {
+            messages.push(RoomMessage::Chat { kind, text: "héllo".to_string(), range_tiles: 5 });
+        }
+        messages.push(RoomMessage::Chat { kind: ChatKind::Message, text: String::new(), range_tiles: 0 });
+        messages.push(RoomMessage::Move { x: 12.5, y: -3.0, time_ms: 123456 });
+        messages.push(RoomMessage::Animation { name: "walk".to_string(), x: 0.0, y: 64.0, time_ms: u32::MAX });
+        messages.push(RoomMessage::Snapshot { x: 1.0, y: 2.0, scale_x: -1.0, animation: "idle".to_string() });
+        for action in 0..8 {
+            messages.push(RoomMessage::Moderate { action: ModerationAction::from_u8(action).unwrap(), target: "someone".to_string() });
+        }
+        messages.push(RoomMessage::Moderate { action: ModerationAction::Lock, target: String::new() });
+
+        return messages.into_iter().map(|message| RoomPacket {
+            sender: "sender".to_string(),
+            message
+        }).collect();
+    }
+
+    #[test]
+    fn every_message_round_trips() {
+        for packet in get_packets() {
+            let decoded = RoomPacket::decode(&packet.encode()).unwrap();
+            assert_eq!(decoded.sender, packet.sender);
+            assert_eq!(decoded.message, packet.message);
+        }
+    }
+
+    #[test]
+    fn other_protocol_version_is_rejected() {
+        let mut bytes = get_packets()[0].encode();
+        bytes[0] = PROTOCOL_VERSION + 1;
+        assert!(matches!(RoomPacket::decode(&bytes), Err(DecodeError::UnsupportedVersion(version)) if version == PROTOCOL_VERSION + 1));
+    }
+
+    #[test]
+    fn truncated_packets_are_rejected() {
+        assert!(matches!(RoomPacket::decode(&[]), Err(DecodeError::Empty)));
+        for packet in get_packets() {
+            let bytes = packet.encode();
+            for len in 1..bytes.len() {
+                assert!(matches!(RoomPacket::decode(&bytes[..len]), Err(DecodeError::Truncated)), "{:?} cut at {}", packet.message, len);
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_tags_are_rejected() {
+        let mut bytes = get_packets()[0].encode();
+        bytes[1] = 200;
+        assert!(matches!(RoomPacket::decode(&bytes), Err(DecodeError::UnknownMessage(200))));
+
+        // system messages are made locally, a packet can't carry one
+        let mut bytes = get_packets()[0].encode();
+        let kind_offset = 2 + 2 + "sender".len();
+        bytes[kind_offset] = ChatKind::System.to_u8();
+        assert!(matches!(RoomPacket::decode(&bytes), Err(DecodeError::UnknownChatKind(3))));
+
+        let mut bytes = RoomPacket {
+            sender: "sender".to_string(),
+            message: RoomMessage::Moderate { action: ModerationAction::Kick, target: String::new() }
+        }.encode();
+        bytes[kind_offset] = 99;
+        assert!(matches!(RoomPacket::decode(&bytes), Err(DecodeError::UnknownAction(99))));
+    }
+
+    #[test]
+    fn non_finite_numbers_are_rejected() {
+        for value in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let messages = [
+                RoomMessage::Move { x: value, y: 0.0, time_ms: 0 },
+                RoomMessage::Animation { name: "walk".to_string(), x: 0.0, y: value, time_ms: 0 },
+                RoomMessage::Snapshot { x: 0.0, y: 0.0, scale_x: value, animation: "idle".to_string() }
+            ];
+            for message in messages {
+                let bytes = RoomPacket { sender: "sender".to_string(), message }.encode();
+                assert!(matches!(RoomPacket::decode(&bytes), Err(DecodeError::InvalidNumber)));
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_text_is_rejected() {
+        let mut bytes = Vec::from([PROTOCOL_VERSION, 0]);
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&[0xff, 0xfe]);
+        assert!(matches!(RoomPacket::decode(&bytes), Err(DecodeError::InvalidText)));
+    }
+}
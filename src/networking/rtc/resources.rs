@@ -2,102 +2,192 @@ use bevy::ecs::world::CommandQueue;
 use bevy::prelude::*;
 use image::RgbaImage;
 
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use bevy::tasks::AsyncComputeTaskPool;
-use flume::{bounded, Receiver, Sender};
-use livekit::prelude::*;
-use livekit::prelude::DataPacketKind;
-
-use livekit_api::services::room::UpdateParticipantOptions;
-use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc::UnboundedReceiver;
+use flume::Receiver;
 
 use std::collections::HashMap;
-use std::{env, sync::Arc};
+use std::sync::Arc;
 use parking_lot::Mutex;
 
-use livekit_api::{access_token, services::room::{CreateRoomOptions, RoomClient, SendDataOptions}};
-
-use crate::networking::rtc::components::LoadMetadataTask;
+use crate::networking::rtc::access::{check_listed_room_access, new_salt, RoomAccess};
+use crate::networking::rtc::components::{JoinOutcome, JoinRoomTask, ListRoomsTask};
 use crate::networking::s3::components::ComputeTask;
 
 use super::components::MultiplayerUserAttribute;
-use super::video::DeviceVideoTrack;
-use super::video_renderer::{self, VideoRenderer};
+pub use super::metadata::RoomMetadata;
+use super::protocol::{RoomMessage, RoomPacket};
+use super::transport::{livekit_transport::LiveKitTransport, RoomConnection, RoomTransport, TransportConnectOptions, TransportError, TransportEvent, MAX_ROOM_PARTICIPANTS};
+use super::video_renderer::VideoRenderer;
 
 #[derive(Resource)]
 pub struct RTCResource {
-    pub room: Arc<Mutex<Option<Room>>>,
-    pub stop_room_sender: Option<flume::Sender<bool>>,
-    pub room_event: Option<Receiver<livekit::RoomEvent>>,
+    pub transport: Arc<dyn RoomTransport>,
+    pub connection: Arc<Mutex<Option<Box<dyn RoomConnection>>>>,
+    pub room_event: Option<Receiver<TransportEvent>>,
 
     topic_cooldown: HashMap<String, (u128, Option<Instant>)>,
 
-    video_tracks: Arc<Mutex<HashMap<String, DeviceVideoTrack>>>,
+    session: Option<RoomSession>,
+    pub reconnect: ReconnectState,
+
     published_video_tracks: HashMap<String, VideoRenderer>
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct RoomMetadata {
-    pub map: String
+// what is needed to connect to the current room again after the connection drops
+#[derive(Clone)]
+struct RoomSession {
+    room_id: String,
+    user_uuid: String,
+    // only the creator recreates the room, in case it closed while everyone was gone
+    create_metadata: Option<String>,
+    password: String,
+    invite_code: String,
+    session_key: String,
+    attributes: HashMap<String, String>
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct RoomSentMessage {
-    pub a: String,
-    pub b: String
+pub const MAX_RECONNECT_ATTEMPTS: u32 = 6;
+const RECONNECT_BASE_DELAY_MILLIS: u64 = 1000;
+const RECONNECT_MAX_DELAY_MILLIS: u64 = 16000;
+
+pub struct ReconnectState {
+    pub is_reconnecting: bool,
+    // set while the transport resumes the session on its own, before it gives up and reports Disconnected
+    pub is_resuming: bool,
+    pub attempt: u32,
+    next_attempt_at: Option<Instant>,
+    pending: Option<(Receiver<Result<Box<dyn RoomConnection>, TransportError>>, Receiver<TransportEvent>)>
 }
 
-impl RTCResource {
-
-    pub fn new_room(&mut self, room_id: String, user_uuid: String, metadata: Option<String>, user_attribute: MultiplayerUserAttribute) {
-        if self.room.lock().is_some() {
-            return;
+impl Default for ReconnectState {
+    fn default() -> ReconnectState {
+        ReconnectState {
+            is_reconnecting: false,
+            is_resuming: false,
+            attempt: 0,
+            next_attempt_at: None,
+            pending: None
         }
+    }
+}
+
+#[derive(PartialEq, Eq)]
+pub enum ReconnectPoll {
+    Idle,
+    Waiting,
+    Reconnected,
+    GaveUp
+}
 
-        let room_arc = Arc::clone(&self.room);
+impl RTCResource {
 
-        let (stop_room_sender, stop_room_receiver) = bounded::<bool>(1);
-        self.stop_room_sender = Some(stop_room_sender);
+    pub fn set_transport(&mut self, transport: Arc<dyn RoomTransport>) {
+        println!("room transport: {}", transport.name());
+        self.transport = transport;
+    }
 
-        let (room_data_sender, room_data_receiver) = bounded::<livekit::RoomEvent>(1);
-        self.room_event = Some(room_data_receiver);
-        
-        std::thread::spawn(move || {
-            tokio::runtime::Runtime::new().unwrap().block_on(async {
+    // the creator waits for a JoinRoomTask, Created opens the game and anything else is shown in the lobby
+    pub fn new_room(&mut self, commands: &mut Commands, scene_uuid: &str, room_id: String, user_uuid: String, metadata: Option<String>, password: &str, invite_code: &str, user_attribute: MultiplayerUserAttribute) {
+        let options = match self.start_new_room_session(room_id, user_uuid, metadata, password, invite_code, user_attribute) {
+            Some(options) => options,
+            None => return
+        };
+        let transport = Arc::clone(&self.transport);
+        let connection_arc = Arc::clone(&self.connection);
 
-                let (url, api_key, api_secret, https_url) = get_livekit_env();
+        let thread_pool = AsyncComputeTaskPool::get();
+        let task_entity = commands.spawn_empty().id();
 
-                let room_service = RoomClient::with_api_key(&https_url, &api_key, &api_secret);
+        let task = thread_pool.spawn(async move {
+            let mut command_queue = CommandQueue::default();
 
-                let mut room_metadata = String::new();
-                if let Some(metadata) = metadata {
-                    room_metadata = metadata;
+            let outcome = match transport.connect(options) {
+                Ok(connection) => {
+                    *connection_arc.lock() = Some(connection);
+                    JoinOutcome::Created
+                },
+                Err(e) => {
+                    println!("create room failed: {}", e);
+                    JoinOutcome::from_transport_error(e)
                 }
-                let _ = room_service
-                .create_room(&room_id, CreateRoomOptions {
-                    empty_timeout: 30,
-                    metadata: room_metadata,
-                    ..default()
+            };
+
+            command_queue.push(move |world: &mut World| {
+                world
+                .entity_mut(task_entity)
+                .insert(JoinRoomTask {
+                    outcome
                 })
-                .await;
+                .remove::<ComputeTask>();
+            });
+            return command_queue;
+        });
+        commands.entity(task_entity).insert(ComputeTask((scene_uuid.to_string(), task)));
+    }
 
-                let (room, rx) = connect(&room_id, &user_uuid, &url, &api_key, &api_secret, true).await;
+    // bots have no scene to report to, a room that can't be created ends their event channel and shows up as Disconnected
+    pub fn new_room_in_background(&mut self, room_id: String, user_uuid: String, metadata: Option<String>, password: &str, invite_code: &str, user_attribute: MultiplayerUserAttribute) {
+        let options = match self.start_new_room_session(room_id, user_uuid, metadata, password, invite_code, user_attribute) {
+            Some(options) => options,
+            None => return
+        };
+        let transport = Arc::clone(&self.transport);
+        let connection_arc = Arc::clone(&self.connection);
 
-                let _ = room_service.update_participant(&room_id, &user_uuid, UpdateParticipantOptions {
-                    attributes: get_user_attribute(user_attribute),
-                    ..default()
-                }).await;
+        std::thread::spawn(move || {
+            match transport.connect(options) {
+                Ok(connection) => {
+                    *connection_arc.lock() = Some(connection);
+                },
+                Err(e) => {
+                    println!("create room failed: {}", e);
+                }
+            }
+        });
+    }
 
-                *room_arc.lock() = Some(room);
-                event(rx, stop_room_receiver, room_data_sender).await;
-            });
+    fn start_new_room_session(&mut self, room_id: String, user_uuid: String, metadata: Option<String>, password: &str, invite_code: &str, user_attribute: MultiplayerUserAttribute) -> Option<TransportConnectOptions> {
+        if self.connection.lock().is_some() {
+            return None;
+        }
+
+        let (room_event_sender, room_event_receiver) = flume::unbounded::<TransportEvent>();
+        self.room_event = Some(room_event_receiver);
+
+        let mut room_metadata = String::new();
+        if let Some(metadata) = metadata {
+            room_metadata = metadata;
+        }
+        let session = RoomSession {
+            room_id,
+            user_uuid,
+            create_metadata: Some(room_metadata),
+            password: password.to_string(),
+            invite_code: invite_code.to_string(),
+            session_key: new_salt(),
+            attributes: get_user_attribute(user_attribute)
+        };
+        self.session = Some(session.clone());
+        self.reconnect = ReconnectState::default();
+
+        return Some(TransportConnectOptions {
+            room_id: session.room_id,
+            identity: session.user_uuid,
+            create_metadata: session.create_metadata,
+            password: session.password,
+            invite_code: session.invite_code,
+            session_key: session.session_key,
+            attributes: session.attributes,
+            event_sender: room_event_sender
         });
     }
 
-    #[tokio::main]
-    pub async fn enter_wardrobe_if_room_exist(&mut self, commands: &mut Commands, scene_uuid: &str, room_id: &str) {
-        let (_, is_exist) = check_room_exist(room_id.to_string()).await;
+    pub fn enter_wardrobe_if_room_exist(&mut self, commands: &mut Commands, scene_uuid: &str, room_id: &str, password: &str, invite_code: &str) {
+        let transport = Arc::clone(&self.transport);
+        let cloned_room_id = room_id.to_string();
+        let password = password.to_string();
+        let invite_code = invite_code.to_string();
 
         let thread_pool = AsyncComputeTaskPool::get();
         let task_entity = commands.spawn_empty().id();
@@ -105,16 +195,31 @@ impl RTCResource {
         let task = thread_pool.spawn(async move {
             let mut command_queue = CommandQueue::default();
 
-            let mut metadata = ":404";
-            if is_exist {
-                metadata = ":join_room_to_wardrobe";
-            }
+            let outcome = match transport.get_room(&cloned_room_id) {
+                Ok(Some(room)) if room.participants >= MAX_ROOM_PARTICIPANTS => JoinOutcome::Full,
+                Ok(Some(room)) => {
+                    // rooms made before metadata had these fields are open
+                    let access = match serde_json::from_str::<RoomMetadata>(&room.metadata) {
+                        Ok(room_metadata) => check_listed_room_access(&room_metadata, &cloned_room_id, &password, &invite_code),
+                        Err(_) => RoomAccess::Granted
+                    };
+                    match access {
+                        RoomAccess::Granted => JoinOutcome::Admitted,
+                        access => JoinOutcome::AuthFailed(access)
+                    }
+                },
+                Ok(None) => JoinOutcome::NotFound,
+                Err(e) => {
+                    println!("room lookup failed: {}", e);
+                    JoinOutcome::from_transport_error(e)
+                }
+            };
 
             command_queue.push(move |world: &mut World| {
                 world
                 .entity_mut(task_entity)
-                .insert(LoadMetadataTask {
-                    metadata: String::from(metadata)
+                .insert(JoinRoomTask {
+                    outcome
                 })
                 .remove::<ComputeTask>();
             });
@@ -123,96 +228,135 @@ impl RTCResource {
         commands.entity(task_entity).insert(ComputeTask((scene_uuid.to_string(), task)));
     }
 
-    pub fn join_existing_room(&mut self, commands: &mut Commands, scene_uuid: &str, room_id: &str, user_uuid: &str, user_attribute: MultiplayerUserAttribute) {
-        if self.room.lock().is_some() {
-            return;
-        }
-
-        let cloned_room_id = room_id.to_string();
-        let cloned_user_uuid = user_uuid.to_string();
-
-        let cloned_room_id2 = room_id.to_string();
-        let cloned_user_uuid2 = user_uuid.to_string();
+    pub fn list_rooms(&mut self, commands: &mut Commands, scene_uuid: &str) {
+        let transport = Arc::clone(&self.transport);
 
-        let room_arc = Arc::clone(&self.room);
+        let thread_pool = AsyncComputeTaskPool::get();
+        let task_entity = commands.spawn_empty().id();
 
-        let (metadata_sender, metadata_receiver) = bounded::<String>(1);
+        let task = thread_pool.spawn(async move {
+            let mut command_queue = CommandQueue::default();
 
-        let (stop_room_sender, stop_room_receiver) = bounded::<bool>(1);
-        self.stop_room_sender = Some(stop_room_sender);
+            let rooms = transport.list_rooms();
 
-        let (room_data_sender, room_data_receiver) = bounded::<livekit::RoomEvent>(1);
-        self.room_event = Some(room_data_receiver);
+            command_queue.push(move |world: &mut World| {
+                world
+                .entity_mut(task_entity)
+                .insert(ListRoomsTask {
+                    rooms
+                })
+                .remove::<ComputeTask>();
+            });
+            return command_queue;
+        });
+        commands.entity(task_entity).insert(ComputeTask((scene_uuid.to_string(), task)));
+    }
 
-        std::thread::spawn(move || {
-            tokio::runtime::Runtime::new().unwrap().block_on(async {
-                let (url, api_key, api_secret, _) = get_livekit_env();
+    pub fn join_existing_room(&mut self, commands: &mut Commands, scene_uuid: &str, room_id: &str, user_uuid: &str, password: &str, invite_code: &str, user_attribute: MultiplayerUserAttribute) {
+        if self.connection.lock().is_some() {
+            return;
+        }
 
-                let (_, is_exist) = check_room_exist(cloned_room_id.to_string()).await;
+        let (room_event_sender, room_event_receiver) = flume::unbounded::<TransportEvent>();
+        self.room_event = Some(room_event_receiver);
+
+        let session = RoomSession {
+            room_id: room_id.to_string(),
+            user_uuid: user_uuid.to_string(),
+            create_metadata: None,
+            password: password.to_string(),
+            invite_code: invite_code.to_string(),
+            session_key: new_salt(),
+            attributes: get_user_attribute(user_attribute)
+        };
+        self.session = Some(session.clone());
+        self.reconnect = ReconnectState::default();
+
+        let options = TransportConnectOptions {
+            room_id: session.room_id,
+            identity: session.user_uuid,
+            create_metadata: None,
+            password: session.password,
+            invite_code: session.invite_code,
+            session_key: session.session_key,
+            attributes: session.attributes,
+            event_sender: room_event_sender
+        };
+        let transport = Arc::clone(&self.transport);
+        let connection_arc = Arc::clone(&self.connection);
 
-                if is_exist {
-                    let (room, rx) = connect(&cloned_room_id, &cloned_user_uuid, &url, &api_key, &api_secret, false).await;
+        let thread_pool = AsyncComputeTaskPool::get();
+        let task_entity = commands.spawn_empty().id();
 
-                    let _ = get_room_service().update_participant(&cloned_room_id2, &cloned_user_uuid2, UpdateParticipantOptions {
-                        attributes: get_user_attribute(user_attribute),
-                        ..default()
-                    }).await;
+        let task = thread_pool.spawn(async move {
+            let mut command_queue = CommandQueue::default();
 
-                    let _ = metadata_sender.send(room.metadata());    
-                    *room_arc.lock() = Some(room);
-                    event(rx, stop_room_receiver, room_data_sender).await;
-                } else {
-                    let _ = metadata_sender.send(":404".to_string());
+            let outcome = match transport.connect(options) {
+                Ok(connection) => {
+                    match serde_json::from_str::<RoomMetadata>(&connection.metadata()) {
+                        Ok(metadata) => {
+                            *connection_arc.lock() = Some(connection);
+                            JoinOutcome::Joined(metadata)
+                        },
+                        Err(e) => {
+                            connection.disconnect();
+                            JoinOutcome::NetworkError(format!("unreadable room metadata, {}", e))
+                        }
+                    }
+                },
+                Err(e) => {
+                    println!("join room failed: {}", e);
+                    JoinOutcome::from_transport_error(e)
                 }
+            };
+
+            command_queue.push(move |world: &mut World| {
+                world
+                .entity_mut(task_entity)
+                .insert(JoinRoomTask {
+                    outcome
+                })
+                .remove::<ComputeTask>();
             });
+            return command_queue;
         });
+        commands.entity(task_entity).insert(ComputeTask((scene_uuid.to_string(), task)));
+    }
 
-        if let Ok(metadata) = metadata_receiver.recv() {
-            let thread_pool = AsyncComputeTaskPool::get();
+    pub fn leave_room(&mut self) {
+        if let Some(connection) = self.connection.lock().take() {
+            connection.disconnect();
+        }
+        self.room_event = None;
+        self.session = None;
+        self.reconnect = ReconnectState::default();
 
-            let task_entity = commands.spawn_empty().id();
-        
-            let task = thread_pool.spawn(async move {
-                let mut command_queue = CommandQueue::default();
-    
-                command_queue.push(move |world: &mut World| {
-                    world
-                    .entity_mut(task_entity)
-                    .insert(LoadMetadataTask {
-                        metadata: metadata
-                    })
-                    .remove::<ComputeTask>();
-                });
-                return command_queue;
-            });
-            commands.entity(task_entity).insert(ComputeTask((scene_uuid.to_string(), task)));
+        let track_names: Vec<String> = self.published_video_tracks.keys().map(|track_name| track_name.to_string()).collect();
+        for track_name in track_names.iter() {
+            self.close_published_video_track(track_name);
         }
     }
 
-    pub fn leave_room(&mut self, room_id: String, user_uuid: String) {
-        if self.room.lock().is_none() {
-            return;
+    pub fn update_attributes(&mut self, attributes: HashMap<String, String>) {
+        // kept in the session so a reconnect publishes them again
+        if let Some(session) = &mut self.session {
+            session.attributes.extend(attributes.clone());
         }
-
-        if let Some(stop_room_sender) = &self.stop_room_sender {
-            let _ = stop_room_sender.send(true);
+        if let Some(connection) = self.connection.lock().as_ref() {
+            connection.set_attributes(attributes);
         }
-        self.stop_room_sender = None;
-        *self.room.lock() = None;
-        self.room_event = None;
-
-        std::thread::spawn(move || {
-            tokio::runtime::Runtime::new().unwrap().block_on(async {
-                let _ = get_room_service().remove_participant(&room_id, &user_uuid).await;
-            });
-        });
     }
 
-    pub fn send_message(&mut self, room_len: usize, room_id: &str, user_uuid: &str, topic: &str, message: &str, kind: DataPacketKind) {
+    pub fn send_message(&mut self, room_len: usize, user_uuid: &str, message: RoomMessage) {
         if room_len < 2 {
             return;
         }
+        self.send_message_to(user_uuid, message, Vec::new());
+    }
 
+    pub fn send_message_to(&mut self, user_uuid: &str, message: RoomMessage, destinations: Vec<String>) {
+
+        let topic = message.topic();
         if let Some((cooldown, instant_opt)) = self.topic_cooldown.get_mut(topic) {
             if let Some(instant) = instant_opt {
                 if instant.elapsed().as_millis() < *cooldown {
@@ -222,38 +366,110 @@ impl RTCResource {
             *instant_opt = Some(Instant::now());
         }
 
-        let cloned_room_id = room_id.to_string();
-        let cloned_topic = topic.to_string();
-        let cloned_message = message.to_string();
-        let cloned_user_uuid = user_uuid.to_string();
-
-        if let Ok(data) = serde_json::to_string(&RoomSentMessage {
-            a: cloned_user_uuid,
-            b: cloned_message
-        }) {
+        let reliability = message.reliability();
+        let packet = RoomPacket {
+            sender: user_uuid.to_string(),
+            message
+        };
+        if let Some(connection) = self.connection.lock().as_ref() {
+            connection.send_data(topic, packet.encode(), reliability, destinations);
+        }
+    }
+
+    // drops the dead connection without removing the participant, so the next attempt can take its place
+    pub fn begin_reconnect(&mut self) {
+        if self.session.is_none() || self.reconnect.is_reconnecting {
+            return;
+        }
+        println!("room connection lost, reconnecting");
+        self.connection.lock().take();
+        self.room_event = None;
+        self.reconnect = ReconnectState {
+            is_reconnecting: true,
+            is_resuming: false,
+            attempt: 0,
+            next_attempt_at: Some(Instant::now() + Duration::from_millis(RECONNECT_BASE_DELAY_MILLIS)),
+            pending: None
+        };
+    }
+
+    pub fn poll_reconnect(&mut self) -> ReconnectPoll {
+        if !self.reconnect.is_reconnecting {
+            return ReconnectPoll::Idle;
+        }
+
+        if let Some((result_receiver, event_receiver)) = self.reconnect.pending.take() {
+            let result = match result_receiver.try_recv() {
+                Ok(result) => result,
+                Err(flume::TryRecvError::Empty) => {
+                    self.reconnect.pending = Some((result_receiver, event_receiver));
+                    return ReconnectPoll::Waiting;
+                },
+                Err(flume::TryRecvError::Disconnected) => Err(TransportError::Network("reconnect thread stopped".to_string()))
+            };
+
+            match result {
+                Ok(connection) => {
+                    println!("room reconnected after {} attempts", self.reconnect.attempt + 1);
+                    *self.connection.lock() = Some(connection);
+                    self.room_event = Some(event_receiver);
+                    self.reconnect = ReconnectState::default();
+                    return ReconnectPoll::Reconnected;
+                },
+                Err(e) => {
+                    println!("reconnect attempt {} failed: {}", self.reconnect.attempt + 1, e);
+                    self.reconnect.attempt += 1;
+                    if self.reconnect.attempt >= MAX_RECONNECT_ATTEMPTS {
+                        self.reconnect = ReconnectState::default();
+                        self.session = None;
+                        return ReconnectPoll::GaveUp;
+                    }
+                    let delay = (RECONNECT_BASE_DELAY_MILLIS << self.reconnect.attempt).min(RECONNECT_MAX_DELAY_MILLIS);
+                    self.reconnect.next_attempt_at = Some(Instant::now() + Duration::from_millis(delay));
+                    return ReconnectPoll::Waiting;
+                }
+            }
+        }
+
+        if let Some(next_attempt_at) = self.reconnect.next_attempt_at {
+            if Instant::now() < next_attempt_at {
+                return ReconnectPoll::Waiting;
+            }
+        }
+
+        if let Some(session) = self.session.clone() {
+            let (result_sender, result_receiver) = flume::bounded::<Result<Box<dyn RoomConnection>, TransportError>>(1);
+            let (room_event_sender, room_event_receiver) = flume::unbounded::<TransportEvent>();
+            self.reconnect.pending = Some((result_receiver, room_event_receiver));
+            self.reconnect.next_attempt_at = None;
+
+            let transport = Arc::clone(&self.transport);
             std::thread::spawn(move || {
-                tokio::runtime::Runtime::new().unwrap().block_on(async {
-                    let room_service = get_room_service();
-                    let _ = room_service.send_data(&cloned_room_id, data.into_bytes(), SendDataOptions {
-                        kind: kind.into(),
-                        topic: Some(cloned_topic),
-                        ..default()
-                    }).await;
-                });
+                let _ = result_sender.send(transport.connect(TransportConnectOptions {
+                    room_id: session.room_id,
+                    identity: session.user_uuid,
+                    create_metadata: session.create_metadata,
+                    password: session.password,
+                    invite_code: session.invite_code,
+                session_key: session.session_key,
+                    attributes: session.attributes,
+                    event_sender: room_event_sender
+                }));
             });
         }
+        return ReconnectPoll::Waiting;
     }
 
     pub fn is_multiplayer(&self) -> bool {
-        if self.room.lock().is_some() {
+        if self.connection.lock().is_some() {
             return true;
         }
         return false;
     }
 
     pub fn is_video_track_exists(&self, track_name: &str) -> bool {
-        if self.video_tracks.lock().contains_key(track_name) {
-            return true;
+        if let Some(connection) = self.connection.lock().as_ref() {
+            return connection.is_video_published(track_name);
         }
         return false;
     }
@@ -262,46 +478,19 @@ impl RTCResource {
         // if room_len < 2 {
         //     return;
         // }
-        if self.room.lock().is_none(){
-            return;
+        if let Some(connection) = self.connection.lock().as_ref() {
+            connection.publish_video(track_name, image_receiver);
         }
-        if self.video_tracks.lock().contains_key(track_name) {
-            return;
-        }
-
-        let cloned_track_name = track_name.to_string();
-        let cloned_room = Arc::clone(&self.room);
-        let video_tracks_arc = Arc::clone(&self.video_tracks);
-        std::thread::spawn( move || {
-            let mut track_lock = video_tracks_arc.lock();
-            tokio::runtime::Runtime::new().unwrap().block_on(async {
-                let mut video_track = DeviceVideoTrack::new(cloned_room);
-                video_track.publish(&cloned_track_name, image_receiver).await;
-                track_lock.insert(cloned_track_name, video_track);
-            });
-        });
     }
 
     pub fn close_video_track(&mut self, track_name: &str) {
-        if !self.video_tracks.lock().contains_key(track_name) {
-            return;
+        if let Some(connection) = self.connection.lock().as_ref() {
+            connection.unpublish_video(track_name);
         }
-
-        let cloned_track_name = track_name.to_string();
-        let video_tracks_arc = Arc::clone(&self.video_tracks);
-        std::thread::spawn( move || {
-            let mut track_lock = video_tracks_arc.lock();
-            tokio::runtime::Runtime::new().unwrap().block_on(async {
-                if let Some(video_track) = track_lock.get_mut(&cloned_track_name) {
-                    video_track.unpublish().await;
-                    track_lock.remove(&cloned_track_name);
-                }
-            });
-        });
     }
 
     pub fn new_published_video_track(&mut self, track_name: &str, video_renderer: VideoRenderer) {
-        if self.room.lock().is_none(){
+        if self.connection.lock().is_none(){
             return;
         }
 
@@ -323,97 +512,27 @@ impl RTCResource {
 impl Default for RTCResource {
     fn default() -> RTCResource {
         RTCResource {
-            room: Arc::new(Mutex::new(None)),
-            stop_room_sender: None,
+            // replaced by setup_room_transport once the config is read
+            transport: Arc::new(LiveKitTransport {
+                token_provider: None
+            }),
+            connection: Arc::new(Mutex::new(None)),
             room_event: None,
 
             topic_cooldown: HashMap::from([
                 ("move".to_string(), (200, None))
             ]),
 
-            video_tracks: Arc::new(Mutex::new(HashMap::new())),
-            published_video_tracks: HashMap::new()
-        }
-    }
-}
-
-async fn connect(room_id: &str, user_uuid: &str, url: &str, api_key: &str, api_secret: &str, admin: bool) -> (livekit::Room, UnboundedReceiver<livekit::RoomEvent>) {
-    let token = access_token::AccessToken::with_api_key(&api_key, &api_secret)
-    .with_identity(&user_uuid)
-    .with_name(&user_uuid)
-    .with_grants(access_token::VideoGrants {
-        room_join: true,
-        room_admin: admin,
-        room: room_id.to_string(),
-        ..Default::default()
-    })
-    .to_jwt()
-    .unwrap();
-
-    let (room, rx) = Room::connect(&url, &token, RoomOptions {
-        auto_subscribe: true,
-        ..default()
-    })
-    .await
-    .unwrap();
-
-    return (room, rx);
-}
+            session: None,
+            reconnect: ReconnectState::default(),
 
-async fn check_room_exist(room_id: String) -> (Option<RoomClient>, bool) {
-    let (_, api_key, api_secret, https_url) = get_livekit_env();
-
-    let room_service = RoomClient::with_api_key(&https_url, &api_key, &api_secret);
-
-    if let Ok(rooms) = room_service.list_rooms(vec![room_id.to_string()]).await {
-        if rooms.len().eq(&0) {
-            return (None, false);
+            published_video_tracks: HashMap::new()
         }
-        return (Some(room_service), true);
     }
-    return (None, false);
-}
-
-fn get_livekit_env() -> (String, String, String, String){
-    let url = env::var("LIVEKIT_URL").expect("LIVEKIT_URL is not set");
-    let api_key = env::var("LIVEKIT_API_KEY").expect("LIVEKIT_API_KEY is not set");
-    let api_secret = env::var("LIVEKIT_API_SECRET").expect("LIVEKIT_API_SECRET is not set");
-
-    let mut https_url = url.to_string();
-    if https_url.starts_with("wss") {
-        https_url = https_url.replace("wss", "https");
-    }
-
-    return (url, api_key, api_secret, https_url);
-}
-
-fn get_room_service() -> RoomClient{
-    let (_, api_key, api_secret, https_url) = get_livekit_env();
-    let room_service = RoomClient::with_api_key(&https_url, &api_key, &api_secret);
-    return room_service;
-}
-
-async fn event(mut room_rx: UnboundedReceiver<RoomEvent>, stop_room_receiver: Receiver<bool>, room_data_sender: Sender<livekit::RoomEvent>) {
-    async_std::task::block_on(async {
-        loop {
-            if let Ok(stop) = stop_room_receiver.try_recv() {
-                if stop {
-                    drop(stop_room_receiver);
-                    drop(room_data_sender);    
-                    println!("stop loop");
-                    break;
-                }
-            }
-    
-            if let Ok(room_event) = room_rx.try_recv() {
-                let _ = room_data_sender.send(room_event);
-            }
-        }
-    });
 }
 
 fn get_user_attribute(user_attribute: MultiplayerUserAttribute) -> HashMap<String, String> {
-    return HashMap::from([
+    let mut attributes = HashMap::from([
         (String::from("name"), user_attribute.username),
         (String::from("hair"), user_attribute.body_part.hair),
         (String::from("eyes"), user_attribute.body_part.eyes),
@@ -422,4 +541,6 @@ fn get_user_attribute(user_attribute: MultiplayerUserAttribute) -> HashMap<Strin
         (String::from("hip"), user_attribute.body_part.hip),
         (String::from("legs"), user_attribute.body_part.legs)
     ]);
+    attributes.extend(user_attribute.roles);
+    return attributes;
 }
\ No newline at end of file
@@ -1,223 +1,434 @@
 use bevy::{prelude::*, utils::tracing::Instrument};
-use livekit::track::{LocalTrack, RemoteTrack};
 use uuid::Uuid;
 
 use std::collections::HashMap;
 
-use crate::{character::{resources::CharacterAnimation, systems::push_character}, editor::{resources::{Scene, StateName}, systems::create_scene, CreateSceneEvent}, game::resources::GameResources, main_menu::resources::{RoomData, UserData, UserStatus}, map_structures::resources::MapData, networking::s3::resources::S3Data, nokhwa::resources::{NokhwaCamera, StreamingResources}, utils::group_numbers, wardrobe::resources::{BodyParts, OnWardrobeConfirmed, WardrobeResources}};
+use crate::{character::{resources::CharacterAnimation, systems::push_character}, editor::{resources::{Scene, StateName}, systems::create_scene, CreateSceneEvent}, game::resources::{ChatMessage, GameResources}, main_menu::resources::{RoomBrowser, RoomData, RoomRole, RoomRoles, UserData, UserStatus}, map_structures::resources::MapData, networking::s3::resources::S3Data, nokhwa::{resources::{NokhwaCamera, StreamingResources}, StreamingState}, utils::is_within_tiles, wardrobe::resources::{BodyParts, OnWardrobeConfirmed, WardrobeResources}};
 
-use super::{components::{LoadMetadataTask, MultiplayerUserAttribute}, video_renderer::VideoRenderer, RTCResource, RoomMetadata, RoomSentMessage};
+use super::{access::{create_host_proof, get_public_key, hash_password, new_salt, new_signing_key, InviteCode, RoomAccess, INVITE_TTL_SECONDS}, components::{JoinOutcome, JoinRoomTask, MultiplayerUserAttribute}, protocol::{ModerationAction, RoomMessage, RoomPacket}, transport::TransportEvent, video_renderer::VideoRenderer, ModerationEvent, ReconnectPoll, RTCResource, RoomMetadata};
 
-pub fn create_room(room_data: &mut ResMut<RoomData>, rtc_resource: &mut ResMut<RTCResource>, map_data: &ResMut<MapData>){
+pub fn create_room(commands: &mut Commands, scene_uuid: &str, room_data: &mut ResMut<RoomData>, rtc_resource: &mut ResMut<RTCResource>, map_data: &ResMut<MapData>){
     room_data.room_id = Uuid::new_v4().to_string();
+    // the creator hosts the room
+    room_data.roles = RoomRoles::default();
+    room_data.roles.host = room_data.this_user_uuid.to_string();
+    room_data.roles.admitted.insert(room_data.this_user_uuid.to_string());
 
-    let room_metadata = RoomMetadata {
-        map: map_data.map_name.to_string()
+    let mut room_metadata = RoomMetadata {
+        map: map_data.map_name.to_string(),
+        name: room_data.room_name.trim().to_string(),
+        host_key: String::new(),
+        password_hash: String::new(),
+        password_salt: String::new(),
+        invite_key: String::new(),
+        has_password: false,
+        has_invite_key: false
     };
+
+    // the host key never leaves this client, only the proof signed with it
+    match new_signing_key() {
+        Ok(host_signing_key) => {
+            room_metadata.host_key = get_public_key(&host_signing_key);
+            room_data.roles.host_key = room_metadata.host_key.to_string();
+            room_data.roles.host_proof = create_host_proof(&host_signing_key, &room_data.room_id, &room_data.this_user_uuid);
+        },
+        Err(e) => println!("no host key for this room, nobody can moderate it: {}", e)
+    }
+
+    // a private room gets an invite key as well, so the host can hand out invites instead of the password
+    room_data.invite_signing_key = None;
+    if !room_data.room_password.is_empty() {
+        room_metadata.password_salt = new_salt();
+        room_metadata.password_hash = hash_password(&room_metadata.password_salt, &room_data.room_password);
+        room_metadata.has_password = true;
+    }
+    if !room_data.room_password.is_empty() || room_data.is_invite_only {
+        match new_signing_key() {
+            Ok(signing_key) => {
+                room_metadata.invite_key = get_public_key(&signing_key);
+                room_metadata.has_invite_key = true;
+                room_data.invite_signing_key = Some(signing_key);
+            },
+            Err(e) => println!("no invite key for this room: {}", e)
+        }
+    }
     let room_metadata_str = serde_json::to_string(&room_metadata).unwrap();
 
     let user_data = room_data.room_users.get(&room_data.this_user_uuid).unwrap();
     let user_attribute = MultiplayerUserAttribute {
         username: user_data.username.to_string(),
-        body_part: user_data.body_parts.clone()
+        body_part: user_data.body_parts.clone(),
+        roles: room_data.roles.to_attributes()
     };
 
-    rtc_resource.new_room(room_data.room_id.to_string(), room_data.this_user_uuid.to_string(), Some(room_metadata_str), user_attribute);
+    // the host gets past the token server with an invite of its own, the same as anyone it invites
+    let mut invite_code = String::new();
+    if let Some(signing_key) = &room_data.invite_signing_key {
+        invite_code = InviteCode::create(signing_key, &room_data.room_id, INVITE_TTL_SECONDS);
+    }
+    rtc_resource.new_room(commands, scene_uuid, room_data.room_id.to_string(), room_data.this_user_uuid.to_string(), Some(room_metadata_str), &room_data.room_password, &invite_code, user_attribute);
 }
 
-pub fn check_room(commands: &mut Commands, scene_uuid: &str, rtc_resource: &mut ResMut<RTCResource>, room_id: String){
-    rtc_resource.enter_wardrobe_if_room_exist(commands, &scene_uuid, &room_id);
+pub fn check_room(commands: &mut Commands, scene_uuid: &str, rtc_resource: &mut ResMut<RTCResource>, room_data: &mut ResMut<RoomData>){
+    // an invite code carries the room id
+    if let Some(invite) = InviteCode::parse(&room_data.room_id) {
+        room_data.invite_code = room_data.room_id.trim().to_string();
+        room_data.room_id = invite.room_id;
+    }
+    rtc_resource.enter_wardrobe_if_room_exist(commands, &scene_uuid, &room_data.room_id, &room_data.room_password, &room_data.invite_code);
 }
 
 pub fn join_room(commands: &mut Commands, scene_uuid: &str, room_data: &mut ResMut<RoomData>, rtc_resource: &mut ResMut<RTCResource>){
     let user_data = room_data.room_users.get(&room_data.this_user_uuid).unwrap();
     let user_attribute = MultiplayerUserAttribute {
         username: user_data.username.to_string(),
-        body_part: user_data.body_parts.clone()
+        body_part: user_data.body_parts.clone(),
+        roles: HashMap::new()
     };
 
-    let _ = rtc_resource.join_existing_room(commands, &scene_uuid, &room_data.room_id, &room_data.this_user_uuid, user_attribute);
+    let _ = rtc_resource.join_existing_room(commands, &scene_uuid, &room_data.room_id, &room_data.this_user_uuid, &room_data.room_password, &room_data.invite_code, user_attribute);
 }
 
 pub fn leave_room(room_data: &mut ResMut<RoomData>, rtc_resource: &mut ResMut<RTCResource>){
-    rtc_resource.leave_room(room_data.room_id.to_string(), room_data.this_user_uuid.to_string());
+    rtc_resource.leave_room();
 
     room_data.room_id.clear();
+    room_data.room_name.clear();
     room_data.room_users.clear();
+    room_data.roles = RoomRoles::default();
+    room_data.room_password.clear();
+    room_data.invite_code.clear();
+    room_data.invite_signing_key = None;
 }
 
-pub fn load_room_metadata_event_listener(events_query: Query<(Entity, &LoadMetadataTask)>, scene: Res<Scene>, mut commands: Commands, mut room_data: ResMut<RoomData>, mut map_data: ResMut<MapData>, mut wardrobe_resources: ResMut<WardrobeResources>, mut create_scene_event: EventWriter<CreateSceneEvent>){
-    for (entity, load_metadata_task) in events_query.iter() {
+// back to the lobby, with the reason shown above the room list
+pub fn leave_room_with_notice(commands: &mut Commands, create_scene_event: &mut EventWriter<CreateSceneEvent>, room_data: &mut ResMut<RoomData>, rtc_resource: &mut ResMut<RTCResource>, room_browser: &mut ResMut<RoomBrowser>, notice: &str){
+    leave_room(room_data, rtc_resource);
+    room_browser.notice = notice.to_string();
 
-        if load_metadata_task.metadata.starts_with(':') {
-            match load_metadata_task.metadata.as_str() {
-                ":404" => {
-                    room_data.room_id.clear();
+    create_scene(commands, create_scene_event, room_data, "select room", HashMap::from([
+        (StateName::MainMenuState as u8, 2),
+        (StateName::RoomMetadataListener as u8, 1)
+    ]), None);
+}
 
-                    if scene.scene_name.ne("lobby") {
-                        create_scene(&mut commands, &mut create_scene_event, &mut room_data, "lobby", HashMap::from([
-                            (StateName::MainMenuState as u8, 2),
-                            (StateName::RoomMetadataListener as u8, 1)
-                        ]), None);
-                    }
-                },
-                ":join_room_to_wardrobe" => {
-                    wardrobe_resources.is_name_changable = true;
-                    wardrobe_resources.is_clothes_changable = true;
-                    wardrobe_resources.is_map_changable = false;
-                    wardrobe_resources.display_comfirm_button = true;
-                    wardrobe_resources.on_confirm_text = "Join Room".to_string();
-                    wardrobe_resources.on_confirm_action = OnWardrobeConfirmed::JoinRoom;
-    
-                    create_scene(&mut commands, &mut create_scene_event, &mut room_data, "wardrobe", HashMap::from([
-                        (StateName::MainMenuState as u8, 3),
-                        (StateName::WardrobeState as u8, 1),
-                        (StateName::TriggerButtonState as u8, 1),
-                        (StateName::MovementState as u8, 1),
-                        (StateName::CharacterExistState as u8, 1),
-                        (StateName::CameraState as u8, 2),
+// acted on locally through the same checks as a message from someone else
+pub fn send_moderation(rtc_resource: &mut ResMut<RTCResource>, room_data: &RoomData, moderation_event: &mut EventWriter<ModerationEvent>, action: ModerationAction, target: String){
+    rtc_resource.send_message_to(&room_data.this_user_uuid, RoomMessage::Moderate {
+        action,
+        target: target.to_string()
+    }, Vec::new());
+    moderation_event.send(ModerationEvent {
+        sender: room_data.this_user_uuid.to_string(),
+        action,
+        target
+    });
+}
+
+pub fn get_display_name(room_data: &RoomData, identity: &str) -> String {
+    if let Some(user_data) = room_data.room_users.get(identity) {
+        if !user_data.username.is_empty() {
+            return user_data.username.to_string();
+        }
+    }
+    return String::from("anonymous");
+}
+
+pub fn join_room_event_listener(events_query: Query<(Entity, &JoinRoomTask)>, scene: Res<Scene>, mut commands: Commands, mut room_data: ResMut<RoomData>, mut map_data: ResMut<MapData>, mut wardrobe_resources: ResMut<WardrobeResources>, mut create_scene_event: EventWriter<CreateSceneEvent>, mut room_browser: ResMut<RoomBrowser>, mut rtc_resource: ResMut<RTCResource>){
+    for (entity, join_room_task) in events_query.iter() {
+        match &join_room_task.outcome {
+            JoinOutcome::Admitted => {
+                room_browser.is_password_requested = false;
+                wardrobe_resources.is_name_changable = true;
+                wardrobe_resources.is_clothes_changable = true;
+                wardrobe_resources.is_map_changable = false;
+                wardrobe_resources.display_comfirm_button = true;
+                wardrobe_resources.on_confirm_text = "Join Room".to_string();
+                wardrobe_resources.on_confirm_action = OnWardrobeConfirmed::JoinRoom;
+
+                create_scene(&mut commands, &mut create_scene_event, &mut room_data, "wardrobe", HashMap::from([
+                    (StateName::MainMenuState as u8, 3),
+                    (StateName::WardrobeState as u8, 1),
+                    (StateName::TriggerButtonState as u8, 1),
+                    (StateName::MovementState as u8, 1),
+                    (StateName::CharacterExistState as u8, 1),
+                    (StateName::CameraState as u8, 2),
+                    (StateName::RoomMetadataListener as u8, 1)
+                ]), Some(Vec::from([
+                    StateName::StreamingState as u8
+                ])));
+            },
+            JoinOutcome::Joined(_) | JoinOutcome::Created => {
+                // the creator already set up the map and its roles
+                if let JoinOutcome::Joined(metadata) = &join_room_task.outcome {
+                    map_data.map_name = metadata.map.to_string();
+                    room_data.room_name = metadata.name.to_string();
+                    room_data.roles = RoomRoles::default();
+                    room_data.roles.host_key = metadata.host_key.to_string();
+                }
+
+                create_scene(&mut commands, &mut create_scene_event, &mut room_data, "game", HashMap::from([
+                    (StateName::TriggerButtonState as u8, 1),
+                    (StateName::MovementState as u8, 1),
+                    (StateName::CharacterExistState as u8, 1),
+                    (StateName::CameraState as u8, 2),
+                    (StateName::MultiplayerRoomState as u8, 1)
+                ]), Some(Vec::from([
+                    StateName::WardrobeState as u8,
+                    StateName::StreamingState as u8
+                ])));
+            },
+            outcome => {
+                room_browser.notice = outcome.get_message();
+                match outcome {
+                    JoinOutcome::NotFound => room_data.room_id.clear(),
+                    JoinOutcome::AuthFailed(RoomAccess::PasswordRequired) | JoinOutcome::AuthFailed(RoomAccess::WrongPassword) => room_browser.is_password_requested = true,
+                    JoinOutcome::AuthFailed(RoomAccess::InviteExpired) | JoinOutcome::AuthFailed(RoomAccess::InvalidInvite) => room_data.invite_code.clear(),
+                    _ => {}
+                }
+
+                // joining from the wardrobe failed, the room id is kept so it can be tried again
+                if scene.scene_name.ne("lobby") {
+                    rtc_resource.leave_room();
+                    create_scene(&mut commands, &mut create_scene_event, &mut room_data, "lobby", HashMap::from([
+                        (StateName::MainMenuState as u8, 2),
                         (StateName::RoomMetadataListener as u8, 1)
-                    ]), Some(Vec::from([
-                        StateName::StreamingState as u8
-                    ])));
+                    ]), None);
                 }
-                _ => {}
             }
-            commands.entity(entity).despawn();
-            return;
-        }
-
-        if let Ok(metadata) = serde_json::from_str::<RoomMetadata>(&load_metadata_task.metadata) {
-            map_data.map_name = metadata.map;
-
-            create_scene(&mut commands, &mut create_scene_event, &mut room_data, "game", HashMap::from([
-                (StateName::TriggerButtonState as u8, 1),
-                (StateName::MovementState as u8, 1),
-                (StateName::CharacterExistState as u8, 1),
-                (StateName::CameraState as u8, 2),
-                (StateName::MultiplayerRoomState as u8, 1)
-            ]), Some(Vec::from([
-                StateName::WardrobeState as u8,
-                StateName::StreamingState as u8
-            ])));
         }
 
         commands.entity(entity).despawn();
     }
 }
 
-pub fn on_room_event_received(mut commands: Commands, assets_server: Res<AssetServer>, mut rtc_resource: ResMut<RTCResource>, scene: Res<Scene>, mut room_data: ResMut<RoomData>, mut s3_data: ResMut<S3Data>, mut map_data: ResMut<MapData>, mut character_animation: Res<CharacterAnimation>, mut game_resource: ResMut<GameResources>, mut nokhwa_camera: Res<NokhwaCamera>){
+pub fn on_room_event_received(mut commands: Commands, assets_server: Res<AssetServer>, mut rtc_resource: ResMut<RTCResource>, scene: Res<Scene>, mut room_data: ResMut<RoomData>, mut s3_data: ResMut<S3Data>, mut map_data: ResMut<MapData>, mut character_animation: Res<CharacterAnimation>, mut game_resource: ResMut<GameResources>, mut nokhwa_camera: Res<NokhwaCamera>, time: Res<Time>, mut create_scene_event: EventWriter<CreateSceneEvent>, mut room_browser: ResMut<RoomBrowser>, mut moderation_event: EventWriter<ModerationEvent>){
+    let mut events: Vec<TransportEvent> = Vec::new();
     if let Some(room_event) = &rtc_resource.room_event {
-        if let Ok(rtc_room_event) = room_event.try_recv() {
-            println!("----------");
-            match rtc_room_event {
-                livekit::RoomEvent::Connected { participants_with_tracks } => {
-                    for (remote_participant, remote_track) in participants_with_tracks.iter() {
-                        if remote_participant.name().eq(&room_data.this_user_uuid) {
-                            continue;
-                        }
-                        if room_data.room_users.contains_key(&remote_participant.name()) {
-                            continue;
-                        }
-                        for track in remote_track.iter() {
-                            track.set_subscribed(true);
-                        }
-                        let mut user_data = UserData::create_empty(&remote_participant.name(), BodyParts::default());
-                        set_attributes(&mut user_data, &remote_participant.attributes());
-                        push_character(&mut commands, &assets_server, &scene.scene_uuid, &mut s3_data, &mut map_data, &mut room_data, &mut character_animation, &mut user_data);
+        events = room_event.try_iter().collect();
+        // the transport thread ended without reporting it
+        if events.is_empty() && room_event.is_disconnected() {
+            events.push(TransportEvent::Disconnected);
+        }
+    }
+
+    // handle everything that arrived since the last frame, the returns below leave the room so the rest is dropped
+    for rtc_room_event in events {
+        println!("----------");
+        match rtc_room_event {
+            TransportEvent::Connected { participants } => {
+                // the host's attributes carry the roles, so read them before deciding whether this client may stay
+                let room_id = room_data.room_id.to_string();
+                if let Some(host) = participants.iter().find(|participant| room_data.roles.has_host_proof(&room_id, &participant.identity, &participant.attributes)) {
+                    room_data.roles.host = host.identity.to_string();
+                    room_data.roles.apply_host_attributes(&host.attributes);
+                }
+                let this_user_uuid = room_data.this_user_uuid.to_string();
+                if !room_data.roles.can_join(&this_user_uuid) {
+                    let mut notice = "This room is locked";
+                    if room_data.roles.kicked.contains(&this_user_uuid) {
+                        notice = "You were removed from this room";
                     }
-                },
-                livekit::RoomEvent::ParticipantDisconnected(remote_participant) => {
-                    if let Some(user_data) = room_data.room_users.get_mut(&remote_participant.name()) {
+                    println!("not staying in room {}: {}", room_data.room_id, notice);
+                    leave_room_with_notice(&mut commands, &mut create_scene_event, &mut room_data, &mut rtc_resource, &mut room_browser, notice);
+                    return;
+                }
+                room_data.roles.admitted.insert(this_user_uuid);
+
+                // after a reconnect, drop anyone who left while this client was gone
+                let stale_users: Vec<String> = room_data.room_users.keys()
+                .filter(|uuid| uuid.ne(&&room_data.this_user_uuid) && !participants.iter().any(|participant| participant.identity.eq(*uuid)))
+                .map(|uuid| uuid.to_string())
+                .collect();
+                for uuid in stale_users.iter() {
+                    if let Some(user_data) = room_data.room_users.get_mut(uuid) {
                         user_data.remove_character(&mut commands);
-                        room_data.room_users.remove(&remote_participant.name());
                     }
-                    for (_, publication) in remote_participant.track_publications() {
-                        publication.set_subscribed(false);
+                    room_data.room_users.remove(uuid);
+                }
+
+                for remote_participant in participants.iter() {
+                    if remote_participant.identity.eq(&room_data.this_user_uuid) {
+                        continue;
+                    }
+                    if room_data.room_users.contains_key(&remote_participant.identity) {
+                        continue;
                     }
-                },
-                livekit::RoomEvent::ParticipantConnected(remote_participant) => {
-                    let mut user_data = UserData::create_empty(&remote_participant.name(), BodyParts::default());
-                    if remote_participant.attributes().len().eq(&0) {
-                        user_data.user_status = UserStatus::Wait;
-                    } else {
-                        set_attributes(&mut user_data, &remote_participant.attributes());
+                    if room_data.roles.kicked.contains(&remote_participant.identity) {
+                        continue;
                     }
+                    room_data.roles.admitted.insert(remote_participant.identity.to_string());
+                    let mut user_data = UserData::create_empty(&remote_participant.identity, BodyParts::default());
+                    set_attributes(&mut user_data, &remote_participant.attributes);
                     push_character(&mut commands, &assets_server, &scene.scene_uuid, &mut s3_data, &mut map_data, &mut room_data, &mut character_animation, &mut user_data);
+                }
+            },
+            TransportEvent::ParticipantDisconnected(identity) => {
+                let display_name = get_display_name(&room_data, &identity);
+                if let Some(user_data) = room_data.room_users.get_mut(&identity) {
+                    user_data.remove_character(&mut commands);
+                    room_data.room_users.remove(&identity);
+                    game_resource.add_system_message(&format!("{} left", display_name));
+                }
+            },
+            TransportEvent::ParticipantConnected(remote_participant) => {
+                // the host coming back, its roles are read again from the attributes
+                if room_data.roles.has_host_proof(&room_data.room_id, &remote_participant.identity, &remote_participant.attributes) {
+                    room_data.roles.host = remote_participant.identity.to_string();
+                    room_data.roles.apply_host_attributes(&remote_participant.attributes);
+                }
+                // the newcomer leaves by itself, until then it is not shown
+                if !room_data.roles.can_join(&remote_participant.identity) {
+                    println!("{} is not let in, the room is locked or they were removed", remote_participant.identity);
+                    continue;
+                }
+                room_data.roles.admitted.insert(remote_participant.identity.to_string());
 
-                    if nokhwa_camera.has_opened {
-                        if !rtc_resource.is_video_track_exists(&room_data.this_user_uuid) {
-                            if let Some(image_receiver) = &nokhwa_camera.image_receiver {
-                                rtc_resource.new_video_track(&room_data.this_user_uuid, room_data.room_users.len(), image_receiver.clone());
-                            }
+                let mut user_data = UserData::create_empty(&remote_participant.identity, BodyParts::default());
+                if remote_participant.attributes.len().eq(&0) {
+                    user_data.user_status = UserStatus::Wait;
+                } else {
+                    set_attributes(&mut user_data, &remote_participant.attributes);
+                }
+                push_character(&mut commands, &assets_server, &scene.scene_uuid, &mut s3_data, &mut map_data, &mut room_data, &mut character_animation, &mut user_data);
+                game_resource.add_system_message(&format!("{} joined", get_display_name(&room_data, &remote_participant.identity)));
+
+                // the newcomer only knows the spawn point, so tell them where this avatar is right now
+                if let Some(this_user_data) = room_data.room_users.get(&room_data.this_user_uuid) {
+                    let pos = this_user_data.character_controller.get_pos();
+                    let snapshot = RoomMessage::Snapshot {
+                        x: pos.0,
+                        y: pos.1,
+                        scale_x: this_user_data.character_controller.get_scale().0,
+                        animation: this_user_data.character_controller.get_animation().0
+                    };
+                    rtc_resource.send_message_to(&room_data.this_user_uuid, snapshot, Vec::from([remote_participant.identity.to_string()]));
+                }
+
+                if nokhwa_camera.has_opened {
+                    if !rtc_resource.is_video_track_exists(&room_data.this_user_uuid) {
+                        if let Some(image_receiver) = &nokhwa_camera.image_receiver {
+                            rtc_resource.new_video_track(&room_data.this_user_uuid, room_data.room_users.len(), image_receiver.clone());
                         }
                     }
-                },
-                livekit::RoomEvent::ParticipantAttributesChanged { participant, changed_attributes } => {
-                    if participant.name().eq(&room_data.this_user_uuid) {
+                }
+            },
+            TransportEvent::AttributesChanged { identity, changed } => {
+                // the host may only publish its proof after connecting
+                if identity.ne(&room_data.this_user_uuid) && room_data.roles.has_host_proof(&room_data.room_id, &identity, &changed) {
+                    room_data.roles.host = identity.to_string();
+                }
+                if identity.eq(&room_data.roles.host) && identity.ne(&room_data.this_user_uuid) {
+                    room_data.roles.apply_host_attributes(&changed);
+                    // in case the kick message itself never arrived
+                    if room_data.roles.kicked.contains(&room_data.this_user_uuid) {
+                        leave_room_with_notice(&mut commands, &mut create_scene_event, &mut room_data, &mut rtc_resource, &mut room_browser, "You were removed from this room");
                         return;
                     }
-                    let user_uuid = participant.name().to_string();
+                }
+                if identity.eq(&room_data.this_user_uuid) {
+                    continue;
+                }
 
-                    if let Some(user_data) = room_data.room_users.get_mut(&user_uuid) {
-                        set_attributes(user_data, &changed_attributes);
-                        room_data.load_wait_user(&mut commands, &assets_server,  &scene.scene_uuid, &mut s3_data, &mut map_data, &mut character_animation, &user_uuid);
-                    }
+                if let Some(user_data) = room_data.room_users.get_mut(&identity) {
+                    set_attributes(user_data, &changed);
+                    room_data.load_wait_user(&mut commands, &assets_server,  &scene.scene_uuid, &mut s3_data, &mut map_data, &mut character_animation, &identity);
                 }
-                livekit::RoomEvent::DataReceived { payload, topic, kind, participant } => {
-                    if let Ok(msg) = String::from_utf8((*payload).to_vec()) {
-                        if let Ok(data) = serde_json::from_str::<RoomSentMessage>(&msg) {
-                            if data.a.eq(&room_data.this_user_uuid) {
-                                return;
+            }
+            TransportEvent::DataReceived { identity, payload, .. } => {
+                match RoomPacket::decode(&payload) {
+                    Ok(packet) => {
+                        if packet.sender.eq(&room_data.this_user_uuid) {
+                            continue;
+                        }
+                        // the transport knows who really sent it, a packet can't speak for someone else
+                        if let Some(identity) = identity {
+                            if identity.ne(&packet.sender) {
+                                println!("dropped room message from {} claiming to be {}", identity, packet.sender);
+                                continue;
                             }
+                        }
 
-                            if let Some(user_data) = room_data.room_users.get_mut(&data.a) {
-                                if let Some(topic) = topic {
-                                    match topic.as_str() {
-                                        "chat" => {
-                                            game_resource.chat_messages.push(data.b);
-                                        },
-                                        "move" => {
-                                            if let Ok(groups) = group_numbers(data.b, 2) {
-                                                let x = groups[0][0];
-                                                let y = groups[0][1];
-                                                user_data.character_controller.set_pos_x(x);
-                                                user_data.character_controller.set_pos_y(y);
-                                                user_data.character_controller.set_animation("walk");
-                                            }
-                                        },
-                                        "anime" => {
-                                            let groups = data.b.split(' ').collect::<Vec<&str>>();
-                                            let anime = groups[0];
-                                            let x = groups[1].parse::<f32>().unwrap();
-                                            let y = groups[2].parse::<f32>().unwrap();
-                                            user_data.character_controller.set_animation(&anime);
-                                            user_data.character_controller.set_pos_x(x);
-                                            user_data.character_controller.set_pos_y(y);
+                        if let RoomMessage::Moderate { action, target } = &packet.message {
+                            moderation_event.send(ModerationEvent {
+                                sender: packet.sender.to_string(),
+                                action: *action,
+                                target: target.to_string()
+                            });
+                            continue;
+                        }
+
+                        let is_muted = room_data.roles.muted.contains(&packet.sender);
+                        let this_pos = room_data.room_users.get(&room_data.this_user_uuid).map(|this_user_data| this_user_data.character_controller.get_pos());
+                        if let Some(user_data) = room_data.room_users.get_mut(&packet.sender) {
+                            match packet.message {
+                                RoomMessage::Chat { kind, text, range_tiles } => {
+                                    // nearby messages are only sent to those in range, this also drops them for anyone who walked off since
+                                    let sender_pos = user_data.character_controller.get_pos();
+                                    let is_in_range = match this_pos {
+                                        Some(this_pos) => range_tiles == 0 || is_within_tiles(this_pos.0, this_pos.1, sender_pos.0, sender_pos.1, map_data.tile_width, map_data.tile_height, range_tiles as f32),
+                                        None => range_tiles == 0
+                                    };
+                                    // muted participants can still move around, only their chat is dropped
+                                    if !is_muted && is_in_range {
+                                        let mut display_name = user_data.username.to_string();
+                                        if display_name.is_empty() {
+                                            display_name = String::from("anonymous");
                                         }
-                                        _ => {}
+                                        let mut message = ChatMessage::new(&packet.sender, &display_name, kind, &text);
+                                        message.is_nearby = range_tiles > 0;
+                                        game_resource.show_speech_bubble(&message, time.elapsed_seconds_f64());
+                                        game_resource.add_chat_message(message);
                                     }
-                                }
+                                },
+                                RoomMessage::Move { x, y, time_ms } => {
+                                    user_data.position_buffer.push(time_ms, time.elapsed_seconds_f64(), x, y);
+                                    user_data.character_controller.set_animation("walk");
+                                },
+                                RoomMessage::Animation { name, x, y, time_ms } => {
+                                    user_data.position_buffer.push(time_ms, time.elapsed_seconds_f64(), x, y);
+                                    user_data.character_controller.set_animation(&name);
+                                },
+                                RoomMessage::Snapshot { x, y, scale_x, animation } => {
+                                    user_data.position_buffer.clear();
+                                    user_data.character_controller.teleport(x, y, scale_x, &animation);
+                                    user_data.is_state_synced = true;
+                                },
+                                RoomMessage::Moderate { .. } => {}
                             }
                         }
+                    },
+                    Err(e) => {
+                        println!("dropped room message: {}", e);
                     }
-                },
-                // livekit::RoomEvent::LocalTrackPublished { publication, track, participant } => {
-                    // if let LocalTrack::Video(ref video_track) = track {
-                    //     let video_renderer = VideoRenderer::new(
-                    //         video_track.rtc_track()
-                    //     );
-                    //     println!("published: {}", publication.name());
-                    //     rtc_resource.new_published_video_track(&publication.name(), video_renderer);
-                    // }
-                // },
-                // livekit::RoomEvent::LocalTrackUnpublished { publication, participant } => {
-                //     println!("unpublished: {}", publication.name());
-                //     rtc_resource.close_published_video_track(&publication.name());
-                // },
-                _ => {
-                    println!("{:?}", rtc_room_event);
                 }
+            },
+            // livekit::RoomEvent::LocalTrackPublished { publication, track, participant } => {
+                // if let LocalTrack::Video(ref video_track) = track {
+                //     let video_renderer = VideoRenderer::new(
+                //         video_track.rtc_track()
+                //     );
+                //     println!("published: {}", publication.name());
+                //     rtc_resource.new_published_video_track(&publication.name(), video_renderer);
+                // }
+            // },
+            // livekit::RoomEvent::LocalTrackUnpublished { publication, participant } => {
+            //     println!("unpublished: {}", publication.name());
+            //     rtc_resource.close_published_video_track(&publication.name());
+            // },
+            TransportEvent::Reconnecting => {
+                rtc_resource.reconnect.is_resuming = true;
+            },
+            TransportEvent::Reconnected => {
+                rtc_resource.reconnect.is_resuming = false;
+            },
+            TransportEvent::Disconnected => {
+                rtc_resource.begin_reconnect();
+                // the connection is gone, anything queued after this is stale
+                break;
+            },
+            _ => {
+                println!("{:?}", rtc_room_event);
             }
         }
     }
@@ -247,4 +458,81 @@ fn set_attributes(user_data: &mut UserData, changed_attributes: &HashMap<String,
             _ => {}
         }
     }
-}
\ No newline at end of file
+}
+pub fn reconnect_room(mut commands: Commands, mut rtc_resource: ResMut<RTCResource>, mut room_data: ResMut<RoomData>, nokhwa_camera: Res<NokhwaCamera>, mut create_scene_event: EventWriter<CreateSceneEvent>, mut room_browser: ResMut<RoomBrowser>){
+    match rtc_resource.poll_reconnect() {
+        ReconnectPoll::Reconnected => {
+            // attributes went out with the new connection, the camera track has to be published again
+            if nokhwa_camera.has_opened {
+                if let Some(image_receiver) = &nokhwa_camera.image_receiver {
+                    rtc_resource.new_video_track(&room_data.this_user_uuid, room_data.room_users.len(), image_receiver.clone());
+                }
+            }
+        },
+        ReconnectPoll::GaveUp => {
+            leave_room_with_notice(&mut commands, &mut create_scene_event, &mut room_data, &mut rtc_resource, &mut room_browser, "Connection lost, the room could not be reached again");
+        },
+        _ => {}
+    }
+}
+
+pub fn apply_moderation(mut commands: Commands, mut moderation_events: EventReader<ModerationEvent>, mut create_scene_event: EventWriter<CreateSceneEvent>, mut room_data: ResMut<RoomData>, mut rtc_resource: ResMut<RTCResource>, mut room_browser: ResMut<RoomBrowser>, mut game_resources: ResMut<GameResources>, nokhwa_camera: Res<NokhwaCamera>, mut streaming_state: ResMut<NextState<StreamingState>>){
+    for event in moderation_events.read() {
+        if !room_data.roles.is_allowed(&event.sender, event.action, &event.target) {
+            println!("ignored {:?} for {} from {}, not allowed", event.action, event.target, event.sender);
+            continue;
+        }
+        room_data.roles.apply(event.action, &event.target);
+
+        let this_user_uuid = room_data.this_user_uuid.to_string();
+        // the host keeps the roles in its attributes for everyone who joins later
+        if room_data.roles.get_role(&this_user_uuid).eq(&RoomRole::Host) {
+            let attributes = room_data.roles.to_attributes();
+            rtc_resource.update_attributes(attributes);
+        }
+
+        let sender_name = get_display_name(&room_data, &event.sender);
+        let target_name = get_display_name(&room_data, &event.target);
+
+        if event.target.eq(&this_user_uuid) {
+            match event.action {
+                ModerationAction::Kick => {
+                    leave_room_with_notice(&mut commands, &mut create_scene_event, &mut room_data, &mut rtc_resource, &mut room_browser, &format!("{} removed you from the room", sender_name));
+                    return;
+                },
+                ModerationAction::Mute => game_resources.add_system_message(&format!("{} muted you", sender_name)),
+                ModerationAction::Unmute => game_resources.add_system_message(&format!("{} unmuted you", sender_name)),
+                ModerationAction::StopCamera => {
+                    if nokhwa_camera.has_opened {
+                        streaming_state.set(StreamingState::Close);
+                    }
+                    game_resources.add_system_message(&format!("{} stopped your camera", sender_name));
+                },
+                ModerationAction::Promote => {
+                    rtc_resource.update_attributes(HashMap::from([(String::from("role"), String::from("moderator"))]));
+                    game_resources.add_system_message(&format!("{} made you a moderator", sender_name));
+                },
+                ModerationAction::Demote => {
+                    rtc_resource.update_attributes(HashMap::from([(String::from("role"), String::new())]));
+                    game_resources.add_system_message(&format!("{} took your moderator role", sender_name));
+                },
+                _ => {}
+            }
+            continue;
+        }
+
+        match event.action {
+            ModerationAction::Kick => {
+                if let Some(user_data) = room_data.room_users.get_mut(&event.target) {
+                    user_data.remove_character(&mut commands);
+                }
+                room_data.room_users.remove(&event.target);
+                game_resources.add_system_message(&format!("{} removed {}", sender_name, target_name));
+            },
+            ModerationAction::Promote => game_resources.add_system_message(&format!("{} is now a moderator", target_name)),
+            ModerationAction::Lock => game_resources.add_system_message(&format!("{} locked the room", sender_name)),
+            ModerationAction::Unlock => game_resources.add_system_message(&format!("{} unlocked the room", sender_name)),
+            _ => {}
+        }
+    }
+}
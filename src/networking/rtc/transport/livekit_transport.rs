@@ -0,0 +1,300 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use flume::{bounded, RecvTimeoutError};
+use image::RgbaImage;
+use livekit::prelude::*;
+use livekit::track::TrackKind;
+use parking_lot::Mutex;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::networking::rtc::video::DeviceVideoTrack;
+
+use super::token_provider::TokenProvider;
+use super::{RoomConnection, RoomListing, RoomTransport, TransportConnectOptions, TransportError, TransportEvent, TransportParticipant, TransportReliability, CONNECT_TIMEOUT_SECONDS, MAX_ROOM_PARTICIPANTS};
+
+pub struct LiveKitTransport {
+    pub token_provider: Option<Arc<dyn TokenProvider>>
+}
+
+impl LiveKitTransport {
+    fn get_token_provider(&self) -> Result<Arc<dyn TokenProvider>, TransportError> {
+        match &self.token_provider {
+            Some(token_provider) => return Ok(Arc::clone(token_provider)),
+            None => return Err(TransportError::Network("no token provider, set token_endpoint in the config or LIVEKIT_TOKEN_ENDPOINT".to_string()))
+        }
+    }
+}
+
+impl RoomTransport for LiveKitTransport {
+    fn name(&self) -> String {
+        match &self.token_provider {
+            Some(token_provider) => return format!("livekit, {} tokens", token_provider.name()),
+            None => return "livekit, no token provider".to_string()
+        }
+    }
+
+    fn get_room(&self, room_id: &str) -> Result<Option<RoomListing>, TransportError> {
+        return self.get_token_provider()?.get_room(room_id);
+    }
+
+    fn list_rooms(&self) -> Result<Vec<RoomListing>, TransportError> {
+        return self.get_token_provider()?.list_rooms();
+    }
+
+    fn connect(&self, options: TransportConnectOptions) -> Result<Box<dyn RoomConnection>, TransportError> {
+        let token_provider = self.get_token_provider()?;
+        if let Some(metadata) = &options.create_metadata {
+            token_provider.create_room(&options.room_id, metadata)?;
+        } else {
+            match token_provider.get_room(&options.room_id)? {
+                Some(room) if room.participants >= MAX_ROOM_PARTICIPANTS => return Err(TransportError::RoomFull),
+                Some(_) => {},
+                None => return Err(TransportError::RoomNotFound)
+            }
+        }
+        let room_token = token_provider.join_token(&options.room_id, &options.identity, &options.password, &options.invite_code, &options.session_key)?;
+
+        let room_arc: Arc<Mutex<Option<Room>>> = Arc::new(Mutex::new(None));
+        let cloned_room = Arc::clone(&room_arc);
+
+        let (result_sender, result_receiver) = bounded::<Result<String, String>>(1);
+        let (stop_sender, stop_receiver) = bounded::<bool>(1);
+
+        std::thread::spawn(move || {
+            tokio::runtime::Runtime::new().unwrap().block_on(async {
+                let (room, rx) = match Room::connect(&room_token.url, &room_token.token, RoomOptions {
+                    auto_subscribe: true,
+                    ..Default::default()
+                }).await {
+                    Ok(connected) => connected,
+                    Err(e) => {
+                        let _ = result_sender.send(Err(format!("{:?}", e)));
+                        return;
+                    }
+                };
+
+                let _ = room.local_participant().set_attributes(options.attributes).await;
+
+                let metadata = room.metadata();
+                *cloned_room.lock() = Some(room);
+                let _ = result_sender.send(Ok(metadata));
+
+                forward_events(rx, stop_receiver, options.event_sender).await;
+            });
+        });
+
+        // a connect that finishes after the timeout stops right away, its stop sender is dropped by then
+        match result_receiver.recv_timeout(Duration::from_secs(CONNECT_TIMEOUT_SECONDS)) {
+            Ok(Ok(metadata)) => {
+                return Ok(Box::new(LiveKitConnection {
+                    metadata,
+                    room: room_arc,
+                    stop_sender,
+                    video_tracks: Arc::new(Mutex::new(HashMap::new()))
+                }));
+            },
+            Ok(Err(e)) => return Err(TransportError::Network(e)),
+            Err(RecvTimeoutError::Timeout) => return Err(TransportError::Timeout),
+            Err(RecvTimeoutError::Disconnected) => return Err(TransportError::Network("livekit connection thread stopped".to_string()))
+        }
+    }
+}
+
+pub struct LiveKitConnection {
+    metadata: String,
+    room: Arc<Mutex<Option<Room>>>,
+    stop_sender: flume::Sender<bool>,
+    video_tracks: Arc<Mutex<HashMap<String, DeviceVideoTrack>>>
+}
+
+impl LiveKitConnection {
+    fn get_local_participant(&self) -> Option<LocalParticipant> {
+        return self.room.lock().as_ref().map(|room| room.local_participant());
+    }
+}
+
+impl RoomConnection for LiveKitConnection {
+    fn metadata(&self) -> String {
+        return self.metadata.to_string();
+    }
+
+    fn send_data(&self, topic: &str, payload: Vec<u8>, reliability: TransportReliability, destinations: Vec<String>) {
+        let local_participant = match self.get_local_participant() {
+            Some(local_participant) => local_participant,
+            None => return
+        };
+        let data_packet = DataPacket {
+            payload,
+            topic: Some(topic.to_string()),
+            reliable: reliability == TransportReliability::Reliable,
+            destination_identities: destinations.into_iter().map(|identity| ParticipantIdentity(identity)).collect()
+        };
+
+        std::thread::spawn(move || {
+            tokio::runtime::Runtime::new().unwrap().block_on(async {
+                let _ = local_participant.publish_data(data_packet).await;
+            });
+        });
+    }
+
+    fn set_attributes(&self, attributes: HashMap<String, String>) {
+        let local_participant = match self.get_local_participant() {
+            Some(local_participant) => local_participant,
+            None => return
+        };
+
+        std::thread::spawn(move || {
+            tokio::runtime::Runtime::new().unwrap().block_on(async {
+                let _ = local_participant.set_attributes(attributes).await;
+            });
+        });
+    }
+
+    fn is_video_published(&self, track_name: &str) -> bool {
+        return self.video_tracks.lock().contains_key(track_name);
+    }
+
+    fn publish_video(&self, track_name: &str, image_receiver: flume::Receiver<RgbaImage>) {
+        if self.room.lock().is_none() || self.is_video_published(track_name) {
+            return;
+        }
+
+        let cloned_track_name = track_name.to_string();
+        let cloned_room = Arc::clone(&self.room);
+        let video_tracks_arc = Arc::clone(&self.video_tracks);
+        std::thread::spawn( move || {
+            let mut track_lock = video_tracks_arc.lock();
+            tokio::runtime::Runtime::new().unwrap().block_on(async {
+                let mut video_track = DeviceVideoTrack::new(cloned_room);
+                video_track.publish(&cloned_track_name, image_receiver).await;
+                track_lock.insert(cloned_track_name, video_track);
+            });
+        });
+    }
+
+    fn unpublish_video(&self, track_name: &str) {
+        if !self.is_video_published(track_name) {
+            return;
+        }
+
+        let cloned_track_name = track_name.to_string();
+        let video_tracks_arc = Arc::clone(&self.video_tracks);
+        std::thread::spawn( move || {
+            let mut track_lock = video_tracks_arc.lock();
+            tokio::runtime::Runtime::new().unwrap().block_on(async {
+                if let Some(video_track) = track_lock.get_mut(&cloned_track_name) {
+                    video_track.unpublish().await;
+                    track_lock.remove(&cloned_track_name);
+                }
+            });
+        });
+    }
+
+    fn disconnect(&self) {
+        let _ = self.stop_sender.send(true);
+        self.video_tracks.lock().clear();
+
+        if let Some(room) = self.room.lock().take() {
+            std::thread::spawn(move || {
+                tokio::runtime::Runtime::new().unwrap().block_on(async {
+                    let _ = room.close().await;
+                });
+            });
+        }
+    }
+}
+
+async fn forward_events(mut room_rx: UnboundedReceiver<RoomEvent>, stop_receiver: flume::Receiver<bool>, event_sender: flume::Sender<TransportEvent>) {
+    loop {
+        tokio::select! {
+            _ = stop_receiver.recv_async() => {
+                println!("stop loop");
+                break;
+            },
+            room_event = room_rx.recv() => {
+                match room_event {
+                    Some(room_event) => {
+                        if let Some(transport_event) = convert_room_event(room_event) {
+                            if event_sender.send(transport_event).is_err() {
+                                break;
+                            }
+                        }
+                    },
+                    None => {
+                        let _ = event_sender.send(TransportEvent::Disconnected);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// participants are told apart by identity, the name can be changed by the participant itself
+fn convert_room_event(room_event: RoomEvent) -> Option<TransportEvent> {
+    match room_event {
+        RoomEvent::Connected { participants_with_tracks } => {
+            let mut participants: Vec<TransportParticipant> = Vec::new();
+            for (remote_participant, remote_tracks) in participants_with_tracks.iter() {
+                for track in remote_tracks.iter() {
+                    track.set_subscribed(true);
+                }
+                participants.push(TransportParticipant {
+                    identity: remote_participant.identity().0,
+                    attributes: remote_participant.attributes()
+                });
+            }
+            return Some(TransportEvent::Connected { participants });
+        },
+        RoomEvent::ParticipantConnected(remote_participant) => {
+            return Some(TransportEvent::ParticipantConnected(TransportParticipant {
+                identity: remote_participant.identity().0,
+                attributes: remote_participant.attributes()
+            }));
+        },
+        RoomEvent::ParticipantDisconnected(remote_participant) => {
+            for (_, publication) in remote_participant.track_publications() {
+                publication.set_subscribed(false);
+            }
+            return Some(TransportEvent::ParticipantDisconnected(remote_participant.identity().0));
+        },
+        RoomEvent::ParticipantAttributesChanged { participant, changed_attributes } => {
+            return Some(TransportEvent::AttributesChanged {
+                identity: participant.identity().0,
+                changed: changed_attributes
+            });
+        },
+        RoomEvent::DataReceived { payload, topic, participant, .. } => {
+            return Some(TransportEvent::DataReceived {
+                identity: participant.map(|participant| participant.identity().0),
+                topic,
+                payload: (*payload).to_vec()
+            });
+        },
+        RoomEvent::TrackPublished { publication, participant } if publication.kind() == TrackKind::Video => {
+            return Some(TransportEvent::VideoTrackPublished {
+                identity: participant.identity().0,
+                track_name: publication.name()
+            });
+        },
+        RoomEvent::TrackUnpublished { publication, participant } if publication.kind() == TrackKind::Video => {
+            return Some(TransportEvent::VideoTrackUnpublished {
+                identity: participant.identity().0,
+                track_name: publication.name()
+            });
+        },
+        RoomEvent::Reconnecting => {
+            return Some(TransportEvent::Reconnecting);
+        },
+        RoomEvent::Reconnected => {
+            return Some(TransportEvent::Reconnected);
+        },
+        RoomEvent::Disconnected { .. } => {
+            return Some(TransportEvent::Disconnected);
+        },
+        _ => {
+            println!("{:?}", room_event);
+            return None;
+        }
+    }
+}
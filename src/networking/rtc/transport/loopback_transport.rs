@@ -0,0 +1,272 @@
+use std::{collections::{HashMap, HashSet}, sync::OnceLock};
+
+use image::RgbaImage;
+use parking_lot::Mutex;
+
+use super::{RoomConnection, RoomListing, RoomTransport, TransportConnectOptions, TransportError, TransportEvent, TransportParticipant, TransportReliability, MAX_ROOM_PARTICIPANTS};
+
+struct LoopbackMember {
+    attributes: HashMap<String, String>,
+    event_sender: flume::Sender<TransportEvent>,
+    video_tracks: HashSet<String>
+}
+
+struct LoopbackRoom {
+    metadata: String,
+    members: HashMap<String, LoopbackMember>
+}
+
+impl LoopbackRoom {
+    fn broadcast(&self, sender_identity: &str, event: TransportEvent) {
+        for (identity, member) in self.members.iter() {
+            if identity.eq(sender_identity) {
+                continue;
+            }
+            let _ = member.event_sender.send(event.clone());
+        }
+    }
+}
+
+// rooms live in this process only, so every client of a room has to run in the same app
+static LOOPBACK_ROOMS: OnceLock<Mutex<HashMap<String, LoopbackRoom>>> = OnceLock::new();
+
+fn get_loopback_rooms() -> &'static Mutex<HashMap<String, LoopbackRoom>> {
+    return LOOPBACK_ROOMS.get_or_init(|| Mutex::new(HashMap::new()));
+}
+
+pub struct LoopbackTransport {
+    // idle clients joined to every room this process creates, so there is someone to see
+    pub simulated_clients: usize
+}
+
+impl Default for LoopbackTransport {
+    fn default() -> LoopbackTransport {
+        LoopbackTransport {
+            simulated_clients: 0
+        }
+    }
+}
+
+impl LoopbackTransport {
+    fn spawn_simulated_clients(&self, room_id: &str, host_identity: &str) {
+        for i in 0..self.simulated_clients {
+            let (event_sender, event_receiver) = flume::unbounded::<TransportEvent>();
+
+            let mut attributes: HashMap<String, String> = HashMap::from([
+                (String::from("name"), format!("Simulated {}", i))
+            ]);
+            for part in ["hair", "eyes", "head", "upper", "hip", "legs"] {
+                attributes.insert(part.to_string(), "0".to_string());
+            }
+
+            let connection = match self.connect(TransportConnectOptions {
+                room_id: room_id.to_string(),
+                identity: format!("simulated-{}", i),
+                create_metadata: None,
+                password: String::new(),
+                invite_code: String::new(),
+                session_key: String::new(),
+                attributes,
+                event_sender
+            }) {
+                Ok(connection) => connection,
+                Err(e) => {
+                    println!("loopback simulated client {} failed: {}", i, e);
+                    continue;
+                }
+            };
+
+            // the simulated client leaves together with whoever created the room
+            let host_identity = host_identity.to_string();
+            std::thread::spawn(move || {
+                while let Ok(event) = event_receiver.recv() {
+                    match event {
+                        TransportEvent::ParticipantDisconnected(identity) if identity.eq(&host_identity) => break,
+                        TransportEvent::Disconnected => break,
+                        _ => {}
+                    }
+                }
+                connection.disconnect();
+            });
+        }
+    }
+}
+
+impl RoomTransport for LoopbackTransport {
+    fn name(&self) -> String {
+        return "loopback".to_string();
+    }
+
+    fn get_room(&self, room_id: &str) -> Result<Option<RoomListing>, TransportError> {
+        return Ok(get_loopback_rooms().lock().get(room_id).map(|room| RoomListing {
+            room_id: room_id.to_string(),
+            participants: room.members.len() as u32,
+            metadata: room.metadata.to_string()
+        }));
+    }
+
+    fn list_rooms(&self) -> Result<Vec<RoomListing>, TransportError> {
+        return Ok(get_loopback_rooms().lock().iter().map(|(room_id, room)| RoomListing {
+            room_id: room_id.to_string(),
+            participants: room.members.len() as u32,
+            metadata: room.metadata.to_string()
+        }).collect());
+    }
+
+    fn connect(&self, options: TransportConnectOptions) -> Result<Box<dyn RoomConnection>, TransportError> {
+        let is_creating = options.create_metadata.is_some();
+        let metadata: String;
+        {
+            let mut rooms = get_loopback_rooms().lock();
+            if let Some(create_metadata) = options.create_metadata {
+                rooms.entry(options.room_id.to_string()).or_insert(LoopbackRoom {
+                    metadata: create_metadata,
+                    members: HashMap::new()
+                });
+            }
+
+            let room = match rooms.get_mut(&options.room_id) {
+                Some(room) => room,
+                None => return Err(TransportError::RoomNotFound)
+            };
+            if room.members.contains_key(&options.identity) {
+                return Err(TransportError::Unauthorized(format!("{} is already in the room", options.identity)));
+            }
+            if room.members.len() as u32 >= MAX_ROOM_PARTICIPANTS {
+                return Err(TransportError::RoomFull);
+            }
+
+            let participants: Vec<TransportParticipant> = room.members.iter().map(|(identity, member)| TransportParticipant {
+                identity: identity.to_string(),
+                attributes: member.attributes.clone()
+            }).collect();
+            let _ = options.event_sender.send(TransportEvent::Connected { participants });
+
+            for (identity, member) in room.members.iter() {
+                for track_name in member.video_tracks.iter() {
+                    let _ = options.event_sender.send(TransportEvent::VideoTrackPublished {
+                        identity: identity.to_string(),
+                        track_name: track_name.to_string()
+                    });
+                }
+            }
+
+            room.broadcast(&options.identity, TransportEvent::ParticipantConnected(TransportParticipant {
+                identity: options.identity.to_string(),
+                attributes: options.attributes.clone()
+            }));
+            room.members.insert(options.identity.to_string(), LoopbackMember {
+                attributes: options.attributes,
+                event_sender: options.event_sender,
+                video_tracks: HashSet::new()
+            });
+            metadata = room.metadata.to_string();
+        }
+
+        if is_creating {
+            self.spawn_simulated_clients(&options.room_id, &options.identity);
+        }
+
+        return Ok(Box::new(LoopbackConnection {
+            room_id: options.room_id,
+            identity: options.identity,
+            metadata
+        }));
+    }
+}
+
+pub struct LoopbackConnection {
+    room_id: String,
+    identity: String,
+    metadata: String
+}
+
+impl RoomConnection for LoopbackConnection {
+    fn metadata(&self) -> String {
+        return self.metadata.to_string();
+    }
+
+    fn send_data(&self, topic: &str, payload: Vec<u8>, _reliability: TransportReliability, destinations: Vec<String>) {
+        if let Some(room) = get_loopback_rooms().lock().get(&self.room_id) {
+            let event = TransportEvent::DataReceived {
+                identity: Some(self.identity.to_string()),
+                topic: Some(topic.to_string()),
+                payload
+            };
+            if destinations.is_empty() {
+                room.broadcast(&self.identity, event);
+                return;
+            }
+            for destination in destinations.iter() {
+                if let Some(member) = room.members.get(destination) {
+                    let _ = member.event_sender.send(event.clone());
+                }
+            }
+        }
+    }
+
+    fn set_attributes(&self, attributes: HashMap<String, String>) {
+        if let Some(room) = get_loopback_rooms().lock().get_mut(&self.room_id) {
+            if let Some(member) = room.members.get_mut(&self.identity) {
+                member.attributes.extend(attributes.clone());
+            }
+            room.broadcast(&self.identity, TransportEvent::AttributesChanged {
+                identity: self.identity.to_string(),
+                changed: attributes
+            });
+        }
+    }
+
+    fn is_video_published(&self, track_name: &str) -> bool {
+        if let Some(room) = get_loopback_rooms().lock().get(&self.room_id) {
+            if let Some(member) = room.members.get(&self.identity) {
+                return member.video_tracks.contains(track_name);
+            }
+        }
+        return false;
+    }
+
+    // only the track name is shared; the frames stay with the local camera preview
+    fn publish_video(&self, track_name: &str, _image_receiver: flume::Receiver<RgbaImage>) {
+        if let Some(room) = get_loopback_rooms().lock().get_mut(&self.room_id) {
+            if let Some(member) = room.members.get_mut(&self.identity) {
+                if !member.video_tracks.insert(track_name.to_string()) {
+                    return;
+                }
+            }
+            room.broadcast(&self.identity, TransportEvent::VideoTrackPublished {
+                identity: self.identity.to_string(),
+                track_name: track_name.to_string()
+            });
+        }
+    }
+
+    fn unpublish_video(&self, track_name: &str) {
+        if let Some(room) = get_loopback_rooms().lock().get_mut(&self.room_id) {
+            if let Some(member) = room.members.get_mut(&self.identity) {
+                if !member.video_tracks.remove(track_name) {
+                    return;
+                }
+            }
+            room.broadcast(&self.identity, TransportEvent::VideoTrackUnpublished {
+                identity: self.identity.to_string(),
+                track_name: track_name.to_string()
+            });
+        }
+    }
+
+    fn disconnect(&self) {
+        let mut rooms = get_loopback_rooms().lock();
+        let mut is_empty = false;
+        if let Some(room) = rooms.get_mut(&self.room_id) {
+            if room.members.remove(&self.identity).is_none() {
+                return;
+            }
+            room.broadcast(&self.identity, TransportEvent::ParticipantDisconnected(self.identity.to_string()));
+            is_empty = room.members.is_empty();
+        }
+        if is_empty {
+            rooms.remove(&self.room_id);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use crate::config::resources::AppConfig;
+
+pub mod livekit_transport;
+pub mod loopback_transport;
+pub mod token_provider;
+
+// rooms are created with this cap, joining a room at it fails with RoomFull
+pub const MAX_ROOM_PARTICIPANTS: u32 = 50;
+// how long a room lookup or connect may take before the join is given up
+pub const CONNECT_TIMEOUT_SECONDS: u64 = 15;
+
+#[derive(Clone, Debug)]
+pub struct TransportParticipant {
+    pub identity: String,
+    pub attributes: HashMap<String, String>
+}
+
+// one active room as seen by the room browser, metadata is the RoomMetadata json it was created with,
+// minus the secrets of a private room when it comes from the token server
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RoomListing {
+    pub room_id: String,
+    pub participants: u32,
+    pub metadata: String
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TransportError {
+    RoomNotFound,
+    RoomFull,
+    // the token server or LiveKit turned this identity away
+    Unauthorized(String),
+    Timeout,
+    Network(String)
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransportError::RoomNotFound => return write!(f, "room not found"),
+            TransportError::RoomFull => return write!(f, "room is full"),
+            TransportError::Unauthorized(reason) => return write!(f, "unauthorized: {}", reason),
+            TransportError::Timeout => return write!(f, "timed out"),
+            TransportError::Network(reason) => return write!(f, "{}", reason)
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransportReliability {
+    Reliable,
+    Lossy
+}
+
+#[derive(Clone, Debug)]
+pub enum TransportEvent {
+    Connected { participants: Vec<TransportParticipant> },
+    ParticipantConnected(TransportParticipant),
+    ParticipantDisconnected(String),
+    AttributesChanged { identity: String, changed: HashMap<String, String> },
+    DataReceived { identity: Option<String>, topic: Option<String>, payload: Vec<u8> },
+    VideoTrackPublished { identity: String, track_name: String },
+    VideoTrackUnpublished { identity: String, track_name: String },
+    // the transport is resuming the same session by itself
+    Reconnecting,
+    Reconnected,
+    Disconnected
+}
+
+pub struct TransportConnectOptions {
+    pub room_id: String,
+    pub identity: String,
+    // Some creates the room with this metadata, None joins an existing room
+    pub create_metadata: Option<String>,
+    // sent along with the token request, private rooms are checked by whoever hands out the token
+    pub password: String,
+    pub invite_code: String,
+    // made once per session, lets the same client take its identity back after a dropped connection
+    pub session_key: String,
+    pub attributes: HashMap<String, String>,
+    pub event_sender: flume::Sender<TransportEvent>
+}
+
+// every call blocks, so callers run them on their own thread or task
+pub trait RoomTransport: Send + Sync {
+    fn name(&self) -> String;
+    // Ok(None) when the room does not exist
+    fn get_room(&self, room_id: &str) -> Result<Option<RoomListing>, TransportError>;
+    fn list_rooms(&self) -> Result<Vec<RoomListing>, TransportError>;
+    // returns the room metadata once connected, or an error when the room is missing, full or unreachable
+    fn connect(&self, options: TransportConnectOptions) -> Result<Box<dyn RoomConnection>, TransportError>;
+}
+
+pub trait RoomConnection: Send + Sync {
+    fn metadata(&self) -> String;
+    // an empty destinations list sends to everyone else in the room
+    fn send_data(&self, topic: &str, payload: Vec<u8>, reliability: TransportReliability, destinations: Vec<String>);
+    fn set_attributes(&self, attributes: HashMap<String, String>);
+    fn is_video_published(&self, track_name: &str) -> bool;
+    fn publish_video(&self, track_name: &str, image_receiver: flume::Receiver<RgbaImage>);
+    fn unpublish_video(&self, track_name: &str);
+    fn disconnect(&self);
+}
+
+// transport = "loopback" keeps rooms inside this process, for running without a LiveKit server
+pub fn get_room_transport(config: &AppConfig) -> Arc<dyn RoomTransport> {
+    if config.offline || config.transport.eq_ignore_ascii_case("loopback") {
+        // loopback_clients adds idle participants to every room created here
+        return Arc::new(loopback_transport::LoopbackTransport {
+            simulated_clients: config.loopback_clients
+        });
+    }
+    return Arc::new(livekit_transport::LiveKitTransport {
+        token_provider: token_provider::get_token_provider(&config.token_endpoint, &config.livekit_url).map(|provider| Arc::from(provider))
+    });
+}
\ No newline at end of file
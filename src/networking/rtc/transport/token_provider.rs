@@ -0,0 +1,243 @@
+use std::{env, time::Duration};
+
+use livekit_api::{access_token, services::room::{CreateRoomOptions, RoomClient}};
+use serde::{Deserialize, Serialize};
+
+use super::{RoomListing, TransportError, CONNECT_TIMEOUT_SECONDS, MAX_ROOM_PARTICIPANTS};
+
+// tokens only need to outlive the connect call, the session keeps working after they expire
+pub const ROOM_TOKEN_TTL_SECONDS: u64 = 10 * 60;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RoomToken {
+    pub url: String,
+    pub token: String
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateRoomRequest {
+    pub room_id: String,
+    pub metadata: String
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RoomTokenRequest {
+    pub room_id: String,
+    pub identity: String,
+    // checked by the token server when the room is private
+    #[serde(default)]
+    pub password: String,
+    #[serde(default)]
+    pub invite_code: String,
+    // an identity already in the room is only handed out again for the same session key
+    #[serde(default)]
+    pub session_key: String
+}
+
+// anything that needs the LiveKit API secret goes through here, so the client never holds it in release builds
+pub trait TokenProvider: Send + Sync {
+    fn name(&self) -> String;
+    fn get_room(&self, room_id: &str) -> Result<Option<RoomListing>, TransportError>;
+    fn list_rooms(&self) -> Result<Vec<RoomListing>, TransportError>;
+    fn create_room(&self, room_id: &str, metadata: &str) -> Result<(), TransportError>;
+    fn join_token(&self, room_id: &str, identity: &str, password: &str, invite_code: &str, session_key: &str) -> Result<RoomToken, TransportError>;
+}
+
+// a token endpoint wins over local signing; with neither configured every join fails with an error
+pub fn get_token_provider(token_endpoint: &str, livekit_url: &str) -> Option<Box<dyn TokenProvider>> {
+    if !token_endpoint.is_empty() {
+        return Some(Box::new(HttpTokenProvider {
+            endpoint: token_endpoint.trim_end_matches('/').to_string()
+        }));
+    }
+    if let Some(provider) = SelfSignedTokenProvider::from_env(livekit_url) {
+        return Some(Box::new(provider));
+    }
+    return None;
+}
+
+// asks a token server (see src/bin/token_server.rs) for short-lived join tokens
+pub struct HttpTokenProvider {
+    pub endpoint: String
+}
+
+impl TokenProvider for HttpTokenProvider {
+    fn name(&self) -> String {
+        return format!("http ({})", self.endpoint);
+    }
+
+    fn get_room(&self, room_id: &str) -> Result<Option<RoomListing>, TransportError> {
+        match ureq::get(&format!("{}/rooms", self.endpoint)).query("room_id", room_id).timeout(Duration::from_secs(CONNECT_TIMEOUT_SECONDS)).call() {
+            Ok(response) => return response.into_json::<RoomListing>().map(|room| Some(room)).map_err(|e| TransportError::Network(e.to_string())),
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(e) => return Err(get_http_error(e))
+        }
+    }
+
+    fn list_rooms(&self) -> Result<Vec<RoomListing>, TransportError> {
+        let response = ureq::get(&format!("{}/rooms", self.endpoint)).timeout(Duration::from_secs(CONNECT_TIMEOUT_SECONDS)).call().map_err(get_http_error)?;
+        return response.into_json::<Vec<RoomListing>>().map_err(|e| TransportError::Network(e.to_string()));
+    }
+
+    fn create_room(&self, room_id: &str, metadata: &str) -> Result<(), TransportError> {
+        match ureq::post(&format!("{}/rooms", self.endpoint))
+        .timeout(Duration::from_secs(CONNECT_TIMEOUT_SECONDS))
+        .send_json(CreateRoomRequest {
+            room_id: room_id.to_string(),
+            metadata: metadata.to_string()
+        }) {
+            Ok(_) => return Ok(()),
+            // the room is still open, e.g. the host reconnecting, and keeps the metadata it was made with
+            Err(ureq::Error::Status(409, _)) => return Ok(()),
+            Err(e) => return Err(get_http_error(e))
+        }
+    }
+
+    fn join_token(&self, room_id: &str, identity: &str, password: &str, invite_code: &str, session_key: &str) -> Result<RoomToken, TransportError> {
+        let response = ureq::post(&format!("{}/token", self.endpoint))
+        .timeout(Duration::from_secs(CONNECT_TIMEOUT_SECONDS))
+        .send_json(RoomTokenRequest {
+            room_id: room_id.to_string(),
+            identity: identity.to_string(),
+            password: password.to_string(),
+            invite_code: invite_code.to_string(),
+            session_key: session_key.to_string()
+        })
+        .map_err(get_http_error)?;
+        return response.into_json::<RoomToken>().map_err(|e| TransportError::Network(e.to_string()));
+    }
+}
+
+fn get_http_error(error: ureq::Error) -> TransportError {
+    match error {
+        ureq::Error::Status(401, response) | ureq::Error::Status(403, response) => return TransportError::Unauthorized(response.into_string().unwrap_or_default()),
+        ureq::Error::Status(404, _) => return TransportError::RoomNotFound,
+        ureq::Error::Status(code, _) => return TransportError::Network(format!("token server answered {}", code)),
+        ureq::Error::Transport(transport) => {
+            // ureq reports its own timeouts as io errors
+            let is_timeout = std::error::Error::source(&transport)
+            .and_then(|source| source.downcast_ref::<std::io::Error>())
+            .map(|io_error| io_error.kind() == std::io::ErrorKind::TimedOut || io_error.kind() == std::io::ErrorKind::WouldBlock)
+            .unwrap_or(false);
+            if is_timeout {
+                return TransportError::Timeout;
+            }
+            return TransportError::Network(transport.to_string());
+        }
+    }
+}
+
+// development only: signs tokens with LIVEKIT_API_KEY and LIVEKIT_API_SECRET from the environment
+pub struct SelfSignedTokenProvider {
+    pub url: String,
+    pub api_key: String,
+    pub api_secret: String
+}
+
+impl SelfSignedTokenProvider {
+    pub fn from_env(url: &str) -> Option<SelfSignedTokenProvider> {
+        if url.is_empty() {
+            return None;
+        }
+        if let (Ok(api_key), Ok(api_secret)) = (env::var("LIVEKIT_API_KEY"), env::var("LIVEKIT_API_SECRET")) {
+            return Some(SelfSignedTokenProvider {
+                url: url.to_string(),
+                api_key,
+                api_secret
+            });
+        }
+        return None;
+    }
+
+    fn get_room_service(&self) -> RoomClient {
+        let mut https_url = self.url.to_string();
+        if https_url.starts_with("wss") {
+            https_url = https_url.replace("wss", "https");
+        }
+        return RoomClient::with_api_key(&https_url, &self.api_key, &self.api_secret);
+    }
+}
+
+impl TokenProvider for SelfSignedTokenProvider {
+    fn name(&self) -> String {
+        return "self-signed".to_string();
+    }
+
+    fn get_room(&self, room_id: &str) -> Result<Option<RoomListing>, TransportError> {
+        return list_room(self.get_room_service(), room_id.to_string()).map_err(TransportError::Network);
+    }
+
+    fn list_rooms(&self) -> Result<Vec<RoomListing>, TransportError> {
+        return list_all_rooms(self.get_room_service()).map_err(TransportError::Network);
+    }
+
+    fn create_room(&self, room_id: &str, metadata: &str) -> Result<(), TransportError> {
+        return create_room(self.get_room_service(), room_id.to_string(), metadata.to_string()).map_err(TransportError::Network);
+    }
+
+    // development only, the client already checked private rooms against the full metadata
+    fn join_token(&self, room_id: &str, identity: &str, _password: &str, _invite_code: &str, _session_key: &str) -> Result<RoomToken, TransportError> {
+        let token = sign_room_token(&self.api_key, &self.api_secret, room_id, identity).map_err(TransportError::Unauthorized)?;
+        return Ok(RoomToken {
+            url: self.url.to_string(),
+            token
+        });
+    }
+}
+
+pub fn sign_room_token(api_key: &str, api_secret: &str, room_id: &str, identity: &str) -> Result<String, String> {
+    return access_token::AccessToken::with_api_key(api_key, api_secret)
+    .with_identity(identity)
+    .with_name(identity)
+    .with_ttl(Duration::from_secs(ROOM_TOKEN_TTL_SECONDS))
+    .with_grants(access_token::VideoGrants {
+        room_join: true,
+        room: room_id.to_string(),
+        can_update_own_metadata: true,
+        ..Default::default()
+    })
+    .to_jwt()
+    .map_err(|e| format!("{:?}", e));
+}
+
+#[tokio::main]
+async fn list_room(room_service: RoomClient, room_id: String) -> Result<Option<RoomListing>, String> {
+    match room_service.list_rooms(vec![room_id]).await {
+        Ok(rooms) => {
+            return Ok(rooms.into_iter().next().map(|room| RoomListing {
+                room_id: room.name,
+                participants: room.num_participants,
+                metadata: room.metadata
+            }));
+        },
+        Err(e) => return Err(format!("{:?}", e))
+    }
+}
+
+#[tokio::main]
+async fn list_all_rooms(room_service: RoomClient) -> Result<Vec<RoomListing>, String> {
+    match room_service.list_rooms(vec![]).await {
+        Ok(rooms) => {
+            return Ok(rooms.into_iter().map(|room| RoomListing {
+                room_id: room.name,
+                participants: room.num_participants,
+                metadata: room.metadata
+            }).collect());
+        },
+        Err(e) => return Err(format!("{:?}", e))
+    }
+}
+
+#[tokio::main]
+async fn create_room(room_service: RoomClient, room_id: String, metadata: String) -> Result<(), String> {
+    return room_service
+    .create_room(&room_id, CreateRoomOptions {
+        empty_timeout: 30,
+        max_participants: MAX_ROOM_PARTICIPANTS,
+        metadata,
+        ..Default::default()
+    })
+    .await
+    .map(|_| ())
+    .map_err(|e| format!("{:?}", e));
+}
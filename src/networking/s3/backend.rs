@@ -0,0 +1,258 @@
+use std::{fmt, fs, path::PathBuf};
+
+use aws_sdk_s3 as s3;
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::primitives::{ByteStream, DateTime, DateTimeFormat};
+
+pub enum StorageFetch {
+    Modified { bytes: Vec<u8>, etag: Option<String>, last_modified: Option<String> },
+    NotModified,
+    NotFound,
+    Unreachable(String)
+}
+
+#[derive(Clone, Debug)]
+pub enum DownloadError {
+    NoStorage,
+    NotFound,
+    Unreachable(String),
+    InvalidData(String)
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DownloadError::NoStorage => write!(f, "no storage backend"),
+            DownloadError::NotFound => write!(f, "not found"),
+            DownloadError::Unreachable(e) => write!(f, "storage unreachable: {}", e),
+            DownloadError::InvalidData(e) => write!(f, "invalid data: {}", e)
+        }
+    }
+}
+
+// blocking object storage, called from inside AsyncComputeTaskPool tasks
+pub trait StorageBackend: Send + Sync {
+    fn name(&self) -> String;
+    // etag and last_modified come from a cached copy; the backend answers NotModified when it is still fresh
+    fn fetch_object(&self, bucket: &str, key: &str, etag: Option<String>, last_modified: Option<String>) -> StorageFetch;
+    fn put_object(&self, bucket: &str, key: &str, bytes: Vec<u8>) -> bool;
+    // an error instead of a partial list, callers decide whether a key exists from it
+    fn list_keys(&self, bucket: &str, prefix: &str) -> Result<Vec<String>, String>;
+
+    fn get_object(&self, bucket: &str, key: &str) -> Vec<u8> {
+        match self.fetch_object(bucket, key, None, None) {
+            StorageFetch::Modified { bytes, .. } => bytes,
+            _ => Vec::new()
+        }
+    }
+}
+
+pub struct S3Backend {
+    pub s3_client: s3::Client
+}
+
+impl StorageBackend for S3Backend {
+    fn name(&self) -> String {
+        return "s3".to_string();
+    }
+
+    fn fetch_object(&self, bucket: &str, key: &str, etag: Option<String>, last_modified: Option<String>) -> StorageFetch {
+        return s3_fetch_bytes(self.s3_client.clone(), bucket.to_string(), key.to_string(), etag, last_modified);
+    }
+
+    fn put_object(&self, bucket: &str, key: &str, bytes: Vec<u8>) -> bool {
+        return s3_put_bytes(self.s3_client.clone(), bucket.to_string(), key.to_string(), bytes);
+    }
+
+    fn list_keys(&self, bucket: &str, prefix: &str) -> Result<Vec<String>, String> {
+        return s3_list_keys(self.s3_client.clone(), bucket.to_string(), prefix.to_string());
+    }
+}
+
+#[tokio::main]
+async fn s3_fetch_bytes(s3_client: s3::Client, bucket: String, key: String, etag: Option<String>, last_modified: Option<String>) -> StorageFetch {
+    let if_modified_since = last_modified.and_then(|date| DateTime::from_str(&date, DateTimeFormat::HttpDate).ok());
+    let builder = s3_client.get_object().bucket(bucket).key(key).set_if_none_match(etag).set_if_modified_since(if_modified_since);
+
+    match builder.send().await {
+        Ok(mut object) => {
+            let etag = object.e_tag().map(|etag| etag.to_string());
+            let last_modified = object.last_modified().and_then(|date| date.fmt(DateTimeFormat::HttpDate).ok());
+            let mut bytes: Vec<u8> = Vec::new();
+            loop {
+                match object.body.try_next().await {
+                    Ok(Some(chunk)) => bytes.extend_from_slice(&chunk),
+                    Ok(None) => break,
+                    Err(e) => return StorageFetch::Unreachable(format!("{:?}", e))
+                }
+            }
+            return StorageFetch::Modified { bytes, etag, last_modified };
+        },
+        Err(SdkError::ServiceError(e)) => {
+            match e.raw().status().as_u16() {
+                304 => return StorageFetch::NotModified,
+                404 => return StorageFetch::NotFound,
+                _ => {}
+            }
+            if e.err().is_no_such_key() {
+                return StorageFetch::NotFound;
+            }
+            return StorageFetch::Unreachable(format!("{:?}", e.err()));
+        },
+        Err(e) => {
+            return StorageFetch::Unreachable(format!("{:?}", e));
+        }
+    }
+}
+
+#[tokio::main]
+async fn s3_put_bytes(s3_client: s3::Client, bucket: String, key: String, bytes: Vec<u8>) -> bool {
+    match s3_client.put_object().bucket(bucket).key(key).body(ByteStream::from(bytes)).send().await {
+        Ok(_) => {
+            return true;
+        },
+        Err(e) => {
+            println!("s3_put_bytes failed: {:?}", e);
+            return false;
+        }
+    }
+}
+
+#[tokio::main]
+async fn s3_list_keys(s3_client: s3::Client, bucket: String, prefix: String) -> Result<Vec<String>, String> {
+    let mut keys: Vec<String> = Vec::new();
+    let mut continuation_token: Option<String> = None;
+
+    loop {
+        let output = match s3_client.list_objects_v2().bucket(bucket.to_string()).prefix(prefix.to_string()).set_continuation_token(continuation_token.clone()).send().await {
+            Ok(output) => output,
+            Err(e) => {
+                println!("s3_list_keys failed: {:?}", e);
+                return Err(format!("{}", e));
+            }
+        };
+        for object in output.contents() {
+            if let Some(key) = object.key() {
+                keys.push(key.to_string());
+            }
+        }
+        continuation_token = output.next_continuation_token().map(|token| token.to_string());
+        if continuation_token.is_none() {
+            break;
+        }
+    }
+    return Ok(keys);
+}
+
+// mirrors the bucket layout on disk, e.g. <root>/map/0.txt and <root>/character/head/0.png
+pub struct LocalBackend {
+    pub root: PathBuf
+}
+
+impl LocalBackend {
+    fn get_path(&self, key: &str) -> Option<PathBuf> {
+        // keys come from the network, so never let them escape the root
+        if key.split('/').any(|part| part.is_empty() || part.eq("..")) {
+            return None;
+        }
+        return Some(self.root.join(key));
+    }
+}
+
+impl StorageBackend for LocalBackend {
+    fn name(&self) -> String {
+        return format!("local ({})", self.root.display());
+    }
+
+    fn fetch_object(&self, _bucket: &str, key: &str, _etag: Option<String>, _last_modified: Option<String>) -> StorageFetch {
+        if let Some(path) = self.get_path(key) {
+            if let Ok(bytes) = fs::read(path) {
+                return StorageFetch::Modified { bytes, etag: None, last_modified: None };
+            }
+        }
+        return StorageFetch::NotFound;
+    }
+
+    fn put_object(&self, _bucket: &str, key: &str, bytes: Vec<u8>) -> bool {
+        let path = match self.get_path(key) {
+            Some(path) => path,
+            None => return false
+        };
+        if let Some(parent) = path.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                println!("local put_object failed: {:?}", e);
+                return false;
+            }
+        }
+        match fs::write(path, bytes) {
+            Ok(_) => {
+                return true;
+            },
+            Err(e) => {
+                println!("local put_object failed: {:?}", e);
+                return false;
+            }
+        }
+    }
+
+    fn list_keys(&self, _bucket: &str, prefix: &str) -> Result<Vec<String>, String> {
+        let mut keys: Vec<String> = Vec::new();
+        let mut dirs: Vec<PathBuf> = Vec::from([self.root.clone()]);
+
+        while let Some(dir) = dirs.pop() {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("{}: {}", dir.display(), e))
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                if let Ok(relative) = path.strip_prefix(&self.root) {
+                    let key = relative.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect::<Vec<String>>().join("/");
+                    if key.starts_with(prefix) {
+                        keys.push(key);
+                    }
+                }
+            }
+        }
+        keys.sort();
+        return Ok(keys);
+    }
+}
+
+// offline mode: maps ship under assets/map, avatar parts without a bundled image fall back to their placeholder
+pub struct BundledBackend {
+    pub assets: LocalBackend
+}
+
+impl StorageBackend for BundledBackend {
+    fn name(&self) -> String {
+        return format!("bundled ({})", self.assets.root.display());
+    }
+
+    fn fetch_object(&self, bucket: &str, key: &str, etag: Option<String>, last_modified: Option<String>) -> StorageFetch {
+        match self.assets.fetch_object(bucket, key, etag.clone(), last_modified.clone()) {
+            StorageFetch::NotFound => {
+                // character/<part>/<index>.png -> placeholder/<part>.png
+                let mut parts = key.split('/');
+                if let (Some("character"), Some(part)) = (parts.next(), parts.next()) {
+                    return self.assets.fetch_object(bucket, &format!("placeholder/{}.png", part), etag, last_modified);
+                }
+                return StorageFetch::NotFound;
+            },
+            fetch => return fetch
+        }
+    }
+
+    fn put_object(&self, bucket: &str, key: &str, bytes: Vec<u8>) -> bool {
+        return self.assets.put_object(bucket, key, bytes);
+    }
+
+    fn list_keys(&self, bucket: &str, prefix: &str) -> Result<Vec<String>, String> {
+        return self.assets.list_keys(bucket, prefix);
+    }
+}
@@ -0,0 +1,190 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+pub const DISK_CACHE_MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DiskCacheEntry {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub size: u64,
+    pub last_access: i64
+}
+
+// the same key can hold different objects on another backend or bucket
+pub fn get_cache_key(backend: &str, bucket: &str, key: &str) -> String {
+    return format!("{}/{}/{}", get_cache_segment(backend), get_cache_segment(bucket), key);
+}
+
+// backend names carry paths and spaces, which must not become folders of their own
+fn get_cache_segment(name: &str) -> String {
+    let segment: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+    if segment.is_empty() {
+        return "_".to_string();
+    }
+    return segment;
+}
+
+// objects are stored under <root>/objects/<key>, with their validators in <root>/index.json
+pub struct DiskCache {
+    pub root: PathBuf,
+    pub max_bytes: u64,
+    pub entries: HashMap<String, DiskCacheEntry>
+}
+
+impl DiskCache {
+    pub fn open(root: PathBuf, max_bytes: u64) -> DiskCache {
+        let entries = match fs::read_to_string(root.join("index.json")) {
+            Ok(index_str) => serde_json::from_str::<HashMap<String, DiskCacheEntry>>(&index_str).unwrap_or_default(),
+            Err(_) => HashMap::new()
+        };
+        return DiskCache {
+            root,
+            max_bytes,
+            entries
+        };
+    }
+
+    fn get_path(&self, key: &str) -> Option<PathBuf> {
+        if key.split('/').any(|part| part.is_empty() || part.eq("..")) {
+            return None;
+        }
+        return Some(self.root.join("objects").join(key));
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<(Vec<u8>, DiskCacheEntry)> {
+        let path = self.get_path(key)?;
+        if !self.entries.contains_key(key) {
+            return None;
+        }
+        match fs::read(path) {
+            Ok(bytes) => {
+                let entry = self.entries.get_mut(key)?;
+                entry.last_access = chrono::Utc::now().timestamp();
+                let entry = entry.clone();
+                // eviction goes by last_access, so it has to outlive this session
+                self.save_index();
+                return Some((bytes, entry));
+            },
+            Err(_) => {
+                self.entries.remove(key);
+                self.save_index();
+                return None;
+            }
+        }
+    }
+
+    pub fn put(&mut self, key: &str, bytes: &[u8], etag: Option<String>, last_modified: Option<String>) {
+        let path = match self.get_path(key) {
+            Some(path) => path,
+            None => return
+        };
+        if bytes.len() as u64 > self.max_bytes {
+            return;
+        }
+        if let Some(parent) = path.parent() {
+            if fs::create_dir_all(parent).is_err() {
+                return;
+            }
+        }
+        if let Err(e) = fs::write(path, bytes) {
+            println!("disk cache write {} failed: {:?}", key, e);
+            return;
+        }
+        self.entries.insert(key.to_string(), DiskCacheEntry {
+            etag,
+            last_modified,
+            size: bytes.len() as u64,
+            last_access: chrono::Utc::now().timestamp()
+        });
+        self.evict();
+        self.save_index();
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        if self.entries.remove(key).is_some() {
+            if let Some(path) = self.get_path(key) {
+                let _ = fs::remove_file(path);
+            }
+            self.save_index();
+        }
+    }
+
+    // drops the least recently used objects until the cache fits in max_bytes
+    fn evict(&mut self) {
+        let mut total: u64 = self.entries.values().map(|entry| entry.size).sum();
+        if total <= self.max_bytes {
+            return;
+        }
+        let mut keys: Vec<(String, i64)> = self.entries.iter().map(|(key, entry)| (key.to_string(), entry.last_access)).collect();
+        keys.sort_by_key(|(_, last_access)| *last_access);
+
+        for (key, _) in keys.iter() {
+            if total <= self.max_bytes {
+                break;
+            }
+            if let Some(entry) = self.entries.remove(key) {
+                total -= entry.size;
+                if let Some(path) = self.get_path(key) {
+                    let _ = fs::remove_file(path);
+                }
+            }
+        }
+    }
+
+    fn save_index(&self) {
+        if fs::create_dir_all(&self.root).is_err() {
+            return;
+        }
+        if let Ok(index_str) = serde_json::to_string(&self.entries) {
+            if let Err(e) = fs::write(self.root.join("index.json"), index_str) {
+                println!("disk cache index write failed: {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_key_on_another_backend_or_bucket_is_cached_apart() {
+        let root = std::env::temp_dir().join(format!("shalloville-cache-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let mut cache = DiskCache::open(root.clone(), DISK_CACHE_MAX_BYTES);
+
+        let s3_key = get_cache_key("s3", "maps", "tilemap/a.json");
+        let local_key = get_cache_key("local (/tmp/assets)", "maps", "tilemap/a.json");
+        let other_bucket_key = get_cache_key("s3", "other", "tilemap/a.json");
+        assert_ne!(s3_key, local_key);
+        assert_ne!(s3_key, other_bucket_key);
+        assert_eq!(get_cache_key("local (/tmp/assets)", "", "a.json"), "local___tmp_assets_/_/a.json");
+
+        cache.put(&s3_key, b"s3", None, None);
+        cache.put(&local_key, b"local", None, None);
+        assert_eq!(cache.get(&s3_key).map(|(bytes, _)| bytes), Some(b"s3".to_vec()));
+        assert_eq!(cache.get(&local_key).map(|(bytes, _)| bytes), Some(b"local".to_vec()));
+        assert!(cache.get(&other_bucket_key).is_none());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn get_keeps_the_last_access_across_sessions() {
+        let root = std::env::temp_dir().join(format!("shalloville-cache-access-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let mut cache = DiskCache::open(root.clone(), DISK_CACHE_MAX_BYTES);
+        cache.put("a.json", b"a", None, None);
+        cache.entries.get_mut("a.json").unwrap().last_access = 0;
+        cache.save_index();
+
+        let (_, entry) = cache.get("a.json").unwrap();
+        let reopened = DiskCache::open(root.clone(), DISK_CACHE_MAX_BYTES);
+        assert_eq!(reopened.entries.get("a.json").map(|entry| entry.last_access), Some(entry.last_access));
+        assert!(entry.last_access > 0);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -1,5 +1,7 @@
 use bevy::prelude::*;
 
+use super::backend::DownloadError;
+
 use bevy::{
     ecs::world::CommandQueue,
     tasks::Task,
@@ -11,11 +13,23 @@ pub struct ComputeTask(pub (String, Task<CommandQueue>));
 #[derive(Component)]
 pub struct LoadTilemapTask {
     pub map_name: String,
-    pub map_str: String
+    pub map_str: String,
+    pub error: Option<DownloadError>
 }
 
 #[derive(Component)]
 pub struct LoadSpriteTask {
     pub image_handle: Handle<Image>,
     pub entity: Entity
+}
+
+#[derive(Component)]
+pub struct UploadTilemapTask {
+    pub map_name: String,
+    pub is_success: bool
+}
+
+#[derive(Component)]
+pub struct ListTilemapTask {
+    pub map_names: Result<Vec<String>, String>
 }
\ No newline at end of file
@@ -8,12 +8,25 @@ use resources::*;
 
 pub mod components;
 
+pub mod backend;
+
+pub mod cache;
+use backend::DownloadError;
+
 pub struct S3Plugin;
 
 impl Plugin for S3Plugin {
     fn build(&self, app: &mut App) {
         app
         .init_resource::<S3Data>()
-        .add_systems(Update, execute_tasks);
+        .add_event::<DownloadFailedEvent>()
+        .add_systems(Update, execute_tasks)
+        .add_systems(Update, show_download_failures);
     }
+}
+
+#[derive(Event)]
+pub struct DownloadFailedEvent {
+    pub key: String,
+    pub error: DownloadError
 }
\ No newline at end of file
@@ -1,13 +1,18 @@
 use std::{collections::HashMap, sync::Arc};
 
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use bevy::prelude::*;
-use aws_sdk_s3 as s3;
 use tokio::sync::Semaphore;
 
+use super::{backend::StorageBackend, cache::DiskCache};
+
+pub const DEFAULT_PARALLEL_DOWNLOADS: usize = 4;
+
 #[derive(Resource)]
 pub struct S3Data {
-    pub s3_client: Option<s3::Client>,
+    pub bucket: String,
+    pub storage: Option<Arc<dyn StorageBackend>>,
+    pub disk_cache: Option<Arc<Mutex<DiskCache>>>,
     pub s3_semaphore: Arc<Semaphore>,
     pub tilemap_cached: Arc<RwLock<HashMap<String, String>>>,
     pub image_cached: Arc<RwLock<HashMap<String, Handle<Image>>>>,
@@ -16,8 +21,10 @@ pub struct S3Data {
 impl Default for S3Data{
     fn default() -> S3Data {
         S3Data {
-            s3_client: None,
-            s3_semaphore: Arc::new(Semaphore::new(1)),
+            bucket: "shalloville".to_string(),
+            storage: None,
+            disk_cache: None,
+            s3_semaphore: Arc::new(Semaphore::new(DEFAULT_PARALLEL_DOWNLOADS)),
             tilemap_cached: Arc::new(RwLock::new(HashMap::new())),
             image_cached: Arc::new(RwLock::new(HashMap::new()))
         }
@@ -1,26 +1,76 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
-use aws_sdk_s3::operation::get_object::builders::GetObjectFluentBuilder;
 use bevy::tasks::AsyncComputeTaskPool;
 
 use bevy::{
     ecs::world::CommandQueue, prelude::*, render::render_asset::RenderAssetUsages, tasks::{block_on, futures_lite::future::{self}}
 };
 
+use bevy_egui::{egui, EguiContexts};
+
 use crate::editor::resources::Scene;
 
-use super::{components::{ComputeTask, LoadSpriteTask, LoadTilemapTask}, S3Data};
+use parking_lot::Mutex;
+use tokio::sync::Semaphore;
 
-#[tokio::main]
-async fn get_bytes(builder: GetObjectFluentBuilder) -> Vec<u8>{
-    let mut final_bytes: Vec<u8> = Vec::new();
+use super::{backend::{DownloadError, StorageBackend, StorageFetch}, cache::{get_cache_key, DiskCache}, components::{ComputeTask, ListTilemapTask, LoadSpriteTask, LoadTilemapTask, UploadTilemapTask}, DownloadFailedEvent, S3Data};
+
+const MAX_DOWNLOAD_ATTEMPTS: u32 = 3;
+const DOWNLOAD_RETRY_BASE_MS: u64 = 500;
 
-    if let Ok(mut object) = builder.send().await {
-        while let Ok(Some(bytes)) = object.body.try_next().await {
-            final_bytes = bytes.to_vec();
+// retries unreachable storage with backoff, then falls back to the disk cache copy
+// a permit is only held for each request, so a download waiting out its backoff does not hold up the others
+async fn fetch_with_cache(storage: &Arc<dyn StorageBackend>, disk_cache: &Option<Arc<Mutex<DiskCache>>>, semaphore: &Arc<Semaphore>, bucket: &str, key: &str) -> Result<Vec<u8>, DownloadError> {
+    let cache_key = get_cache_key(&storage.name(), bucket, key);
+    let cached = match disk_cache {
+        Some(disk_cache) => disk_cache.lock().get(&cache_key),
+        None => None
+    };
+    let (etag, last_modified) = match &cached {
+        Some((_, entry)) => (entry.etag.clone(), entry.last_modified.clone()),
+        None => (None, None)
+    };
+
+    let mut attempt: u32 = 0;
+    loop {
+        attempt += 1;
+        let permit = semaphore.acquire().await.unwrap();
+        let fetch = storage.fetch_object(bucket, key, etag.clone(), last_modified.clone());
+        drop(permit);
+        match fetch {
+            StorageFetch::Modified { bytes, etag, last_modified } => {
+                if let Some(disk_cache) = disk_cache {
+                    disk_cache.lock().put(&cache_key, &bytes, etag, last_modified);
+                }
+                return Ok(bytes);
+            },
+            StorageFetch::NotModified => {
+                return match cached {
+                    Some((bytes, _)) => Ok(bytes),
+                    None => Err(DownloadError::InvalidData("not modified without a cached copy".to_string()))
+                };
+            },
+            StorageFetch::NotFound => {
+                if let Some(disk_cache) = disk_cache {
+                    disk_cache.lock().remove(&cache_key);
+                }
+                return Err(DownloadError::NotFound);
+            },
+            StorageFetch::Unreachable(e) => {
+                if attempt >= MAX_DOWNLOAD_ATTEMPTS {
+                    return match cached {
+                        Some((bytes, _)) => {
+                            println!("fetch {} failed, using disk cache: {}", key, e);
+                            Ok(bytes)
+                        },
+                        None => Err(DownloadError::Unreachable(e))
+                    };
+                }
+                println!("fetch {} failed (attempt {}/{}): {}", key, attempt, MAX_DOWNLOAD_ATTEMPTS, e);
+                async_std::task::sleep(Duration::from_millis(DOWNLOAD_RETRY_BASE_MS * 2u64.pow(attempt - 1))).await;
+            }
         }
     }
-    return final_bytes;
 }
 
 fn convert_bytes_to_image(bytes: &mut Vec<u8>) -> Option<Image>{
@@ -39,10 +89,12 @@ fn convert_bytes_to_image(bytes: &mut Vec<u8>) -> Option<Image>{
 }
 
 #[tokio::main]
-pub async fn load_tilemap_from_s3(commands: &mut Commands, scene_uuid: &str, s3_data: &mut ResMut<S3Data>, bucket: String, key: String){
-    if let Some(s3_client) = &s3_data.s3_client {
+pub async fn load_tilemap_from_s3(commands: &mut Commands, scene_uuid: &str, s3_data: &mut ResMut<S3Data>, key: String){
+    if let Some(storage) = &s3_data.storage {
+        let bucket = s3_data.bucket.to_string();
         let full_key = format!("map/{}.txt", key);
-        let builder = s3_client.get_object().bucket(bucket.to_string()).key(full_key.to_string());
+        let storage = Arc::clone(storage);
+        let disk_cache = s3_data.disk_cache.clone();
         let semaphore = s3_data.s3_semaphore.clone();
         let thread_pool = AsyncComputeTaskPool::get();
 
@@ -51,7 +103,6 @@ pub async fn load_tilemap_from_s3(commands: &mut Commands, scene_uuid: &str, s3_
         let arc_tilemap_cached = Arc::clone(&s3_data.tilemap_cached); // Clone the Arc
 
         let task = thread_pool.spawn(async move {
-            let permit = semaphore.acquire().await.unwrap();
             let mut command_queue = CommandQueue::default();
 
             if let Some(map_str) = arc_tilemap_cached.read().get(&full_key.to_string()) {
@@ -61,28 +112,100 @@ pub async fn load_tilemap_from_s3(commands: &mut Commands, scene_uuid: &str, s3_
                     .entity_mut(task_entity)
                     .insert(LoadTilemapTask {
                         map_name: key.to_string(),
-                        map_str: cloned_map_str
+                        map_str: cloned_map_str,
+                        error: None
                     })
                     .remove::<ComputeTask>();
                 });
-                drop(permit);
                 return command_queue;
             }
 
-            let bytes = get_bytes(builder);
-            let map_str =  match String::from_utf8(bytes.clone()) {
-                Ok(text) => text,
-                Err(_) => String::new()
+            let result = match fetch_with_cache(&storage, &disk_cache, &semaphore, &bucket, &full_key).await {
+                Ok(bytes) if bytes.is_empty() => Err(DownloadError::InvalidData("empty map file".to_string())),
+                Ok(bytes) => String::from_utf8(bytes).map_err(|e| DownloadError::InvalidData(e.to_string())),
+                Err(e) => Err(e)
             };
 
             command_queue.push(move |world: &mut World| {
-                arc_tilemap_cached.write().insert(full_key.to_string(), map_str.to_string());
+                let (map_str, error) = match result {
+                    Ok(map_str) => {
+                        arc_tilemap_cached.write().insert(full_key.to_string(), map_str.to_string());
+                        (map_str, None)
+                    },
+                    Err(e) => {
+                        world.send_event(DownloadFailedEvent {
+                            key: full_key.to_string(),
+                            error: e.clone()
+                        });
+                        (String::new(), Some(e))
+                    }
+                };
 
                 world
                 .entity_mut(task_entity)
                 .insert(LoadTilemapTask {
                     map_name: key.to_string(),
-                    map_str: map_str
+                    map_str,
+                    error
+                })
+                .remove::<ComputeTask>();
+            });
+
+            command_queue
+        });
+        commands.entity(task_entity).insert(ComputeTask((scene_uuid.to_string(), task)));
+    } else {
+        let full_key = format!("map/{}.txt", key);
+        commands.spawn(LoadTilemapTask {
+            map_name: key.to_string(),
+            map_str: String::new(),
+            error: Some(DownloadError::NoStorage)
+        });
+        commands.add(move |world: &mut World| {
+            world.send_event(DownloadFailedEvent {
+                key: full_key,
+                error: DownloadError::NoStorage
+            });
+        });
+    }
+}
+
+#[tokio::main]
+pub async fn upload_tilemap_to_s3(commands: &mut Commands, scene_uuid: &str, s3_data: &mut ResMut<S3Data>, key: String, map_str: String){
+    if let Some(storage) = &s3_data.storage {
+        let bucket = s3_data.bucket.to_string();
+        let full_key = format!("map/{}.txt", key);
+        let storage = Arc::clone(storage);
+        let disk_cache = s3_data.disk_cache.clone();
+        let semaphore = s3_data.s3_semaphore.clone();
+        let thread_pool = AsyncComputeTaskPool::get();
+
+        let task_entity = commands.spawn_empty().id();
+
+        let arc_tilemap_cached = Arc::clone(&s3_data.tilemap_cached);
+
+        let task = thread_pool.spawn(async move {
+            let permit = semaphore.acquire().await.unwrap();
+            let mut command_queue = CommandQueue::default();
+
+            let is_success = storage.put_object(&bucket, &full_key, map_str.as_bytes().to_vec());
+            if is_success {
+                if let Some(disk_cache) = &disk_cache {
+                    disk_cache.lock().remove(&get_cache_key(&storage.name(), &bucket, &full_key));
+                }
+            }
+
+            command_queue.push(move |world: &mut World| {
+                // rooms opened later in this session should see the published version
+                if is_success {
+                    arc_tilemap_cached.write().insert(full_key.to_string(), map_str);
+                }
+
+                world
+                .entity_mut(task_entity)
+                .insert(UploadTilemapTask {
+                    map_name: key.to_string(),
+                    is_success
                 })
                 .remove::<ComputeTask>();
             });
@@ -95,9 +218,51 @@ pub async fn load_tilemap_from_s3(commands: &mut Commands, scene_uuid: &str, s3_
 }
 
 #[tokio::main]
-pub async fn load_sprite_from_s3(commands: &mut Commands, scene_uuid: &str, s3_data: &mut ResMut<S3Data>, bucket: String, key: String, target: Entity){
-    if let Some(s3_client) = &s3_data.s3_client {
-        let builder = s3_client.get_object().bucket(bucket.to_string()).key(key.to_string());
+pub async fn list_tilemaps_from_s3(commands: &mut Commands, scene_uuid: &str, s3_data: &mut ResMut<S3Data>){
+    if let Some(storage) = &s3_data.storage {
+        let bucket = s3_data.bucket.to_string();
+        let storage = Arc::clone(storage);
+        let semaphore = s3_data.s3_semaphore.clone();
+        let thread_pool = AsyncComputeTaskPool::get();
+
+        let task_entity = commands.spawn_empty().id();
+
+        let task = thread_pool.spawn(async move {
+            let permit = semaphore.acquire().await.unwrap();
+            let mut command_queue = CommandQueue::default();
+
+            let map_names = storage.list_keys(&bucket, "map/").map(|keys| {
+                let mut map_names: Vec<String> = keys.iter()
+                .filter_map(|key| key.strip_prefix("map/").and_then(|name| name.strip_suffix(".txt")))
+                .filter(|name| !name.is_empty())
+                .map(|name| name.to_string())
+                .collect();
+                map_names.sort();
+                map_names
+            });
+
+            command_queue.push(move |world: &mut World| {
+                world
+                .entity_mut(task_entity)
+                .insert(ListTilemapTask {
+                    map_names
+                })
+                .remove::<ComputeTask>();
+            });
+
+            drop(permit);
+            command_queue
+        });
+        commands.entity(task_entity).insert(ComputeTask((scene_uuid.to_string(), task)));
+    }
+}
+
+#[tokio::main]
+pub async fn load_sprite_from_s3(commands: &mut Commands, scene_uuid: &str, s3_data: &mut ResMut<S3Data>, key: String, target: Entity){
+    if let Some(storage) = &s3_data.storage {
+        let bucket = s3_data.bucket.to_string();
+        let storage = Arc::clone(storage);
+        let disk_cache = s3_data.disk_cache.clone();
         let semaphore = s3_data.s3_semaphore.clone();
         let thread_pool = AsyncComputeTaskPool::get();
         
@@ -106,7 +271,6 @@ pub async fn load_sprite_from_s3(commands: &mut Commands, scene_uuid: &str, s3_d
         let arc_image_cached = Arc::clone(&s3_data.image_cached); // Clone the Arc
 
         let task = thread_pool.spawn(async move {
-            let permit = semaphore.acquire().await.unwrap();
             let mut command_queue = CommandQueue::default();
 
             if let Some(image_handle) = arc_image_cached.read().get(&key) {
@@ -120,28 +284,37 @@ pub async fn load_sprite_from_s3(commands: &mut Commands, scene_uuid: &str, s3_d
                     })
                     .remove::<ComputeTask>();
                 });
-                drop(permit);
                 return command_queue
             }
-            let mut bytes = get_bytes(builder);
+            let result = fetch_with_cache(&storage, &disk_cache, &semaphore, &bucket, &key).await;
             command_queue.push(move |world: &mut World| {
-                if let Some(image) = convert_bytes_to_image( &mut bytes) {
-                    let image_handle = world.add_asset::<Image>(image);
-                    arc_image_cached.write().insert(key.to_string(), image_handle.clone());
-                    world
-                    .entity_mut(task_entity)
-                    .insert(LoadSpriteTask {
-                        image_handle: image_handle.clone(),
-                        entity: target
-                    })
-                    .remove::<ComputeTask>();
-                } else {
-                    world
-                    .entity_mut(task_entity)
-                    .despawn();
+                let image = match result {
+                    Ok(mut bytes) => convert_bytes_to_image(&mut bytes).ok_or(DownloadError::InvalidData("not an image".to_string())),
+                    Err(e) => Err(e)
+                };
+                match image {
+                    Ok(image) => {
+                        let image_handle = world.add_asset::<Image>(image);
+                        arc_image_cached.write().insert(key.to_string(), image_handle.clone());
+                        world
+                        .entity_mut(task_entity)
+                        .insert(LoadSpriteTask {
+                            image_handle: image_handle.clone(),
+                            entity: target
+                        })
+                        .remove::<ComputeTask>();
+                    },
+                    Err(e) => {
+                        world.send_event(DownloadFailedEvent {
+                            key: key.to_string(),
+                            error: e
+                        });
+                        world
+                        .entity_mut(task_entity)
+                        .despawn();
+                    }
                 }
             });
-            drop(permit);
             return command_queue
         });
         commands.entity(task_entity).insert(ComputeTask((scene_uuid.to_string(), task)));
@@ -159,4 +332,45 @@ pub fn execute_tasks(mut commands: Commands, scene: Res<Scene>, mut tasks: Query
             commands.append(&mut commands_queue);
         }
     }
-}
\ No newline at end of file
+}
+
+const DOWNLOAD_FAILURE_DISPLAY_SECONDS: f32 = 6.0;
+
+pub fn show_download_failures(mut contexts: EguiContexts, mut download_failed_events: EventReader<DownloadFailedEvent>, time: Res<Time>, mut messages: Local<Vec<(String, f32)>>) {
+    for event in download_failed_events.read() {
+        println!("download {} failed: {}", event.key, event.error);
+        let message = match (&event.error, event.key.strip_prefix("map/").and_then(|name| name.strip_suffix(".txt"))) {
+            (DownloadError::NotFound, Some(map_name)) => format!("Map \"{}\" not found", map_name),
+            (_, Some(map_name)) => format!("Map \"{}\" could not be loaded: {}", map_name, event.error),
+            (_, None) => format!("Could not download {}: {}", event.key, event.error)
+        };
+        if !messages.iter().any(|(m, _)| m.eq(&message)) {
+            messages.push((message, DOWNLOAD_FAILURE_DISPLAY_SECONDS));
+        }
+    }
+
+    if messages.is_empty() {
+        return;
+    }
+    for (_, remaining) in messages.iter_mut() {
+        *remaining -= time.delta_seconds();
+    }
+    messages.retain(|(_, remaining)| *remaining > 0.0);
+
+    let mut is_dismissed = false;
+    egui::Window::new("Download Failures")
+    .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0 ,5.0))
+    .resizable(false)
+    .title_bar(false)
+    .show(contexts.ctx_mut(), |ui| {
+        for (message, _) in messages.iter() {
+            ui.colored_label(egui::Color32::LIGHT_RED, message);
+        }
+        if ui.button("Dismiss").clicked() {
+            is_dismissed = true;
+        }
+    });
+    if is_dismissed {
+        messages.clear();
+    }
+}
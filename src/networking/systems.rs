@@ -1,15 +1,54 @@
-use bevy::prelude::*;
+use std::{path::PathBuf, sync::Arc};
+
+use bevy::{asset::io::file::FileAssetReader, prelude::*};
 
 use aws_config::load_from_env;
 use aws_sdk_s3 as s3;
 
-use super::s3::resources::S3Data;
+use parking_lot::Mutex;
+use tokio::sync::Semaphore;
+
+use crate::config::resources::AppConfig;
+
+use super::rtc::{resources::RTCResource, transport::get_room_transport};
+use super::s3::{backend::{BundledBackend, LocalBackend, S3Backend}, cache::{DiskCache, DISK_CACHE_MAX_BYTES}, resources::S3Data};
 
+// storage = "local" serves maps and sprites from storage_dir instead of the bucket
 #[tokio::main]
-pub async fn setup_aws_clients(mut s3_data: ResMut<S3Data>){
-    let myconfig = load_from_env().await;
+pub async fn setup_storage_backend(mut s3_data: ResMut<S3Data>, config: Res<AppConfig>){
+    s3_data.bucket = config.bucket.to_string();
+    // max_downloads sets how many objects are fetched at the same time
+    s3_data.s3_semaphore = Arc::new(Semaphore::new(config.max_downloads.max(1)));
+
+    if config.offline {
+        s3_data.storage = Some(Arc::new(BundledBackend {
+            assets: LocalBackend {
+                root: FileAssetReader::get_base_path().join("assets")
+            }
+        }));
+    } else if config.storage.eq_ignore_ascii_case("local") {
+        s3_data.storage = Some(Arc::new(LocalBackend {
+            root: PathBuf::from(config.storage_dir.to_string())
+        }));
+    } else {
+        let myconfig = load_from_env().await;
+
+        let s3_client = s3::Client::new(&myconfig);
+        s3_data.storage = Some(Arc::new(S3Backend {
+            s3_client
+        }));
+
+        // only remote objects are worth keeping on disk between launches
+        if let Some(cache_dir) = dirs::cache_dir() {
+            s3_data.disk_cache = Some(Arc::new(Mutex::new(DiskCache::open(cache_dir.join("shalloville"), DISK_CACHE_MAX_BYTES))));
+        }
+    }
 
-    let s3_client = s3::Client::new(&myconfig);
-    s3_data.s3_client = Some(s3_client);
+    if let Some(storage) = &s3_data.storage {
+        println!("storage backend: {}", storage.name());
+    }
+}
 
-}
\ No newline at end of file
+pub fn setup_room_transport(mut rtc_resource: ResMut<RTCResource>, config: Res<AppConfig>){
+    rtc_resource.set_transport(get_room_transport(&config));
+}
@@ -16,7 +16,7 @@ use nokhwa::utils::{ApiBackend, CameraInfo, RequestedFormat, RequestedFormatType
 use nokhwa::CallbackCamera;
 use nokhwa::{nokhwa_initialize, query};
 
-use crate::networking::s3::components::ComputeTask;
+use crate::{config::resources::AppConfig, networking::s3::components::ComputeTask};
 
 #[derive(Resource)]
 pub struct StreamingResources {
@@ -174,7 +174,9 @@ impl Default for StreamingResources {
 #[derive(Resource)]
 pub struct NokhwaCamera {
     pub has_opened: bool,
-    pub camera_setting: NokhwaCameraSetting,
+    pub camera_setting: Option<NokhwaCameraSetting>,
+    // why the camera can't be used, shown to the user instead of opening it
+    pub unavailable_reason: Option<String>,
 
     pub image_receiver: Option<flume::Receiver<RgbaImage>>,
     pub stop_camera_sender: Option<flume::Sender<bool>>,
@@ -186,12 +188,31 @@ pub struct NokhwaCameraSetting {
     pub first_camera: CameraInfo
 }
 
-impl Default for NokhwaCamera {
-    fn default() -> NokhwaCamera {
+impl FromWorld for NokhwaCamera {
+    fn from_world(world: &mut World) -> Self {
+        let is_offline = match world.get_resource::<AppConfig>() {
+            Some(config) => config.offline,
+            None => false
+        };
+
+        let mut camera_setting = None;
+        let mut unavailable_reason = None;
+        if is_offline {
+            unavailable_reason = Some("Camera is disabled in offline mode".to_string());
+        } else {
+            match NokhwaCamera::get_camera_settings() {
+                Ok(setting) => camera_setting = Some(setting),
+                Err(e) => {
+                    println!("camera unavailable: {}", e);
+                    unavailable_reason = Some(e);
+                }
+            }
+        }
 
         NokhwaCamera {
             has_opened: false,
-            camera_setting: NokhwaCamera::get_camera_settings(),
+            camera_setting,
+            unavailable_reason,
             image_receiver: None,
 
             stop_camera_sender: None,
@@ -200,20 +221,24 @@ impl Default for NokhwaCamera {
 }
 
 impl NokhwaCamera {
-    pub fn get_camera_settings() -> NokhwaCameraSetting{
-        let cameras = query(ApiBackend::Auto).unwrap();
+    pub fn get_camera_settings() -> Result<NokhwaCameraSetting, String> {
+        let cameras = query(ApiBackend::Auto).map_err(|e| format!("Camera unavailable: {}", e))?;
         let format = RequestedFormat::new::<RgbFormat>(RequestedFormatType::AbsoluteHighestFrameRate);
-        let first_camera = cameras.first().expect("camera not exist");
+        let first_camera = cameras.first().ok_or("No camera found".to_string())?;
 
         let settings = NokhwaCameraSetting {
             format: format,
             first_camera: first_camera.clone()
         };
 
-        return settings;
+        return Ok(settings);
+    }
+
+    pub fn is_available(&self) -> bool {
+        return self.camera_setting.is_some();
     }
 
-    pub fn switch_camera(&mut self) {
+    pub fn switch_camera(&mut self) -> Result<(), String> {
         if self.has_opened {
             self.has_opened = false;
             if let Some(stop_camera_sender) = &self.stop_camera_sender {
@@ -224,9 +249,13 @@ impl NokhwaCamera {
             }
             self.image_receiver = None;
             self.stop_camera_sender = None;
-            return;
+            return Ok(());
         }
-        self.has_opened = true;
+
+        let camera_setting = match &self.camera_setting {
+            Some(camera_setting) => camera_setting,
+            None => return Err(self.unavailable_reason.clone().unwrap_or("Camera unavailable".to_string()))
+        };
 
         let (image_sender, image_receiver) = bounded::<RgbaImage>(2);
         let (stop_camera_sender, stop_camera_receiver) = bounded::<bool>(1);
@@ -235,27 +264,38 @@ impl NokhwaCamera {
 
         let callback = |_| {};
 
-        let mut threaded = CallbackCamera::new(self.camera_setting.first_camera.index().clone(), self.camera_setting.format, callback).unwrap();
+        let mut threaded = CallbackCamera::new(camera_setting.first_camera.index().clone(), camera_setting.format, callback).map_err(|e| format!("Could not open the camera: {}", e))?;
+        threaded.open_stream().map_err(|e| format!("Could not start the camera: {}", e))?;
+        self.has_opened = true;
 
         std::thread::spawn(move || {
-            threaded.open_stream().unwrap();
             #[allow(clippy::empty_loop)]
             loop {        
                 if let Ok(stop) = stop_camera_receiver.try_recv() {
                     if stop {
                         drop(stop_camera_receiver);
-                        threaded.stop_stream().unwrap();
+                        let _ = threaded.stop_stream();
                         break;
                     }
                 } else {
-                    let frame = threaded.poll_frame().unwrap();
-                    let new_image = frame.decode_image::<RgbAFormat>().unwrap();
-                    let _ = image_sender.send(new_image);
+                    // the camera can be unplugged while streaming
+                    let frame = match threaded.poll_frame() {
+                        Ok(frame) => frame,
+                        Err(e) => {
+                            println!("camera stopped: {}", e);
+                            let _ = threaded.stop_stream();
+                            break;
+                        }
+                    };
+                    if let Ok(new_image) = frame.decode_image::<RgbAFormat>() {
+                        let _ = image_sender.send(new_image);
+                    }
                 }
             }
         });
 
         self.image_receiver = Some(image_receiver);
         self.stop_camera_sender = Some(stop_camera_sender);
+        return Ok(());
     }
-}
\ No newline at end of file
+}
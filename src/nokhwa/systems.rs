@@ -1,15 +1,19 @@
 use bevy::prelude::*;
-use crate::{editor::resources::Scene, main_menu::resources::RoomData, map_structures::{components::{InteractiveEvent, InteractiveType}, resources::{MapData, MapStructures}}, networking::rtc::resources::RTCResource, utils::group_numbers};
+use crate::{editor::resources::Scene, game::resources::GameResources, main_menu::resources::RoomData, map_structures::{components::{InteractiveEvent, InteractiveType}, resources::{get_ui_depth, MapData, MapStructures}}, networking::rtc::resources::RTCResource, utils::group_numbers};
 
 use super::{NokhwaCamera, StreamingResources, StreamingState};
 
-pub fn detect_device_camera(mut commands: Commands, scene: Res<Scene>, mut image_assets: ResMut<Assets<Image>>, mut interactive_event: EventReader<InteractiveEvent>, mut nokhwa_camera: ResMut<NokhwaCamera>, mut streaming_resources: ResMut<StreamingResources>, map_data: Res<MapData>, map_structures: Res<MapStructures>, mut streaming_state: ResMut<NextState<StreamingState>>, mut rtc_resource: ResMut<RTCResource>, room_data: Res<RoomData>){
+pub fn detect_device_camera(mut commands: Commands, scene: Res<Scene>, mut image_assets: ResMut<Assets<Image>>, mut interactive_event: EventReader<InteractiveEvent>, mut nokhwa_camera: ResMut<NokhwaCamera>, mut streaming_resources: ResMut<StreamingResources>, map_data: Res<MapData>, map_structures: Res<MapStructures>, mut streaming_state: ResMut<NextState<StreamingState>>, mut rtc_resource: ResMut<RTCResource>, room_data: Res<RoomData>, mut game_resources: ResMut<GameResources>){
     for ev in interactive_event.read(){
         if ev.0.0.ne(&InteractiveType::SwitchCameraShare) {
             break;
         }
 
-        nokhwa_camera.switch_camera();
+        if let Err(e) = nokhwa_camera.switch_camera() {
+            println!("switch camera failed: {}", e);
+            game_resources.add_system_message(&format!("camera: {}", e));
+            continue;
+        }
         if nokhwa_camera.has_opened {
             streaming_state.set(StreamingState::Open);
             streaming_resources.add_screen(&format!("{} cam", room_data.this_user_uuid));
@@ -18,7 +22,7 @@ pub fn detect_device_camera(mut commands: Commands, scene: Res<Scene>, mut image
                     if title.eq(&"screens") {
                         if let Ok(groups) = group_numbers(content.to_string(), 3) {
                             for group in groups.iter() {
-                                streaming_resources.add_sprite(&mut commands, &scene.scene_uuid, &format!("{} cam", room_data.this_user_uuid), Vec3::new(group[0], group[1], map_data.max_y + 10.0), group[2]);
+                                streaming_resources.add_sprite(&mut commands, &scene.scene_uuid, &format!("{} cam", room_data.this_user_uuid), Vec3::new(group[0], group[1], get_ui_depth(map_data.max_y)), group[2]);
                             }
                         }
                     }
@@ -55,7 +59,7 @@ pub fn update_device_camera_image(mut image_assets: ResMut<Assets<Image>>, mut s
 
 pub fn close_device_camera(mut commands: Commands, mut nokhwa_camera: ResMut<NokhwaCamera>, mut streaming_resources: ResMut<StreamingResources>, mut rtc_resource: ResMut<RTCResource>, room_data: Res<RoomData>){
     if nokhwa_camera.has_opened {
-        nokhwa_camera.switch_camera();
+        let _ = nokhwa_camera.switch_camera();
 
         if rtc_resource.is_multiplayer() {
             rtc_resource.close_video_track(&room_data.this_user_uuid);
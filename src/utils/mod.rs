@@ -4,6 +4,12 @@ pub fn from_xy_to_grid(x: f32, y: f32, tile_width: f32, tile_height: f32) -> (f3
     (grid_x, grid_y)
 }
 
+pub fn is_within_tiles(x1: f32, y1: f32, x2: f32, y2: f32, tile_width: f32, tile_height: f32, tiles: f32) -> bool {
+    let dx = (x1 - x2) / tile_width;
+    let dy = (y1 - y2) / tile_height;
+    return dx * dx + dy * dy <= tiles * tiles;
+}
+
 pub fn from_grid_xy_to_index(grid_x: f32, grid_y: f32, map_col: f32) -> i32 {
     return (grid_y * map_col + grid_x) as i32;
 }
@@ -47,4 +53,120 @@ pub fn group_numbers(input: String, group_size: usize) -> Result<Vec<Vec<f32>>,
 
 pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
     a + (b - a) * t.clamp(0.0, 1.0)
-}
\ No newline at end of file
+}
+
+pub fn grid_line(start: (i32, i32), end: (i32, i32)) -> Vec<(i32, i32)> {
+    let mut cells: Vec<(i32, i32)> = Vec::new();
+    let (mut x, mut y) = start;
+    let dx = (end.0 - x).abs();
+    let dy = -(end.1 - y).abs();
+    let step_x = if x < end.0 { 1 } else { -1 };
+    let step_y = if y < end.1 { 1 } else { -1 };
+    let mut error = dx + dy;
+
+    loop {
+        cells.push((x, y));
+        if x == end.0 && y == end.1 {
+            break;
+        }
+        let doubled = error * 2;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+    return cells;
+}
+
+pub fn grid_rect(start: (i32, i32), end: (i32, i32)) -> Vec<(i32, i32)> {
+    let mut cells: Vec<(i32, i32)> = Vec::new();
+    for y in start.1.min(end.1)..=start.1.max(end.1) {
+        for x in start.0.min(end.0)..=start.0.max(end.0) {
+            cells.push((x, y));
+        }
+    }
+    return cells;
+}
+
+pub fn flood_fill(start: (i32, i32), map_col: i32, map_row: i32, is_target: impl Fn(i32) -> bool) -> Vec<i32> {
+    let mut filled: Vec<i32> = Vec::new();
+    let mut visited = vec![false; (map_col * map_row).max(0) as usize];
+    let mut stack: Vec<(i32, i32)> = Vec::from([start]);
+
+    while let Some((x, y)) = stack.pop() {
+        if x < 0 || y < 0 || x >= map_col || y >= map_row {
+            continue;
+        }
+        let index = from_grid_xy_to_index(x as f32, y as f32, map_col as f32);
+        if visited[index as usize] || !is_target(index) {
+            continue;
+        }
+        visited[index as usize] = true;
+        filled.push(index);
+        stack.extend([(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]);
+    }
+    return filled;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_visits_every_cell_between_the_ends() {
+        assert_eq!(grid_line((2, 3), (2, 3)), Vec::from([(2, 3)]));
+        assert_eq!(grid_line((0, 0), (3, 0)), Vec::from([(0, 0), (1, 0), (2, 0), (3, 0)]));
+        assert_eq!(grid_line((0, 2), (0, 0)), Vec::from([(0, 2), (0, 1), (0, 0)]));
+        assert_eq!(grid_line((3, 3), (0, 0)), Vec::from([(3, 3), (2, 2), (1, 1), (0, 0)]));
+
+        // a fast drag skips cells between mouse events, the line must not leave gaps
+        let cells = grid_line((0, 0), (5, 2));
+        assert_eq!(cells.first(), Some(&(0, 0)));
+        assert_eq!(cells.last(), Some(&(5, 2)));
+        for pair in cells.windows(2) {
+            assert!((pair[0].0 - pair[1].0).abs() <= 1 && (pair[0].1 - pair[1].1).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn rect_covers_both_corners_in_any_order() {
+        let cells = grid_rect((2, 1), (0, 0));
+        assert_eq!(cells, Vec::from([(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (2, 1)]));
+        assert_eq!(grid_rect((0, 0), (2, 1)), cells);
+        assert_eq!(grid_rect((4, 4), (4, 4)), Vec::from([(4, 4)]));
+    }
+
+    #[test]
+    fn flood_fill_stays_inside_the_region() {
+        // 4 x 3, the wall (1) splits the left column from the rest
+        let tiles = [
+            0, 1, 0, 0,
+            0, 1, 0, 0,
+            0, 1, 1, 0
+        ];
+        let mut filled = flood_fill((0, 0), 4, 3, |index| tiles[index as usize] == 0);
+        filled.sort();
+        assert_eq!(filled, Vec::from([0, 4, 8]));
+
+        let mut filled = flood_fill((3, 2), 4, 3, |index| tiles[index as usize] == 0);
+        filled.sort();
+        assert_eq!(filled, Vec::from([2, 3, 6, 7, 11]));
+    }
+
+    #[test]
+    fn flood_fill_stops_at_the_map_edge() {
+        // every cell matches, so only the bounds stop the fill
+        let mut filled = flood_fill((2, 1), 3, 2, |_| true);
+        filled.sort();
+        assert_eq!(filled, Vec::from([0, 1, 2, 3, 4, 5]));
+
+        assert!(flood_fill((-1, 0), 3, 2, |_| true).is_empty());
+        assert!(flood_fill((3, 0), 3, 2, |_| true).is_empty());
+        assert!(flood_fill((0, 0), 0, 0, |_| true).is_empty());
+        assert!(flood_fill((0, 0), 3, 2, |_| false).is_empty());
+    }
+}
@@ -1,13 +1,11 @@
-use std::collections::HashMap;
-
 use bevy::{prelude::*, window::PrimaryWindow};
 use bevy_egui::{egui, EguiContexts};
 
-use crate::{character::components::Character, editor::{resources::{Scene, StateName}, systems::create_scene, CreateSceneEvent}, game::resources::GameResources, main_menu::resources::RoomData, map_structures::resources::MapData, networking::{rtc::{components::MultiplayerUserAttribute, resources::RTCResource, systems::{create_room, join_room}}, s3::{resources::S3Data, systems::{load_sprite_from_s3, load_tilemap_from_s3}}}, nokhwa::StreamingState, FocusState};
+use crate::{character::components::Character, editor::resources::Scene, game::resources::GameResources, main_menu::resources::RoomData, map_structures::resources::MapData, networking::{rtc::{components::MultiplayerUserAttribute, resources::RTCResource, systems::{create_room, join_room}}, s3::{resources::S3Data, systems::{load_sprite_from_s3, load_tilemap_from_s3}}}, nokhwa::StreamingState, FocusState};
 
 use super::{OnWardrobeConfirmed, WardrobeResources, WardrobeState};
 
-pub fn wardrobe_editor(mut commands: Commands, mut contexts: EguiContexts, scene: Res<Scene>, mut focus_state: ResMut<NextState<FocusState>>, mut wardrobe_state: ResMut<NextState<WardrobeState>>, mut wardrobe_resources: ResMut<WardrobeResources>, mut s3_data: ResMut<S3Data>, window_query: Query<&Window, With<PrimaryWindow>>, mut room_data: ResMut<RoomData>, mut map_data: ResMut<MapData>, mut game_resource: ResMut<GameResources>, mut rtc_resource: ResMut<RTCResource>, mut create_scene_event: EventWriter<CreateSceneEvent>, mut characters_query: Query<&mut Character>){
+pub fn wardrobe_editor(mut commands: Commands, mut contexts: EguiContexts, scene: Res<Scene>, mut focus_state: ResMut<NextState<FocusState>>, mut wardrobe_state: ResMut<NextState<WardrobeState>>, mut wardrobe_resources: ResMut<WardrobeResources>, mut s3_data: ResMut<S3Data>, window_query: Query<&Window, With<PrimaryWindow>>, mut room_data: ResMut<RoomData>, mut map_data: ResMut<MapData>, mut game_resource: ResMut<GameResources>, mut rtc_resource: ResMut<RTCResource>, mut characters_query: Query<&mut Character>){
     let window = window_query.get_single().unwrap();
 
     let ctx: &mut egui::Context = contexts.ctx_mut();
@@ -289,6 +287,32 @@ pub fn wardrobe_editor(mut commands: Commands, mut contexts: EguiContexts, scene
             ui.separator();
             ui.add_space(10.0);
 
+            // only a new room can be named, it is stored in the room metadata
+            if wardrobe_resources.display_comfirm_button && matches!(wardrobe_resources.on_confirm_action, OnWardrobeConfirmed::CreateRoom) {
+                ui.label(egui::RichText::new("Room Name (Optional)").strong().color(egui::Color32::WHITE));
+                let room_name_response = ui.text_edit_singleline(&mut room_data.room_name);
+
+                if room_name_response.gained_focus() {
+                    focus_state.set(FocusState::UI);
+                }
+                if room_name_response.lost_focus() {
+                    focus_state.set(FocusState::Game);
+                }
+
+                ui.label(egui::RichText::new("Password (Optional)").strong().color(egui::Color32::WHITE));
+                let password_response = ui.add(egui::TextEdit::singleline(&mut room_data.room_password).password(true));
+
+                if password_response.gained_focus() {
+                    focus_state.set(FocusState::UI);
+                }
+                if password_response.lost_focus() {
+                    focus_state.set(FocusState::Game);
+                }
+                ui.checkbox(&mut room_data.is_invite_only, "Invite only");
+
+                ui.add_space(10.0);
+            }
+
             if wardrobe_resources.is_map_changable {
                 ui.label("Current Map");
                 ui.horizontal(|ui| {
@@ -305,7 +329,7 @@ pub fn wardrobe_editor(mut commands: Commands, mut contexts: EguiContexts, scene
                         }
                         if current_map_name.ne(&map_data.map_name) {
                             map_data.is_map_loaded = false;
-                            load_tilemap_from_s3(&mut commands, &scene.scene_uuid, &mut s3_data, "shalloville".to_string(), map_data.map_name.to_string());   
+                            load_tilemap_from_s3(&mut commands, &scene.scene_uuid, &mut s3_data, map_data.map_name.to_string());   
                         }
                     }
                     ui.add_enabled(false, egui::TextEdit::singleline(&mut map_data.map_name));
@@ -322,7 +346,7 @@ pub fn wardrobe_editor(mut commands: Commands, mut contexts: EguiContexts, scene
                         }
                         if current_map_name.ne(&map_data.map_name) {
                             map_data.is_map_loaded = false;
-                            load_tilemap_from_s3(&mut commands, &scene.scene_uuid, &mut s3_data, "shalloville".to_string(), map_data.map_name.to_string());   
+                            load_tilemap_from_s3(&mut commands, &scene.scene_uuid, &mut s3_data, map_data.map_name.to_string());   
                         }
                     }
                 });
@@ -339,18 +363,8 @@ pub fn wardrobe_editor(mut commands: Commands, mut contexts: EguiContexts, scene
                         OnWardrobeConfirmed::CreateRoom => {
                             room_data.set_loaded_to_ready();
 
-                            create_room(&mut room_data, &mut rtc_resource, &map_data);
-
-                            create_scene(&mut commands, &mut create_scene_event, &mut room_data, "game", HashMap::from([
-                                (StateName::TriggerButtonState as u8, 1),
-                                (StateName::MovementState as u8, 1),
-                                (StateName::CharacterExistState as u8, 1),
-                                (StateName::CameraState as u8, 2),
-                                (StateName::MultiplayerRoomState as u8, 1)
-                            ]), Some(Vec::from([
-                                StateName::WardrobeState as u8,
-                                StateName::StreamingState as u8
-                            ])));
+                            // the game opens once the room is there, see join_room_event_listener
+                            create_room(&mut commands, &scene.scene_uuid, &mut room_data, &mut rtc_resource, &map_data);
                         },
                         OnWardrobeConfirmed::JoinRoom => {
                             room_data.set_loaded_to_ready();
@@ -381,7 +395,7 @@ fn replace_wardrobe_sprite(commands: &mut Commands, scene_uuid: &str, s3_data: &
                             ..default()
                         }
                     ).id();
-                    load_sprite_from_s3(commands, scene_uuid, s3_data, "shalloville".to_string(), format!("character/{}/{}.png", part, index), sprite);
+                    load_sprite_from_s3(commands, scene_uuid, s3_data, format!("character/{}/{}.png", part, index), sprite);
                     character.entity_parts.insert(format!("{} sprite", part), (sprite, Vec3::new(0.0, 0.0, new_offset.z)));
                     commands.entity(_parent).add_child(sprite);
                 }